@@ -4,6 +4,7 @@ use std::{
   ffi::OsStr,
   fs,
   path::{Path, PathBuf},
+  process::Command,
   time::Duration,
 };
 
@@ -16,8 +17,7 @@ impl NetWithData<'_> {
   fn new(size: usize) -> Self {
     let data = Box::leak(run::Heap::init(size));
     let boxed = unsafe { Box::from_raw(data) };
-    let mut net = run::Net::new(data);
-    net.boot(name_to_val("main"));
+    let net = run::Net::new(data);
     NetWithData(net, boxed)
   }
 }
@@ -27,11 +27,11 @@ fn load_from_core<'a, P: AsRef<Path>>(file: P) -> (run::Book, NetWithData<'a>) {
   let code = fs::read_to_string(file).unwrap();
   let (size, code) = extract_size(&code);
 
-  let book = ast::do_parse_book(code);
-  let rbook = ast::book_to_runtime(&book);
+  let book = ast::do_parse_book(code).unwrap();
+  let rbook = ast::book_to_runtime(&book).unwrap();
 
   let mut net = NetWithData::new(size);
-  net.0.boot(name_to_val("main"));
+  net.0.boot(rbook.syms.id("main").unwrap());
   (rbook, net)
 }
 
@@ -42,10 +42,10 @@ fn load_from_lang<'a, P: AsRef<Path>>(file: P) -> (run::Book, NetWithData<'a>) {
 
   let mut book = hvml::term::parser::parse_definition_book(&code).unwrap();
   let book = hvml::compile_book(&mut book, hvml::OptimizationLevel::Heavy).unwrap().core_book;
-  let book = ast::book_to_runtime(&book);
+  let book = ast::book_to_runtime(&book).unwrap();
 
   let mut net = NetWithData::new(size);
-  net.0.boot(name_to_val("main"));
+  net.0.boot(book.syms.id("main").unwrap());
   (book, net)
 }
 
@@ -162,6 +162,77 @@ fn benchmark_group(path: &PathBuf, group: String, c: &mut Criterion) {
   });
 }
 
+// Compares the schedulers of 'parallel_normal' on the programs of 'benches/programs/parallel'
+fn scheduler_benchmark(c: &mut Criterion) {
+  if cfg!(feature = "cuda") {
+    return;
+  }
+
+  let root = PathBuf::from(format!("{}/benches/programs/parallel", env!("CARGO_MANIFEST_DIR")));
+  let mut group = c.benchmark_group("scheduler");
+  group.sample_size(10);
+
+  for entry in std::fs::read_dir(root).unwrap().flatten() {
+    let path = entry.path();
+    let file_name = path.file_stem().unwrap().to_string_lossy().to_string();
+    for scheduler in [run::Scheduler::Split, run::Scheduler::Steal] {
+      let config = run::RuntimeConfig { scheduler, ..Default::default() };
+      group.bench_function(format!("{}/{:?}", file_name, scheduler).to_lowercase(), |b| {
+        b.iter_batched(
+          || load_from_core(&path),
+          |(book, net)| black_box(black_box(net.0).parallel_normal(black_box(&book), black_box(&config))),
+          criterion::BatchSize::PerIteration,
+        );
+      });
+    }
+  }
+}
+
+// Compares executables compiled with and without '--tco', on a tail-recursive and a tree-recursive
+// program. Both include the process startup, since compiled definitions only run in their executable.
+fn tco_benchmark(c: &mut Criterion) {
+  if cfg!(feature = "cuda") {
+    return;
+  }
+
+  let root = env!("CARGO_MANIFEST_DIR");
+  let programs = [
+    (PathBuf::from(format!("{root}/examples/stress_tests/sum_tail.hvmc")), "256M"),
+    (PathBuf::from(format!("{root}/benches/programs/parallel/sum_rec.hvmc")), "64M"),
+  ];
+  let mut group = c.benchmark_group("tco");
+  group.sample_size(10);
+
+  for (path, heap) in programs {
+    let file_name = path.file_stem().unwrap().to_string_lossy().to_string();
+    for tco in [false, true] {
+      let exe = compile_executable(&path, tco);
+      group.bench_function(format!("{}/{}", file_name, if tco { "tco" } else { "no-tco" }), |b| {
+        b.iter(|| {
+          let output = Command::new(&exe).args(["run", &file_name, "-m", heap]).output().unwrap();
+          assert!(output.status.success(), "{}", String::from_utf8_lossy(&output.stderr));
+          black_box(output)
+        });
+      });
+    }
+  }
+}
+
+// Compiles a program with 'hvmc compile', in a temporary directory, returning its executable.
+fn compile_executable(path: &Path, tco: bool) -> PathBuf {
+  let file_name = path.file_name().unwrap();
+  let dir = std::env::temp_dir().join(format!("hvmc-bench-{}-{}", path.file_stem().unwrap().to_string_lossy(), tco));
+  fs::create_dir_all(&dir).unwrap();
+  fs::copy(path, dir.join(file_name)).unwrap();
+  let mut cmd = Command::new(env!("CARGO_BIN_EXE_hvmc"));
+  cmd.current_dir(&dir).arg("compile").arg(file_name);
+  if tco {
+    cmd.arg("--tco");
+  }
+  assert!(cmd.status().unwrap().success(), "failed to compile {}", path.to_string_lossy());
+  dir.join(path.file_stem().unwrap())
+}
+
 fn interact_benchmark(c: &mut Criterion) {
   if cfg!(feature = "cuda") {
     return;
@@ -183,8 +254,8 @@ fn interact_benchmark(c: &mut Criterion) {
       b.iter_batched(
         || {
           let mut net = NetWithData::new(1 << 4);
-          let book = run::Book::new();
-          ast::net_to_runtime(&mut net.0, &ast::Net { root: Era, rdex: vec![redex.clone()] });
+          let mut book = run::Book::new();
+          ast::net_to_runtime(&mut net.0, &mut book.syms, &ast::Net { root: Era, rdex: vec![redex.clone()] }).unwrap();
           let (rdx_a, rdx_b) = net.0.rdex[0];
           (book, net, rdx_a, rdx_b)
         },
@@ -203,5 +274,7 @@ criterion_group! {
   targets =
     // run_programs_dir,
     interact_benchmark,
+    scheduler_benchmark,
+    tco_benchmark,
 }
 criterion_main!(benches);
@@ -100,6 +100,39 @@ const Tag CT3 = 0xD; // main port of con node 3
 const Tag CT4 = 0xE; // main port of con node 4
 const Tag CT5 = 0xF; // main port of con node 5
 
+// Numeric operations (OP2/OP1 labels), mirroring src/run.rs
+const u32 ADD = 0x00; // addition
+const u32 SUB = 0x01; // subtraction
+const u32 MUL = 0x02; // multiplication
+const u32 DIV = 0x03; // division
+const u32 MOD = 0x04; // modulus
+const u32 EQ  = 0x05; // equal-to
+const u32 NE  = 0x06; // not-equal-to
+const u32 LT  = 0x07; // less-than
+const u32 GT  = 0x08; // greater-than
+const u32 LTE = 0x09; // less-than-or-equal
+const u32 GTE = 0x0A; // greater-than-or-equal
+const u32 AND = 0x0B; // logical-and
+const u32 OR  = 0x0C; // logical-or
+const u32 XOR = 0x0D; // logical-xor
+const u32 LSH = 0x0E; // left-shift
+const u32 RSH = 0x0F; // right-shift
+const u32 NOT = 0x10; // logical-not
+const u32 MIN = 0x11; // minimum
+const u32 MAX = 0x12; // maximum
+const u32 POW = 0x13; // exponentiation
+const u32 ROL = 0x14; // rotate-left
+const u32 ROR = 0x15; // rotate-right
+const u32 POP = 0x16; // population count (number of set bits)
+const u32 CLZ = 0x17; // count leading zeros
+const u32 CTZ = 0x18; // count trailing zeros
+
+// Numeric types, on the high bits of operation labels
+const u32 U60 = 0x000; // unsigned integers
+const u32 I60 = 0x100; // signed integers
+const u32 F60 = 0x200; // floats
+const u32 OPS = 0x0FF; // operation bits of a label
+
 // Special values
 const u32 ROOT = 0x0 | VR2;  // pointer to root port
 const u32 NONE = 0x00000000; // empty value, not allocated
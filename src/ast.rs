@@ -6,10 +6,11 @@
 // syntax reflects this representation. The grammar is specified on this repo's README.
 
 use crate::run;
+use crate::{f60, i60};
 use std::collections::BTreeMap;
 use std::collections::HashMap;
-use std::iter::Peekable;
-use std::str::Chars;
+use std::collections::HashSet;
+use std::sync::RwLock;
 
 // AST
 // ---
@@ -21,8 +22,8 @@ pub enum Tree {
   Tup { lft: Box<Tree>, rgt: Box<Tree> },
   Dup { lab: run::Lab, lft: Box<Tree>, rgt: Box<Tree> },
   Var { nam: String },
-  Ref { nam: run::Val },
-  Num { val: run::Val },
+  Ref { nam: run::Val }, // a definition name, as a value (see 'name_to_val')
+  Num { typ: run::Lab, val: run::Val }, // a number literal, like '#5', '#-5' or '#1.5', as its numeric type and runtime number
   Op1 { opr: run::Lab, lft: run::Val, rgt: Box<Tree> },
   Op2 { opr: run::Lab, lft: Box<Tree>, rgt: Box<Tree> },
   Mat { sel: Box<Tree>, ret: Box<Tree> },
@@ -41,12 +42,93 @@ pub type Book = BTreeMap<String, Net>;
 // Parser
 // ------
 
-fn skip(chars: &mut Peekable<Chars>) {
+// A position-aware cursor over the source code.
+pub struct Cursor<'a> {
+  pub code: &'a str, // source code
+  pub offset: usize, // byte offset of the next char
+  pub encoding: Encoding, // encoding of the string and list literals
+  pub fresh: usize, // internal variables of the literals desugared so far
+}
+
+// A parse error, pointing to the offending position of the source code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ParseError {
+  pub offset: usize, // byte offset
+  pub line: usize, // line, starting at 1
+  pub column: usize, // column, starting at 1
+  pub expected: String, // what the parser expected
+  pub found: Option<String>, // what it found instead (None if end of input)
+}
+
+impl<'a> Cursor<'a> {
+  pub fn new(code: &'a str) -> Self {
+    Cursor::with_encoding(code, Encoding::Scott)
+  }
+
+  pub fn with_encoding(code: &'a str, encoding: Encoding) -> Self {
+    Cursor { code, offset: 0, encoding, fresh: 0 }
+  }
+
+  #[inline(always)]
+  pub fn peek(&self) -> Option<char> {
+    self.code[self.offset..].chars().next()
+  }
+
+  // Builds an error at the current position, reporting the next char as found.
+  pub fn error(&self, expected: &str) -> ParseError {
+    ParseError::new(self.code, self.offset, expected, self.peek().map(|c| c.to_string()))
+  }
+}
+
+impl<'a> Iterator for Cursor<'a> {
+  type Item = char;
+
+  #[inline(always)]
+  fn next(&mut self) -> Option<char> {
+    let c = self.peek()?;
+    self.offset += c.len_utf8();
+    Some(c)
+  }
+}
+
+impl ParseError {
+  pub fn new(code: &str, offset: usize, expected: &str, found: Option<String>) -> Self {
+    let before = &code[..offset];
+    let line = before.matches('\n').count() + 1;
+    let column = before[before.rfind('\n').map_or(0, |i| i + 1)..].chars().count() + 1;
+    ParseError { offset, line, column, expected: expected.to_string(), found }
+  }
+
+  // Renders the error with the offending source line and a caret below it.
+  pub fn render(&self, code: &str) -> String {
+    let text = code.lines().nth(self.line - 1).unwrap_or("");
+    let gutter = " ".repeat(self.line.to_string().len());
+    let mut result = String::new();
+    result.push_str(&format!("error: {}\n", self));
+    result.push_str(&format!("{} |\n", gutter));
+    result.push_str(&format!("{} | {}\n", self.line, text));
+    result.push_str(&format!("{} | {}^", gutter, " ".repeat(self.column - 1)));
+    return result;
+  }
+}
+
+impl std::fmt::Display for ParseError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match &self.found {
+      Some(found) => write!(f, "expected {}, found '{}' at {}:{}", self.expected, found, self.line, self.column),
+      None        => write!(f, "expected {}, found end of input at {}:{}", self.expected, self.line, self.column),
+    }
+  }
+}
+
+impl std::error::Error for ParseError {}
+
+fn skip(chars: &mut Cursor) {
   while let Some(c) = chars.peek() {
-    if *c == '/' {
+    if c == '/' {
       chars.next();
       while let Some(c) = chars.peek() {
-        if *c == '\n' {
+        if c == '\n' {
           break;
         }
         chars.next();
@@ -59,21 +141,22 @@ fn skip(chars: &mut Peekable<Chars>) {
   }
 }
 
-pub fn consume(chars: &mut Peekable<Chars>, text: &str) -> Result<(), String> {
+pub fn consume(chars: &mut Cursor, text: &str) -> Result<(), ParseError> {
   skip(chars);
   for c in text.chars() {
-    if chars.next() != Some(c) {
-      return Err(format!("Expected '{}', found {:?}", text, chars.peek()));
+    if chars.peek() != Some(c) {
+      return Err(chars.error(&format!("'{}'", text)));
     }
+    chars.next();
   }
   return Ok(());
 }
 
-pub fn parse_decimal(chars: &mut Peekable<Chars>) -> Result<u64, String> {
+pub fn parse_decimal(chars: &mut Cursor) -> Result<u64, ParseError> {
   let mut num: u64 = 0;
   skip(chars);
   if !chars.peek().map_or(false, |c| c.is_digit(10)) {
-    return Err(format!("Expected a decimal number, found {:?}", chars.peek()));
+    return Err(chars.error("a decimal number"));
   }
   while let Some(c) = chars.peek() {
     if !c.is_digit(10) {
@@ -85,60 +168,123 @@ pub fn parse_decimal(chars: &mut Peekable<Chars>) -> Result<u64, String> {
   Ok(num)
 }
 
-pub fn parse_name(chars: &mut Peekable<Chars>) -> Result<String, String> {
+// Parses a numeric literal: an u60 ('5'), an i60 ('-5') or an f60 ('1.5', '-1.5'). Returns its
+// numeric type, as the type label of an operator ('run::U60', 'run::I60' or 'run::F60'), and its
+// runtime number.
+pub fn parse_number(chars: &mut Cursor) -> Result<(run::Lab, run::Val), ParseError> {
+  skip(chars);
+  let init = chars.offset;
+  let neg = chars.peek() == Some('-');
+  if neg {
+    chars.next();
+  }
+  let val = parse_decimal(chars)?;
+  if chars.peek() == Some('.') {
+    chars.next();
+    if !chars.peek().map_or(false, |c| c.is_digit(10)) {
+      return Err(chars.error("a decimal number"));
+    }
+    parse_decimal(chars)?;
+    let val = chars.code[init .. chars.offset].parse::<f64>().unwrap();
+    return Ok((run::F60, f60::new(val)));
+  }
+  if neg {
+    return Ok((run::I60, i60::new((val as i64).wrapping_neg())));
+  }
+  return Ok((run::U60, val));
+}
+
+pub fn parse_name(chars: &mut Cursor) -> Result<String, ParseError> {
   let mut txt = String::new();
   skip(chars);
-  if !chars.peek().map_or(false, |c| c.is_alphanumeric() || *c == '_' || *c == '.') {
-    return Err(format!("Expected a name character, found {:?}", chars.peek()))
+  if !chars.peek().map_or(false, |c| c.is_alphanumeric() || c == '_' || c == '.') {
+    return Err(chars.error("a name"));
   }
   while let Some(c) = chars.peek() {
-    if !c.is_alphanumeric() && *c != '_' && *c != '.' {
+    if !c.is_alphanumeric() && c != '_' && c != '.' {
       break;
     }
-    txt.push(*c);
+    txt.push(c);
     chars.next();
   }
   Ok(txt)
 }
 
-pub fn parse_opx_lit(chars: &mut Peekable<Chars>) -> Result<String, String> {
+// Operators are either symbols, like '<<', or lowercase words, like 'min'.
+pub fn parse_opx_lit(chars: &mut Cursor) -> Result<String, ParseError> {
   let mut opx = String::new();
   skip(chars);
+  let word = chars.peek().map_or(false, |c| c.is_ascii_lowercase());
+  let is_opx = |c: char| if word { c.is_ascii_lowercase() } else { "+-=*/%<>|&^!?".contains(c) };
   while let Some(c) = chars.peek() {
-    if !"+-=*/%<>|&^!?".contains(*c) {
+    if !is_opx(c) {
       break;
     }
-    opx.push(*c);
+    opx.push(c);
     chars.next();
   }
   Ok(opx)
 }
 
-fn parse_opr(chars: &mut Peekable<Chars>) -> Result<run::Lab, String> {
+// Parses an operator, with an optional numeric type suffix (':u60', ':i60' or ':f60').
+pub fn parse_opr(chars: &mut Cursor) -> Result<run::Lab, ParseError> {
+  skip(chars);
+  let init = chars.offset;
+  let opr = parse_opr_untyped(chars)?;
+  if chars.peek() != Some(':') {
+    return Ok(opr);
+  }
+  chars.next();
+  let init = chars.offset;
+  let mut typ = String::new();
+  while let Some(c) = chars.peek().filter(|c| c.is_alphanumeric()) {
+    typ.push(c);
+    chars.next();
+  }
+  match typ.as_str() {
+    "u60" => Ok(run::U60 | opr),
+    "i60" => Ok(run::I60 | opr),
+    "f60" => Ok(run::F60 | opr),
+    ""    => Err(chars.error("a numeric type")),
+    _     => Err(ParseError::new(chars.code, init, "a numeric type", Some(typ))),
+  }
+}
+
+fn parse_opr_untyped(chars: &mut Cursor) -> Result<run::Lab, ParseError> {
+  let init = chars.offset;
   let opx = parse_opx_lit(chars)?;
   match opx.as_str() {
-    "+"  => Ok(run::ADD),
-    "-"  => Ok(run::SUB),
-    "*"  => Ok(run::MUL),
-    "/"  => Ok(run::DIV),
-    "%"  => Ok(run::MOD),
-    "==" => Ok(run::EQ),
-    "!=" => Ok(run::NE),
-    "<"  => Ok(run::LT),
-    ">"  => Ok(run::GT),
-    "<=" => Ok(run::LTE),
-    ">=" => Ok(run::GTE),
-    "&&" => Ok(run::AND),
-    "||" => Ok(run::OR),
-    "^"  => Ok(run::XOR),
-    "!"  => Ok(run::NOT),
-    "<<" => Ok(run::LSH),
-    ">>" => Ok(run::RSH),
-    _ => Err(format!("Unknown operator: {}", opx)),
-  }
-}
-
-pub fn parse_tree(chars: &mut Peekable<Chars>) -> Result<Tree, String> {
+    "+"   => Ok(run::ADD),
+    "-"   => Ok(run::SUB),
+    "*"   => Ok(run::MUL),
+    "/"   => Ok(run::DIV),
+    "%"   => Ok(run::MOD),
+    "=="  => Ok(run::EQ),
+    "!="  => Ok(run::NE),
+    "<"   => Ok(run::LT),
+    ">"   => Ok(run::GT),
+    "<="  => Ok(run::LTE),
+    ">="  => Ok(run::GTE),
+    "&&"  => Ok(run::AND),
+    "||"  => Ok(run::OR),
+    "^"   => Ok(run::XOR),
+    "!"   => Ok(run::NOT),
+    "<<"  => Ok(run::LSH),
+    ">>"  => Ok(run::RSH),
+    "min" => Ok(run::MIN),
+    "max" => Ok(run::MAX),
+    "**"  => Ok(run::POW),
+    "rol" => Ok(run::ROL),
+    "ror" => Ok(run::ROR),
+    "pop" => Ok(run::POP),
+    "clz" => Ok(run::CLZ),
+    "ctz" => Ok(run::CTZ),
+    ""    => Err(chars.error("an operator")),
+    _     => Err(ParseError::new(chars.code, init, "an operator", Some(opx))),
+  }
+}
+
+pub fn parse_tree(chars: &mut Cursor) -> Result<Tree, ParseError> {
   skip(chars);
   match chars.peek() {
     Some('*') => {
@@ -154,11 +300,28 @@ pub fn parse_tree(chars: &mut Peekable<Chars>) -> Result<Tree, String> {
     }
     Some('[') => {
       chars.next();
-      let lab = 1;
-      let lft = Box::new(parse_tree(chars)?);
+      skip(chars);
+      // A list is '[]', '[a]' or '[a, b, ...]'; a tuple is '[a b]'.
+      if chars.peek() == Some(']') {
+        chars.next();
+        return Ok(desugar(vec![], chars.encoding, &mut chars.fresh));
+      }
+      let lft = parse_tree(chars)?;
+      skip(chars);
+      if chars.peek() == Some(',') || chars.peek() == Some(']') {
+        let mut els = vec![lft];
+        while chars.next() == Some(',') {
+          els.push(parse_tree(chars)?);
+          skip(chars);
+          if chars.peek() != Some(',') && chars.peek() != Some(']') {
+            return Err(chars.error("',' or ']'"));
+          }
+        }
+        return Ok(desugar(els, chars.encoding, &mut chars.fresh));
+      }
       let rgt = Box::new(parse_tree(chars)?);
       consume(chars, "]")?;
-      Ok(Tree::Tup { lft, rgt })
+      Ok(Tree::Tup { lft: Box::new(lft), rgt })
     }
     Some('{') => {
       chars.next();
@@ -176,12 +339,13 @@ pub fn parse_tree(chars: &mut Peekable<Chars>) -> Result<Tree, String> {
     }
     Some('#') => {
       chars.next();
-      Ok(Tree::Num { val: parse_decimal(chars)? })
+      let (typ, val) = parse_number(chars)?;
+      Ok(Tree::Num { typ, val })
     }
     Some('<') => {
       chars.next();
-      if chars.peek().map_or(false, |c| c.is_digit(10)) {
-        let lft = parse_decimal(chars)?;
+      if starts_number(chars) {
+        let (_, lft) = parse_number(chars)?;
         let opr = parse_opr(chars)?;
         let rgt = Box::new(parse_tree(chars)?);
         consume(chars, ">")?;
@@ -202,17 +366,81 @@ pub fn parse_tree(chars: &mut Peekable<Chars>) -> Result<Tree, String> {
       consume(chars, ">")?;
       Ok(Tree::Mat { sel, ret })
     }
+    Some('"') => {
+      let txt = parse_string(chars)?;
+      Ok(desugar(string_to_list(&txt), chars.encoding, &mut chars.fresh))
+    }
     _ => {
+      if !chars.peek().map_or(false, |c| c.is_alphanumeric() || c == '_' || c == '.') {
+        return Err(chars.error("a tree"));
+      }
       Ok(Tree::Var { nam: parse_name(chars)? })
     },
   }
 }
 
-pub fn parse_net(chars: &mut Peekable<Chars>) -> Result<Net, String> {
+// Parses a string literal, like '"hi"'. The escapes are '\n', '\t', '\r', '\0', '\\', '\"' and
+// '\u{hex}'. Slashes inside a string don't start comments.
+pub fn parse_string(chars: &mut Cursor) -> Result<String, ParseError> {
+  consume(chars, "\"")?;
+  let mut txt = String::new();
+  loop {
+    match chars.next() {
+      Some('"')  => return Ok(txt),
+      Some('\\') => {
+        let init = chars.offset;
+        let chr = match chars.next() {
+          Some('n')  => Some('\n'),
+          Some('t')  => Some('\t'),
+          Some('r')  => Some('\r'),
+          Some('0')  => Some('\0'),
+          Some('\\') => Some('\\'),
+          Some('"')  => Some('"'),
+          Some('u')  => parse_unicode(chars),
+          _          => None,
+        };
+        match chr {
+          Some(chr) => txt.push(chr),
+          None      => return Err(ParseError::new(chars.code, init, "an escape sequence", (chars.offset > init).then(|| chars.code[init .. chars.offset].to_string()))),
+        }
+      }
+      Some(chr)  => txt.push(chr),
+      None       => return Err(chars.error("'\"'")),
+    }
+  }
+}
+
+// Parses the '{hex}' of a '\u{hex}' escape.
+fn parse_unicode(chars: &mut Cursor) -> Option<char> {
+  if chars.next() != Some('{') {
+    return None;
+  }
+  let mut hex = String::new();
+  while let Some(c) = chars.peek().filter(|c| c.is_ascii_hexdigit()) {
+    hex.push(c);
+    chars.next();
+  }
+  if chars.next() != Some('}') {
+    return None;
+  }
+  u32::from_str_radix(&hex, 16).ok().and_then(char::from_u32)
+}
+
+// Whether the next chars are a numeric literal, like '5' or '-5', rather than an operator.
+pub fn starts_number(chars: &Cursor) -> bool {
+  let mut rest = chars.code[chars.offset ..].chars();
+  match rest.next() {
+    Some('-') => rest.next().map_or(false, |c| c.is_digit(10)),
+    Some(c)   => c.is_digit(10),
+    None      => false,
+  }
+}
+
+pub fn parse_net(chars: &mut Cursor) -> Result<Net, ParseError> {
   let mut rdex = Vec::new();
   let root = parse_tree(chars)?;
   while let Some(c) = { skip(chars); chars.peek() } {
-    if *c == '&' {
+    if c == '&' {
       chars.next();
       let tree1 = parse_tree(chars)?;
       consume(chars, "~")?;
@@ -225,10 +453,10 @@ pub fn parse_net(chars: &mut Peekable<Chars>) -> Result<Net, String> {
   Ok(Net { root, rdex })
 }
 
-pub fn parse_book(chars: &mut Peekable<Chars>) -> Result<Book, String> {
+pub fn parse_book(chars: &mut Cursor) -> Result<Book, ParseError> {
   let mut book = BTreeMap::new();
   while let Some(c) = { skip(chars); chars.peek() } {
-    if *c == '@' {
+    if c == '@' {
       chars.next();
       let name = parse_name(chars)?;
       consume(chars, "=")?;
@@ -241,40 +469,47 @@ pub fn parse_book(chars: &mut Peekable<Chars>) -> Result<Book, String> {
   Ok(book)
 }
 
-fn do_parse<T>(code: &str, parse_fn: impl Fn(&mut Peekable<Chars>) -> Result<T, String>) -> T {
-  let chars = &mut code.chars().peekable();
-  match parse_fn(chars) {
-    Ok(result) => {
-      if chars.next().is_none() {
-        result
-      } else {
-        eprintln!("Unable to parse the whole input. Is this not an hvmc file?");
-        std::process::exit(1);
-      }
-    }
-    Err(err) => {
-      eprintln!("{}", err);
-      std::process::exit(1);
-    }
+fn do_parse<T>(code: &str, encoding: Encoding, parse_fn: impl Fn(&mut Cursor) -> Result<T, ParseError>) -> Result<T, ParseError> {
+  let chars = &mut Cursor::with_encoding(code, encoding);
+  let result = parse_fn(chars)?;
+  skip(chars);
+  if chars.peek().is_some() {
+    return Err(chars.error("end of input"));
   }
+  Ok(result)
+}
+
+pub fn do_parse_tree(code: &str) -> Result<Tree, ParseError> {
+  do_parse(code, Encoding::Scott, parse_tree)
 }
 
-pub fn do_parse_tree(code: &str) -> Tree {
-  do_parse(code, parse_tree)
+pub fn do_parse_net(code: &str) -> Result<Net, ParseError> {
+  do_parse(code, Encoding::Scott, parse_net)
 }
 
-pub fn do_parse_net(code: &str) -> Net {
-  do_parse(code, parse_net)
+pub fn do_parse_book(code: &str) -> Result<Book, ParseError> {
+  do_parse(code, Encoding::Scott, parse_book)
 }
 
-pub fn do_parse_book(code: &str) -> Book {
-  do_parse(code, parse_book)
+// Like 'do_parse_book', with the literals in the given encoding, instead of Scott lists.
+pub fn do_parse_book_with_encoding(code: &str, encoding: Encoding) -> Result<Book, ParseError> {
+  do_parse(code, encoding, parse_book)
 }
 
 // Stringifier
 // -----------
 
-fn show_opr(opr: run::Lab) -> String {
+pub fn show_opr(opr: run::Lab) -> String {
+  let typ = match opr & !run::OPS {
+    run::U60 => "",
+    run::I60 => ":i60",
+    run::F60 => ":f60",
+    _        => return format!("?{:x}", opr), // unknown, see 'Problem::BadOperator'
+  };
+  return format!("{}{}", show_opr_untyped(opr & run::OPS), typ);
+}
+
+fn show_opr_untyped(opr: run::Lab) -> String {
   match opr {
     run::ADD => "+".to_string(),
     run::SUB => "-".to_string(),
@@ -293,45 +528,105 @@ fn show_opr(opr: run::Lab) -> String {
     run::NOT => "!".to_string(),
     run::LSH => "<<".to_string(),
     run::RSH => ">>".to_string(),
-    _        => panic!("Unknown operator label."),
+    run::MIN => "min".to_string(),
+    run::MAX => "max".to_string(),
+    run::POW => "**".to_string(),
+    run::ROL => "rol".to_string(),
+    run::ROR => "ror".to_string(),
+    run::POP => "pop".to_string(),
+    run::CLZ => "clz".to_string(),
+    run::CTZ => "ctz".to_string(),
+    _        => format!("?{:x}", opr),
   }
 }
 
 pub fn show_tree(tree: &Tree) -> String {
+  show_tree_go(tree, None, &mut HashMap::new())
+}
+
+// Shows a tree with its encoded lists as literals (see 'Literals'). Empty lists are shown as they
+// are. Since the variables inside lists aren't shown, the rest are renamed in order.
+pub fn show_tree_sugared(tree: &Tree, encoding: Encoding) -> String {
+  show_tree_go(tree, Some(encoding), &mut HashMap::new())
+}
+
+fn show_tree_go(tree: &Tree, sugar: Option<Encoding>, vars: &mut HashMap<String, String>) -> String {
+  if let Some(encoding) = sugar {
+    if let Some(els) = read_list(tree, encoding).filter(|els| !els.is_empty()) {
+      let chrs = els.iter().map(|el| match el {
+        Tree::Num { typ: run::U60, val } => u32::try_from(*val).ok().and_then(char::from_u32).filter(|chr| !chr.is_control() || "\n\t\r".contains(*chr)),
+        _                                => None,
+      });
+      if let Some(txt) = chrs.collect::<Option<String>>() {
+        return show_string(&txt);
+      }
+      return format!("[{}]", els.into_iter().map(|el| show_tree_go(el, sugar, vars)).collect::<Vec<_>>().join(", "));
+    }
+  }
   match tree {
     Tree::Era => {
       "*".to_string()
     }
     Tree::Con { lft, rgt } => {
-      format!("({} {})", show_tree(&*lft), show_tree(&*rgt))
+      format!("({} {})", show_tree_go(&*lft, sugar, vars), show_tree_go(&*rgt, sugar, vars))
     }
     Tree::Tup { lft, rgt } => {
-      format!("[{} {}]", show_tree(&*lft), show_tree(&*rgt))
+      format!("[{} {}]", show_tree_go(&*lft, sugar, vars), show_tree_go(&*rgt, sugar, vars))
     }
     Tree::Dup { lab, lft, rgt } => {
-      format!("{{{} {} {}}}", lab, show_tree(&*lft), show_tree(&*rgt))
+      format!("{{{} {} {}}}", lab, show_tree_go(&*lft, sugar, vars), show_tree_go(&*rgt, sugar, vars))
     }
     Tree::Var { nam } => {
-      nam.clone()
+      if sugar.is_none() {
+        return nam.clone();
+      }
+      let len = vars.len();
+      vars.entry(nam.clone()).or_insert_with(|| num_to_str(len)).clone()
     }
     Tree::Ref { nam } => {
       format!("@{}", val_to_name(*nam))
     }
-    Tree::Num { val } => {
-      format!("#{}", (*val).to_string())
+    Tree::Num { typ, val } => {
+      format!("#{}", show_lit(*typ, *val))
     }
     Tree::Op1 { opr, lft, rgt } => {
-      format!("<{}{} {}>", lft, show_opr(*opr), show_tree(rgt))
+      format!("<{}{} {}>", show_lit(*opr, *lft), show_opr(*opr), show_tree_go(rgt, sugar, vars))
     }
     Tree::Op2 { opr, lft, rgt } => {
-      format!("<{} {} {}>", show_opr(*opr), show_tree(&*lft), show_tree(&*rgt))
+      format!("<{} {} {}>", show_opr(*opr), show_tree_go(&*lft, sugar, vars), show_tree_go(&*rgt, sugar, vars))
     }
     Tree::Mat { sel, ret } => {
-      format!("?<{} {}>", show_tree(&*sel), show_tree(&*ret))
+      format!("?<{} {}>", show_tree_go(&*sel, sugar, vars), show_tree_go(&*ret, sugar, vars))
     }
   }
 }
 
+pub fn show_string(txt: &str) -> String {
+  let mut result = String::from("\"");
+  for chr in txt.chars() {
+    match chr {
+      '\n'  => result.push_str("\\n"),
+      '\t'  => result.push_str("\\t"),
+      '\r'  => result.push_str("\\r"),
+      '\\'  => result.push_str("\\\\"),
+      '"'   => result.push_str("\\\""),
+      chr if chr.is_control() => result.push_str(&format!("\\u{{{:x}}}", chr as u32)),
+      chr   => result.push(chr),
+    }
+  }
+  result.push('"');
+  return result;
+}
+
+// Shows the literal of an OP1, or a number, in a numeric type, or in the numeric type of an operator.
+pub fn show_lit(opr: run::Lab, val: run::Val) -> String {
+  match opr & !run::OPS {
+    run::I60 => i60::show(val),
+    run::F60 => f60::show(val),
+    _        => val.to_string(),
+  }
+}
+
 pub fn show_net(net: &Net) -> String {
   let mut result = String::new();
   result.push_str(&format!("{}", show_tree(&net.root)));
@@ -341,6 +636,16 @@ pub fn show_net(net: &Net) -> String {
   return result;
 }
 
+// Shows a net with its encoded lists as literals, like 'show_tree_sugared'.
+pub fn show_net_sugared(net: &Net, encoding: Encoding) -> String {
+  let vars = &mut HashMap::new();
+  let mut result = show_tree_go(&net.root, Some(encoding), vars);
+  for (a, b) in &net.rdex {
+    result.push_str(&format!("\n& {} ~ {}", show_tree_go(a, Some(encoding), vars), show_tree_go(b, Some(encoding), vars)));
+  }
+  return result;
+}
+
 pub fn show_book(book: &Book) -> String {
   let mut result = String::new();
   for (name, net) in book {
@@ -349,12 +654,16 @@ pub fn show_book(book: &Book) -> String {
   return result;
 }
 
-pub fn show_runtime_tree(rt_net: &run::Net, ptr: run::Ptr) -> String {
-  show_tree(&tree_from_runtime_go(rt_net, ptr, PARENT_ROOT, &mut HashMap::new(), &mut 0))
+pub fn show_runtime_tree(rt_net: &run::Net, syms: &Symbols, ptr: run::Ptr) -> String {
+  show_tree(&tree_from_runtime(rt_net, syms, ptr))
+}
+
+pub fn show_runtime_net(rt_net: &run::Net, syms: &Symbols) -> String {
+  show_net(&net_from_runtime(rt_net, syms))
 }
 
-pub fn show_runtime_net(rt_net: &run::Net) -> String {
-  show_net(&net_from_runtime(rt_net))
+pub fn show_runtime_net_sugared(rt_net: &run::Net, syms: &Symbols, encoding: Encoding) -> String {
+  show_net_sugared(&net_from_runtime(rt_net, syms), encoding)
 }
 
 pub fn show_runtime_book(book: &run::Book) -> String {
@@ -392,61 +701,429 @@ pub fn port_to_tag(port: run::Port) -> run::Tag {
   }
 }
 
-pub fn name_to_letters(name: &str) -> Vec<u8> {
-  let mut letters = Vec::new();
-  for c in name.chars() {
-    letters.push(match c {
-      '0'..='9' => c as u8 - '0' as u8 + 0,
-      'A'..='Z' => c as u8 - 'A' as u8 + 10,
-      'a'..='z' => c as u8 - 'a' as u8 + 36,
-      '_'       => 62,
-      '.'       => 63,
-      _         => panic!("Invalid character in name"),
-    });
+// Definition names are interned into dense ids, so that names of any length fit in a REF pointer.
+// Each runtime book has its own table, which shows its ids back as names (readback, the JIT).
+// Ids can't outgrow a REF pointer, since a table can't hold 2^60 names in memory.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct Symbols {
+  names: Vec<String>, // name of each id
+  ids: BTreeMap<String, run::Val>, // id of each name
+  vals: Vec<run::Val>, // AST value of each id's name (see 'name_to_val'), which readback uses
+}
+
+// A symbol table that can't be restored.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum SymbolError {
+  Collision { name: String, got: run::Val, want: run::Val }, // a name is listed twice
+}
+
+impl std::fmt::Display for SymbolError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      SymbolError::Collision { name, got, want } => write!(f, "symbol collision: '{}' is listed as both id {} and {}", name, got, want),
+    }
+  }
+}
+
+impl std::error::Error for SymbolError {}
+
+impl Symbols {
+  pub fn new() -> Self {
+    Symbols::default()
+  }
+
+  // Restores a table from its names, in id order (see 'names'). This is how compiled books, which
+  // refer to definitions by id, restore their names.
+  pub fn load(names: &[&str]) -> Result<Self, SymbolError> {
+    let mut syms = Symbols::new();
+    for (id, name) in names.iter().enumerate() {
+      let got = syms.intern(name);
+      if got != id as run::Val {
+        return Err(SymbolError::Collision { name: name.to_string(), got, want: id as run::Val });
+      }
+    }
+    return Ok(syms);
+  }
+
+  // Gives a name the next id, unless it already has one.
+  pub fn intern(&mut self, name: &str) -> run::Val {
+    if let Some(id) = self.ids.get(name) {
+      return *id;
+    }
+    return self.insert(name, name_to_val(name));
+  }
+
+  // Gives a new name the next id, along with its AST value.
+  fn insert(&mut self, name: &str, val: run::Val) -> run::Val {
+    let id = self.names.len() as run::Val;
+    self.names.push(name.to_string());
+    self.ids.insert(name.to_string(), id);
+    self.vals.push(val);
+    return id;
+  }
+
+  pub fn id(&self, name: &str) -> Option<run::Val> {
+    self.ids.get(name).copied()
+  }
+
+  pub fn name(&self, id: run::Val) -> Option<&str> {
+    self.names.get(id as usize).map(|name| name.as_str())
+  }
+
+  // Shows an id as its name, or as '?<id>' if it has none, so that readback never fails.
+  pub fn show(&self, id: run::Val) -> String {
+    match self.name(id) {
+      Some(name) => name.to_string(),
+      None       => format!("?{}", id),
+    }
+  }
+
+  // All names, in id order.
+  pub fn names(&self) -> &[String] {
+    &self.names
+  }
+
+  // The AST value of an id's name, or of '?<id>' if it has none.
+  pub fn val(&self, id: run::Val) -> run::Val {
+    match self.vals.get(id as usize) {
+      Some(val) => *val,
+      None      => name_to_val(&self.show(id)),
+    }
+  }
+}
+
+// Definition names are also given values, which is how the AST holds references. Front-ends
+// built against the AST, like hvm-lang, identify definitions by these values, so a name of up to
+// 10 chars of [0-9A-Za-z_.], not starting with '0', keeps its base-64 packing, below 2^60. Other
+// names, which didn't fit, take the next value above it, from a table shared by the whole process,
+// so that no two names share a value.
+//
+// Since AST values carry no book to resolve them with, that table is never freed: it leaks each
+// distinct long name for as long as the process runs, growing with the names it parses, not with
+// how often it does. Runtime books record the values of their own names when they intern them, so
+// that readback resolves names through the book, and never writes to the table.
+const PACKED_NAMES: run::Val = 1 << 60;
+
+static OTHER_NAMES: RwLock<Symbols> = RwLock::new(Symbols { names: Vec::new(), ids: BTreeMap::new(), vals: Vec::new() });
+
+fn name_to_letter(c: char) -> Option<run::Val> {
+  match c {
+    '0'..='9' => Some(c as run::Val - '0' as run::Val + 0),
+    'A'..='Z' => Some(c as run::Val - 'A' as run::Val + 10),
+    'a'..='z' => Some(c as run::Val - 'a' as run::Val + 36),
+    '_'       => Some(62),
+    '.'       => Some(63),
+    _         => None,
+  }
+}
+
+fn letter_to_name(letter: run::Val) -> char {
+  match letter {
+     0..= 9 => (letter as u8 - 0 + b'0') as char,
+    10..=35 => (letter as u8 - 10 + b'A') as char,
+    36..=61 => (letter as u8 - 36 + b'a') as char,
+    62      => '_',
+    _       => '.',
+  }
+}
+
+// Packs a name base-64, if it fits and no other name has the same packing.
+fn pack_name(name: &str) -> Option<run::Val> {
+  if name.len() > 10 || name.starts_with('0') {
+    return None;
+  }
+  name.chars().try_fold(0, |val, c| Some(val * 64 + name_to_letter(c)?))
+}
+
+pub fn name_to_val(name: &str) -> run::Val {
+  if let Some(val) = pack_name(name) {
+    return val;
+  }
+  if let Some(id) = OTHER_NAMES.read().unwrap().id(name) {
+    return PACKED_NAMES + id;
+  }
+  // Checks again, since another thread may have added it before the write lock was taken.
+  let mut others = OTHER_NAMES.write().unwrap();
+  if let Some(id) = others.id(name) {
+    return PACKED_NAMES + id;
   }
-  return letters;
+  let val = PACKED_NAMES + others.names.len() as run::Val;
+  return PACKED_NAMES + others.insert(name, val);
 }
 
-pub fn letters_to_name(letters: Vec<u8>) -> String {
+// Shows a value as its name, or as '?<val>' if no name has it.
+pub fn val_to_name(val: run::Val) -> String {
+  if val >= PACKED_NAMES {
+    return match OTHER_NAMES.read().unwrap().name(val - PACKED_NAMES) {
+      Some(name) => name.to_string(),
+      None       => format!("?{}", val),
+    };
+  }
   let mut name = String::new();
-  for letter in letters {
-    name.push(match letter {
-       0..= 9 => (letter - 0 + '0' as u8) as char,
-      10..=35 => (letter - 10 + 'A' as u8) as char,
-      36..=61 => (letter - 36 + 'a' as u8) as char,
-      62      => '_',
-      63      => '.',
-      _       => panic!("Invalid letter in name"),
-    });
+  let mut val = val;
+  while val > 0 {
+    name.insert(0, letter_to_name(val % 64));
+    val /= 64;
   }
   return name;
 }
 
-pub fn val_to_letters(num: run::Val) -> Vec<u8> {
-  let mut letters = Vec::new();
-  let mut num = num;
-  while num > 0 {
-    letters.push((num % 64) as u8);
-    num /= 64;
+// Literals
+// --------
+
+// String and list literals are sugar for encoded lists, with strings being lists of char codes.
+// They're desugared as they're parsed, in the encoding of the cursor, and only shown back as
+// literals by 'show_tree_sugared', given the same encoding:
+// - Scott: a list takes its cases, as '(cons (nil r))', and passes its head and tail to cons.
+//   This is how the strings of '@IO' calls are encoded.
+//   - cons: ((head (tail r)) (* r))
+//   - nil : (* (r r))
+// - Church: a list is its own right fold. It takes '(cons (nil r))' too, but applies cons to
+//   each element and the fold of the rest, copying cons with DUPs labelled 'LIST_LAB'.
+//   - [a, b]: ({L (a (x r)) (b (n x))} (n r))
+//   - []    : (* (r r))
+// The empty list is the same in both, and also the Church numeral zero, so it's never shown as
+// '[]'. Lists of printable char codes are shown as strings.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Encoding {
+  Scott,
+  Church,
+}
+
+// Label of the DUPs that copy the cons of Church-encoded lists.
+pub const LIST_LAB: run::Lab = 0xFFFFFFF;
+
+// An internal variable of a desugared literal, numbered by 'fresh'. Their names start with '$',
+// which can't occur on parsed names, so they never clash.
+fn literal_var(fresh: &mut usize) -> Tree {
+  *fresh += 1;
+  Tree::Var { nam: format!("${}", *fresh - 1) }
+}
+
+fn con(lft: Tree, rgt: Tree) -> Tree {
+  Tree::Con { lft: Box::new(lft), rgt: Box::new(rgt) }
+}
+
+// The elements of a string literal: its char codes.
+pub fn string_to_list(txt: &str) -> Vec<Tree> {
+  txt.chars().map(|chr| Tree::Num { typ: run::U60, val: chr as run::Val }).collect()
+}
+
+// Expands the elements of a literal into the list they stand for.
+pub fn desugar(els: Vec<Tree>, encoding: Encoding, fresh: &mut usize) -> Tree {
+  let r = literal_var(fresh);
+  let nil = con(Tree::Era, con(r.clone(), r));
+  match encoding {
+    Encoding::Scott => {
+      els.into_iter().rev().fold(nil, |tail, head| {
+        let r = literal_var(fresh);
+        con(con(head, con(tail, r.clone())), con(Tree::Era, r))
+      })
+    }
+    Encoding::Church => {
+      if els.is_empty() {
+        return nil;
+      }
+      let (n, r) = (literal_var(fresh), literal_var(fresh));
+      let mut outs = vec![r.clone()];
+      outs.extend(els.iter().skip(1).map(|_| literal_var(fresh)));
+      outs.push(n.clone());
+      let mut apps = els.into_iter().enumerate().map(|(i, el)| con(el, con(outs[i + 1].clone(), outs[i].clone()))).collect::<Vec<_>>();
+      let last = apps.pop().unwrap();
+      let cons = apps.into_iter().rev().fold(last, |rest, app| Tree::Dup { lab: LIST_LAB, lft: Box::new(app), rgt: Box::new(rest) });
+      con(cons, con(n, r))
+    }
+  }
+}
+
+// Reads the elements of a tree that is exactly an encoded list.
+fn read_list(tree: &Tree, encoding: Encoding) -> Option<Vec<&Tree>> {
+  fn var(tree: &Tree) -> Option<&str> {
+    if let Tree::Var { nam } = tree { Some(nam) } else { None }
+  }
+  fn pair(tree: &Tree) -> Option<(&Tree, &Tree)> {
+    if let Tree::Con { lft, rgt } = tree { Some((lft, rgt)) } else { None }
+  }
+  // nil: (* (r r))
+  fn nil(tree: &Tree) -> bool {
+    let Some((Tree::Era, rgt)) = pair(tree) else { return false };
+    let Some((a, b)) = pair(rgt) else { return false };
+    var(a).is_some() && var(a) == var(b)
+  }
+  let mut els = vec![];
+  match encoding {
+    Encoding::Scott => {
+      // cons: ((head (tail r)) (* r))
+      let mut tree = tree;
+      while !nil(tree) {
+        let (lft, rgt) = pair(tree)?;
+        let (head, lft) = pair(lft)?;
+        let (tail, r) = pair(lft)?;
+        let (Tree::Era, s) = pair(rgt)? else { return None };
+        if var(r).is_none() || var(r) != var(s) {
+          return None;
+        }
+        els.push(head);
+        tree = tail;
+      }
+    }
+    Encoding::Church => {
+      // ({L (a (x r)) (b (n x))} (n r))
+      if nil(tree) {
+        return Some(els);
+      }
+      let (mut cons, rgt) = pair(tree)?;
+      let (n, r) = pair(rgt)?;
+      let mut out = var(r)?;
+      loop {
+        let (app, rest) = match cons {
+          Tree::Dup { lab: LIST_LAB, lft, rgt } => (&**lft, Some(&**rgt)),
+          app                                   => (app, None),
+        };
+        let (el, rgt) = pair(app)?;
+        let (acc, r) = pair(rgt)?;
+        if var(r)? != out {
+          return None;
+        }
+        els.push(el);
+        out = var(acc)?;
+        match rest {
+          Some(rest) => cons = rest,
+          None       => break,
+        }
+      }
+      if var(n)? != out {
+        return None;
+      }
+    }
   }
-  letters.reverse();
-  return letters;
+  return Some(els);
+}
+
+// Validation
+// ----------
+
+// A problem that makes a definition unsafe to run.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Problem {
+  MissingMain, // the book has no '@main'
+  UnpairedVar { nam: String, count: usize }, // a variable doesn't occur exactly twice
+  RedexVar { nam: String }, // a variable is a side of an active pair
+  UnknownRef { nam: String }, // a reference has no definition
+  BigLiteral { val: run::Val }, // an OP1 literal doesn't fit in 60 bits
+  BadOperator { opr: run::Lab }, // an operator label has an unknown type or operation
+  OutOfMemory(run::OutOfMemory), // the net doesn't fit in the heap it's injected into
+}
+
+// A problem, located at the definition it was found in.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ValidationError {
+  pub def: String, // definition name
+  pub problem: Problem, // what's wrong with it
 }
 
-pub fn letters_to_val(letters: Vec<u8>) -> run::Val {
-  let mut num = 0;
-  for letter in letters {
-    num = num * 64 + letter as run::Val;
+impl std::fmt::Display for Problem {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      Problem::MissingMain                => write!(f, "definition not found"),
+      Problem::UnpairedVar { nam, count } => write!(f, "variable '{}' occurs {} time(s), expected 2", nam, count),
+      Problem::RedexVar { nam }           => write!(f, "variable '{}' is a side of an active pair", nam),
+      Problem::UnknownRef { nam }         => write!(f, "reference to undefined '@{}'", nam),
+      Problem::BigLiteral { val }         => write!(f, "OP1 literal #{} doesn't fit in 60 bits", val),
+      Problem::BadOperator { opr }        => write!(f, "operator label 0x{:x} has an unknown type or operation", opr),
+      Problem::OutOfMemory(err)           => err.fmt(f),
+    }
   }
-  return num;
 }
 
-pub fn name_to_val(name: &str) -> run::Val {
-  letters_to_val(name_to_letters(name))
+impl From<run::OutOfMemory> for Problem {
+  fn from(err: run::OutOfMemory) -> Self {
+    Problem::OutOfMemory(err)
+  }
+}
+
+impl std::fmt::Display for ValidationError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(f, "@{}: {}", self.def, self.problem)
+  }
+}
+
+impl std::error::Error for ValidationError {}
+
+// Checks that every net is linear and closed, that no variable is a side of an active pair, that
+// every reference resolves (to a definition, or to an IO call), and that OP1 literals and operator
+// labels are well-formed. Reports all problems found.
+pub fn validate_book(book: &Book) -> Result<(), Vec<ValidationError>> {
+  let mut errs = Vec::new();
+  if !book.contains_key("main") {
+    errs.push(ValidationError { def: "main".to_string(), problem: Problem::MissingMain });
+  }
+  for (name, net) in book {
+    let mut vars = BTreeMap::new();
+    let mut problems = Vec::new();
+    validate_tree(&net.root, book, &mut vars, &mut problems);
+    for (a, b) in &net.rdex {
+      for tree in [a, b] {
+        if let Tree::Var { nam } = tree {
+          push_problem(&mut problems, Problem::RedexVar { nam: nam.clone() });
+        }
+        validate_tree(tree, book, &mut vars, &mut problems);
+      }
+    }
+    for (nam, count) in vars {
+      if count != 2 {
+        problems.push(Problem::UnpairedVar { nam, count });
+      }
+    }
+    errs.extend(problems.into_iter().map(|problem| ValidationError { def: name.clone(), problem }));
+  }
+  return if errs.is_empty() { Ok(()) } else { Err(errs) };
 }
 
-pub fn val_to_name(num: run::Val) -> String {
-  letters_to_name(val_to_letters(num))
+fn validate_tree(tree: &Tree, defs: &Book, vars: &mut BTreeMap<String, usize>, problems: &mut Vec<Problem>) {
+  match tree {
+    Tree::Era | Tree::Num { .. } => {}
+    Tree::Var { nam } => {
+      *vars.entry(nam.clone()).or_insert(0) += 1;
+    }
+    Tree::Ref { nam } => {
+      let nam = val_to_name(*nam);
+      if !defs.contains_key(&nam) && !crate::io::CALLS.contains(&nam.as_str()) {
+        push_problem(problems, Problem::UnknownRef { nam });
+      }
+    }
+    Tree::Op1 { opr, lft, rgt } => {
+      if !run::is_op(*opr) {
+        push_problem(problems, Problem::BadOperator { opr: *opr });
+      }
+      if *lft >> 60 != 0 {
+        push_problem(problems, Problem::BigLiteral { val: *lft });
+      }
+      validate_tree(rgt, defs, vars, problems);
+    }
+    Tree::Op2 { opr, lft, rgt } => {
+      if !run::is_op(*opr) {
+        push_problem(problems, Problem::BadOperator { opr: *opr });
+      }
+      validate_tree(lft, defs, vars, problems);
+      validate_tree(rgt, defs, vars, problems);
+    }
+    Tree::Con { lft, rgt } | Tree::Tup { lft, rgt } | Tree::Dup { lft, rgt, .. } => {
+      validate_tree(lft, defs, vars, problems);
+      validate_tree(rgt, defs, vars, problems);
+    }
+    Tree::Mat { sel, ret } => {
+      validate_tree(sel, defs, vars, problems);
+      validate_tree(ret, defs, vars, problems);
+    }
+  }
+}
+
+// Reports each problem once per definition.
+fn push_problem(problems: &mut Vec<Problem>, problem: Problem) {
+  if !problems.contains(&problem) {
+    problems.push(problem);
+  }
 }
 
 // Injection and Readback
@@ -461,38 +1138,38 @@ pub enum Parent {
 }
 const PARENT_ROOT: Parent = Parent::Node { loc: run::ROOT.loc(), port: tag_to_port(run::ROOT.tag()) };
 
-pub fn tree_to_runtime_go(rt_net: &mut run::Net, tree: &Tree, vars: &mut HashMap<String, Parent>, parent: Parent) -> run::Ptr {
-  match tree {
+pub fn tree_to_runtime_go(rt_net: &mut run::Net, syms: &mut Symbols, tree: &Tree, vars: &mut HashMap<String, Parent>, parent: Parent) -> Result<run::Ptr, Problem> {
+  let ptr = match tree {
     Tree::Era => {
       run::ERAS
     }
     Tree::Con { lft, rgt } => {
-      let loc = rt_net.alloc();
-      let p1 = tree_to_runtime_go(rt_net, &*lft, vars, Parent::Node { loc, port: run::P1 });
+      let loc = rt_net.alloc()?;
+      let p1 = tree_to_runtime_go(rt_net, syms, &*lft, vars, Parent::Node { loc, port: run::P1 })?;
       rt_net.heap.set(loc, run::P1, p1);
-      let p2 = tree_to_runtime_go(rt_net, &*rgt, vars, Parent::Node { loc, port: run::P2 });
+      let p2 = tree_to_runtime_go(rt_net, syms, &*rgt, vars, Parent::Node { loc, port: run::P2 })?;
       rt_net.heap.set(loc, run::P2, p2);
       run::Ptr::new(run::LAM, 0, loc)
     }
     Tree::Tup { lft, rgt } => {
-      let loc = rt_net.alloc();
-      let p1 = tree_to_runtime_go(rt_net, &*lft, vars, Parent::Node { loc, port: run::P1 });
+      let loc = rt_net.alloc()?;
+      let p1 = tree_to_runtime_go(rt_net, syms, &*lft, vars, Parent::Node { loc, port: run::P1 })?;
       rt_net.heap.set(loc, run::P1, p1);
-      let p2 = tree_to_runtime_go(rt_net, &*rgt, vars, Parent::Node { loc, port: run::P2 });
+      let p2 = tree_to_runtime_go(rt_net, syms, &*rgt, vars, Parent::Node { loc, port: run::P2 })?;
       rt_net.heap.set(loc, run::P2, p2);
       run::Ptr::new(run::TUP, 0, loc)
     }
     Tree::Dup { lab, lft, rgt } => {
-      let loc = rt_net.alloc();
-      let p1 = tree_to_runtime_go(rt_net, &*lft, vars, Parent::Node { loc, port: run::P1 });
+      let loc = rt_net.alloc()?;
+      let p1 = tree_to_runtime_go(rt_net, syms, &*lft, vars, Parent::Node { loc, port: run::P1 })?;
       rt_net.heap.set(loc, run::P1, p1);
-      let p2 = tree_to_runtime_go(rt_net, &*rgt, vars, Parent::Node { loc, port: run::P2 });
+      let p2 = tree_to_runtime_go(rt_net, syms, &*rgt, vars, Parent::Node { loc, port: run::P2 })?;
       rt_net.heap.set(loc, run::P2, p2);
       run::Ptr::new(run::DUP, *lab, loc)
     }
     Tree::Var { nam } => {
       if let Parent::Redex = parent {
-        panic!("By definition, can't have variable on active pairs.");
+        return Err(Problem::RedexVar { nam: nam.clone() });
       };
       match vars.get(nam) {
         Some(Parent::Redex) => {
@@ -503,7 +1180,7 @@ pub fn tree_to_runtime_go(rt_net: &mut run::Net, tree: &Tree, vars: &mut HashMap
             Parent::Redex => { unreachable!(); }
             Parent::Node { loc, port } => rt_net.heap.set(*other_loc, *other_port, run::Ptr::new(port_to_tag(port), 0, loc)),
           }
-          return run::Ptr::new(port_to_tag(*other_port), 0, *other_loc);
+          run::Ptr::new(port_to_tag(*other_port), 0, *other_loc)
         }
         None => {
           vars.insert(nam.clone(), parent);
@@ -512,63 +1189,72 @@ pub fn tree_to_runtime_go(rt_net: &mut run::Net, tree: &Tree, vars: &mut HashMap
       }
     }
     Tree::Ref { nam } => {
-      run::Ptr::big(run::REF, *nam)
+      run::Ptr::big(run::REF, syms.intern(&val_to_name(*nam)))
     }
-    Tree::Num { val } => {
-      run::Ptr::big(run::NUM, *val)
+    Tree::Num { typ, val } => {
+      run::Ptr::num(*typ, *val)
     }
     Tree::Op1 { opr, lft, rgt } => {
-      let loc = rt_net.alloc();
-      let p1 = run::Ptr::big(run::NUM, *lft);
+      let loc = rt_net.alloc()?;
+      let p1 = run::Ptr::num(*opr & !run::OPS, *lft);
       rt_net.heap.set(loc, run::P1, p1);
-      let p2 = tree_to_runtime_go(rt_net, rgt, vars, Parent::Node { loc, port: run::P2 });
+      let p2 = tree_to_runtime_go(rt_net, syms, rgt, vars, Parent::Node { loc, port: run::P2 })?;
       rt_net.heap.set(loc, run::P2, p2);
       run::Ptr::new(run::OP1, *opr, loc)
     }
     Tree::Op2 { opr, lft, rgt } => {
-      let loc = rt_net.alloc();
-      let p1 = tree_to_runtime_go(rt_net, &*lft, vars, Parent::Node { loc, port: run::P1 });
+      let loc = rt_net.alloc()?;
+      let p1 = tree_to_runtime_go(rt_net, syms, &*lft, vars, Parent::Node { loc, port: run::P1 })?;
       rt_net.heap.set(loc, run::P1, p1);
-      let p2 = tree_to_runtime_go(rt_net, &*rgt, vars, Parent::Node { loc, port: run::P2 });
+      let p2 = tree_to_runtime_go(rt_net, syms, &*rgt, vars, Parent::Node { loc, port: run::P2 })?;
       rt_net.heap.set(loc, run::P2, p2);
       run::Ptr::new(run::OP2, *opr, loc)
     }
     Tree::Mat { sel, ret } => {
-      let loc = rt_net.alloc();
-      let p1 = tree_to_runtime_go(rt_net, &*sel, vars, Parent::Node { loc, port: run::P1 });
+      let loc = rt_net.alloc()?;
+      let p1 = tree_to_runtime_go(rt_net, syms, &*sel, vars, Parent::Node { loc, port: run::P1 })?;
       rt_net.heap.set(loc, run::P1, p1);
-      let p2 = tree_to_runtime_go(rt_net, &*ret, vars, Parent::Node { loc, port: run::P2 });
+      let p2 = tree_to_runtime_go(rt_net, syms, &*ret, vars, Parent::Node { loc, port: run::P2 })?;
       rt_net.heap.set(loc, run::P2, p2);
       run::Ptr::new(run::MAT, 0, loc)
     }
-  }
+  };
+  return Ok(ptr);
 }
 
-pub fn tree_to_runtime(rt_net: &mut run::Net, tree: &Tree) -> run::Ptr {
-  tree_to_runtime_go(rt_net, tree, &mut HashMap::new(), PARENT_ROOT)
+pub fn tree_to_runtime(rt_net: &mut run::Net, syms: &mut Symbols, tree: &Tree) -> Result<run::Ptr, Problem> {
+  tree_to_runtime_go(rt_net, syms, tree, &mut HashMap::new(), PARENT_ROOT)
 }
 
-pub fn net_to_runtime(rt_net: &mut run::Net, net: &Net) {
+pub fn net_to_runtime(rt_net: &mut run::Net, syms: &mut Symbols, net: &Net) -> Result<(), Problem> {
   let mut vars = HashMap::new();
-  let root = tree_to_runtime_go(rt_net, &net.root, &mut vars, PARENT_ROOT);
+  let root = tree_to_runtime_go(rt_net, syms, &net.root, &mut vars, PARENT_ROOT)?;
   rt_net.heap.set_root(root);
   for (tree1, tree2) in &net.rdex {
-    let ptr1 = tree_to_runtime_go(rt_net, tree1, &mut vars, Parent::Redex);
-    let ptr2 = tree_to_runtime_go(rt_net, tree2, &mut vars, Parent::Redex);
+    let ptr1 = tree_to_runtime_go(rt_net, syms, tree1, &mut vars, Parent::Redex)?;
+    let ptr2 = tree_to_runtime_go(rt_net, syms, tree2, &mut vars, Parent::Redex)?;
     rt_net.rdex.push((ptr1, ptr2));
   }
+  return Ok(());
 }
 
-pub fn book_to_runtime(book: &Book) -> run::Book {
+// Definitions get the first ids, in name order, and the references with no definition the next.
+// Fails on the first definition that can't be injected.
+pub fn book_to_runtime(book: &Book) -> Result<run::Book, ValidationError> {
   let mut rt_book = run::Book::new();
+  for name in book.keys() {
+    rt_book.syms.intern(name);
+  }
   for (name, net) in book {
-    let fid = name_to_val(name);
+    let fid = rt_book.syms.intern(name);
     let data = run::Heap::init(1 << 16);
     let mut rt = run::Net::new(&data);
-    net_to_runtime(&mut rt, net);
+    if let Err(problem) = net_to_runtime(&mut rt, &mut rt_book.syms, net) {
+      return Err(ValidationError { def: name.clone(), problem });
+    }
     rt_book.def(fid, runtime_net_to_runtime_def(&rt));
   }
-  rt_book
+  return Ok(rt_book);
 }
 
 // Converts to a def.
@@ -616,33 +1302,36 @@ pub fn runtime_def_to_runtime_net<'a>(data: &'a run::Data, def: &run::Def) -> ru
   net
 }
 
-pub fn tree_from_runtime_go(rt_net: &run::Net, ptr: run::Ptr, parent: Parent, vars: &mut HashMap<Parent, String>, fresh: &mut usize) -> Tree {
+pub fn tree_from_runtime_go(rt_net: &run::Net, syms: &Symbols, ptr: run::Ptr, parent: Parent, vars: &mut HashMap<Parent, String>, fresh: &mut usize) -> Tree {
   match ptr.tag() {
     run::ERA => {
       Tree::Era
     }
     run::REF => {
-      Tree::Ref { nam: ptr.val() }
+      Tree::Ref { nam: syms.val(ptr.val()) }
     }
-    run::NUM => {
-      Tree::Num { val: ptr.val() }
+    run::NUM | run::INT | run::FLT => {
+      Tree::Num { typ: ptr.num_type(), val: ptr.val() }
     }
     run::OP1 => {
       let opr = ptr.lab();
-      let lft = tree_from_runtime_go(rt_net, rt_net.heap.get(ptr.loc(), run::P1), Parent::Node { loc: ptr.loc(), port: run::P1 }, vars, fresh);
-      let Tree::Num { val } = lft else { unreachable!() };
-      let rgt = tree_from_runtime_go(rt_net, rt_net.heap.get(ptr.loc(), run::P2), Parent::Node { loc: ptr.loc(), port: run::P2 }, vars, fresh);
-      Tree::Op1 { opr, lft: val, rgt: Box::new(rgt) }
+      let lft = tree_from_runtime_go(rt_net, syms, rt_net.heap.get(ptr.loc(), run::P1), Parent::Node { loc: ptr.loc(), port: run::P1 }, vars, fresh);
+      let rgt = tree_from_runtime_go(rt_net, syms, rt_net.heap.get(ptr.loc(), run::P2), Parent::Node { loc: ptr.loc(), port: run::P2 }, vars, fresh);
+      match lft {
+        Tree::Num { val, .. } => Tree::Op1 { opr, lft: val, rgt: Box::new(rgt) },
+        // A malformed OP1 (see 'InteractionError') is shown as an OP2, so that it can be reported.
+        lft => Tree::Op2 { opr, lft: Box::new(lft), rgt: Box::new(rgt) },
+      }
     }
     run::OP2 => {
       let opr = ptr.lab();
-      let lft = tree_from_runtime_go(rt_net, rt_net.heap.get(ptr.loc(), run::P1), Parent::Node { loc: ptr.loc(), port: run::P1 }, vars, fresh);
-      let rgt = tree_from_runtime_go(rt_net, rt_net.heap.get(ptr.loc(), run::P2), Parent::Node { loc: ptr.loc(), port: run::P2 }, vars, fresh);
+      let lft = tree_from_runtime_go(rt_net, syms, rt_net.heap.get(ptr.loc(), run::P1), Parent::Node { loc: ptr.loc(), port: run::P1 }, vars, fresh);
+      let rgt = tree_from_runtime_go(rt_net, syms, rt_net.heap.get(ptr.loc(), run::P2), Parent::Node { loc: ptr.loc(), port: run::P2 }, vars, fresh);
       Tree::Op2 { opr, lft: Box::new(lft), rgt: Box::new(rgt) }
     }
     run::MAT => {
-      let sel = tree_from_runtime_go(rt_net, rt_net.heap.get(ptr.loc(), run::P1), Parent::Node { loc: ptr.loc(), port: run::P1 }, vars, fresh);
-      let ret = tree_from_runtime_go(rt_net, rt_net.heap.get(ptr.loc(), run::P2), Parent::Node { loc: ptr.loc(), port: run::P2 }, vars, fresh);
+      let sel = tree_from_runtime_go(rt_net, syms, rt_net.heap.get(ptr.loc(), run::P1), Parent::Node { loc: ptr.loc(), port: run::P1 }, vars, fresh);
+      let ret = tree_from_runtime_go(rt_net, syms, rt_net.heap.get(ptr.loc(), run::P2), Parent::Node { loc: ptr.loc(), port: run::P2 }, vars, fresh);
       Tree::Mat { sel: Box::new(sel), ret: Box::new(ret) }
     }
     run::VR1 | run::VR2 => {
@@ -663,22 +1352,22 @@ pub fn tree_from_runtime_go(rt_net: &run::Net, ptr: run::Ptr, parent: Parent, va
     run::LAM => {
       let p1  = rt_net.heap.get(ptr.loc(), run::P1);
       let p2  = rt_net.heap.get(ptr.loc(), run::P2);
-      let lft = tree_from_runtime_go(rt_net, p1, Parent::Node { loc: ptr.loc(), port: run::P1 }, vars, fresh);
-      let rgt = tree_from_runtime_go(rt_net, p2, Parent::Node { loc: ptr.loc(), port: run::P2 }, vars, fresh);
+      let lft = tree_from_runtime_go(rt_net, syms, p1, Parent::Node { loc: ptr.loc(), port: run::P1 }, vars, fresh);
+      let rgt = tree_from_runtime_go(rt_net, syms, p2, Parent::Node { loc: ptr.loc(), port: run::P2 }, vars, fresh);
       Tree::Con { lft: Box::new(lft), rgt: Box::new(rgt) }
     }
     run::TUP => {
       let p1  = rt_net.heap.get(ptr.loc(), run::P1);
       let p2  = rt_net.heap.get(ptr.loc(), run::P2);
-      let lft = tree_from_runtime_go(rt_net, p1, Parent::Node { loc: ptr.loc(), port: run::P1 }, vars, fresh);
-      let rgt = tree_from_runtime_go(rt_net, p2, Parent::Node { loc: ptr.loc(), port: run::P2 }, vars, fresh);
+      let lft = tree_from_runtime_go(rt_net, syms, p1, Parent::Node { loc: ptr.loc(), port: run::P1 }, vars, fresh);
+      let rgt = tree_from_runtime_go(rt_net, syms, p2, Parent::Node { loc: ptr.loc(), port: run::P2 }, vars, fresh);
       Tree::Tup { lft: Box::new(lft), rgt: Box::new(rgt) }
     }
     run::DUP => {
       let p1  = rt_net.heap.get(ptr.loc(), run::P1);
       let p2  = rt_net.heap.get(ptr.loc(), run::P2);
-      let lft = tree_from_runtime_go(rt_net, p1, Parent::Node { loc: ptr.loc(), port: run::P1 }, vars, fresh);
-      let rgt = tree_from_runtime_go(rt_net, p2, Parent::Node { loc: ptr.loc(), port: run::P2 }, vars, fresh);
+      let lft = tree_from_runtime_go(rt_net, syms, p1, Parent::Node { loc: ptr.loc(), port: run::P1 }, vars, fresh);
+      let rgt = tree_from_runtime_go(rt_net, syms, p2, Parent::Node { loc: ptr.loc(), port: run::P2 }, vars, fresh);
       Tree::Dup { lab: ptr.lab(), lft: Box::new(lft), rgt: Box::new(rgt) }
     }
     _ => {
@@ -687,20 +1376,20 @@ pub fn tree_from_runtime_go(rt_net: &run::Net, ptr: run::Ptr, parent: Parent, va
   }
 }
 
-pub fn tree_from_runtime(rt_net: &run::Net, ptr: run::Ptr) -> Tree {
+pub fn tree_from_runtime(rt_net: &run::Net, syms: &Symbols, ptr: run::Ptr) -> Tree {
   let mut vars = HashMap::new();
   let mut fresh = 0;
-  tree_from_runtime_go(rt_net, ptr, PARENT_ROOT, &mut vars, &mut fresh)
+  tree_from_runtime_go(rt_net, syms, ptr, PARENT_ROOT, &mut vars, &mut fresh)
 }
 
-pub fn net_from_runtime(rt_net: &run::Net) -> Net {
+pub fn net_from_runtime(rt_net: &run::Net, syms: &Symbols) -> Net {
   let mut vars = HashMap::new();
   let mut fresh = 0;
   let mut rdex = Vec::new();
-  let root = tree_from_runtime_go(rt_net, rt_net.heap.get_root(), PARENT_ROOT, &mut vars, &mut fresh);
+  let root = tree_from_runtime_go(rt_net, syms, rt_net.heap.get_root(), PARENT_ROOT, &mut vars, &mut fresh);
   for &(a, b) in &rt_net.rdex {
-    let tree_a = tree_from_runtime_go(rt_net, a, Parent::Redex, &mut vars, &mut fresh);
-    let tree_b = tree_from_runtime_go(rt_net, b, Parent::Redex, &mut vars, &mut fresh);
+    let tree_a = tree_from_runtime_go(rt_net, syms, a, Parent::Redex, &mut vars, &mut fresh);
+    let tree_b = tree_from_runtime_go(rt_net, syms, b, Parent::Redex, &mut vars, &mut fresh);
     rdex.push((tree_a, tree_b));
   }
   Net { root, rdex }
@@ -710,9 +1399,9 @@ pub fn book_from_runtime(rt_book: &run::Book) -> Book {
   let mut book = BTreeMap::new();
   for (fid, def) in rt_book.defs.iter() {
     if def.node.len() > 0 {
-      let name = val_to_name(*fid);
+      let name = rt_book.syms.show(*fid);
       let data = run::Heap::init(def.node.len());
-      let net  = net_from_runtime(&runtime_def_to_runtime_net(&data, &def));
+      let net  = net_from_runtime(&runtime_def_to_runtime_net(&data, &def), &rt_book.syms);
       book.insert(name, net);
     }
   }
@@ -0,0 +1,431 @@
+// Binary books and snapshots
+// --------------------------
+// This file serializes runtime books to a compact binary format, so that big books can be loaded
+// without parsing or rebuilding their nets, runtime nets to snapshots, so that long reductions
+// can be resumed, and reduction traces, so that runs can be replayed elsewhere. All integers are
+// little-endian. The layout of a book is:
+//
+// - the magic bytes "HVMC\0BIN" and a u32 format version;
+// - a u64 symbol count, then, for each symbol, its u64 id, u64 byte length and UTF-8 name;
+// - a u64 definition count, then, for each definition, its u64 id, a u8 'safe' flag, a u64 redex
+//   count and redexes, and a u64 node count and nodes, each redex or node being two u64 pointers;
+// - a u64 FNV-1a checksum of everything before it.
+//
+// The layout of a snapshot is:
+//
+// - the magic bytes "HVMC\0NET" and a u32 format version;
+// - the symbol table, as above;
+// - the five u64 rewrite counters (anni, comm, eras, dref, oper) and the u64 allocation index;
+// - a u64 count of used heap nodes, then, for each, its u64 location and two u64 pointers;
+// - a u64 redex count and redexes;
+// - a u64 FNV-1a checksum of everything before it.
+//
+// The layout of a trace is:
+//
+// - the magic bytes "HVMC\0LOG" and a u32 format version;
+// - the symbol table, as above;
+// - a u64 thread count, then a u64 step count and, for each step, its u8 rule code, u32 thread,
+//   u32 epoch and two u64 pointers;
+// - a u64 FNV-1a checksum of everything before it.
+//
+// Ids are only meaningful within a file, since each book interns its own names. On load, a book's
+// symbols are interned into its table, while a snapshot's or trace's symbols are looked up in the
+// table of the book it's resumed or replayed with. Definitions and REF pointers are then remapped
+// to the ids of that table.
+
+use crate::ast::Symbols;
+use crate::run;
+use crate::trace::{Rule, Step, Trace};
+use std::collections::{BTreeSet, HashMap};
+
+pub const MAGIC: &[u8; 8] = b"HVMC\0BIN";
+pub const SNAPSHOT_MAGIC: &[u8; 8] = b"HVMC\0NET";
+pub const TRACE_MAGIC: &[u8; 8] = b"HVMC\0LOG";
+pub const VERSION: u32 = 2;
+
+// The most nodes a definition can have, i.e., the length of 'Net::locs'.
+const MAX_DEF_NODES: usize = 1 << 16;
+
+// An error found while loading a binary book or snapshot.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum BinaryError {
+  BadMagic, // the input doesn't start with the expected magic bytes
+  BadVersion { version: u32 }, // the input has an unsupported format version
+  BadChecksum, // the input is corrupted
+  Truncated, // the input ends too early
+  TrailingBytes, // the input has bytes after its checksum
+  BadSymbol { id: run::Val }, // a symbol isn't UTF-8, or a REF points to an id without symbol
+  UnknownSymbol { name: String }, // a snapshot or trace refers to a name the book doesn't have
+  HeapTooSmall { need: usize, size: usize }, // a snapshot doesn't fit in the heap it's loaded in
+  BadRule { code: u8 }, // a trace step has an unknown rule code
+  BadOperator { lab: run::Lab }, // an OP1/OP2 has an unknown type or operation
+  BadDefinition { name: String }, // a definition has too many nodes, or a pointer outside of them
+}
+
+impl std::fmt::Display for BinaryError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      BinaryError::BadMagic                    => write!(f, "not a binary book, snapshot or trace"),
+      BinaryError::BadVersion { version }      => write!(f, "unsupported binary format version {} (expected {})", version, VERSION),
+      BinaryError::BadChecksum                 => write!(f, "binary checksum mismatch"),
+      BinaryError::Truncated                   => write!(f, "binary input is truncated"),
+      BinaryError::TrailingBytes               => write!(f, "binary input has trailing bytes"),
+      BinaryError::BadSymbol { id }            => write!(f, "binary input has an invalid symbol for id {}", id),
+      BinaryError::UnknownSymbol { name }      => write!(f, "binary input refers to '@{}', which the book doesn't have", name),
+      BinaryError::HeapTooSmall { need, size } => write!(f, "snapshot needs a heap of {} nodes, but it has {}", need, size),
+      BinaryError::BadRule { code }            => write!(f, "binary trace has an unknown rule code {}", code),
+      BinaryError::BadOperator { lab }         => write!(f, "binary input has an unknown operator label 0x{:x}", lab),
+      BinaryError::BadDefinition { name }      => write!(f, "binary definition '@{}' has too many nodes, or a pointer outside of them", name),
+    }
+  }
+}
+
+impl std::error::Error for BinaryError {}
+
+// Whether some bytes look like a binary book, rather than text.
+pub fn is_binary(bytes: &[u8]) -> bool {
+  bytes.starts_with(MAGIC)
+}
+
+pub fn book_to_bytes(book: &run::Book) -> Vec<u8> {
+  // Definitions are written in id order, so that the output is deterministic.
+  let mut fids = book.defs.keys().copied().collect::<Vec<_>>();
+  fids.sort();
+
+  // The whole symbol table goes in, in id order, so that loading the book gives back the same ids,
+  // which compiled libraries rely on.
+  let mut syms = (0 .. book.syms.names().len() as run::Val).collect::<BTreeSet<_>>();
+  for fid in &fids {
+    syms.insert(*fid);
+    let def = &book.defs[fid];
+    add_symbols(&mut syms, def.rdex.iter().chain(def.node.iter()).copied());
+  }
+
+  let mut out = Vec::new();
+  out.extend_from_slice(MAGIC);
+  out.extend_from_slice(&VERSION.to_le_bytes());
+  put_symbols(&mut out, &book.syms, syms);
+  put_u64(&mut out, fids.len() as u64);
+  for fid in fids {
+    let def = &book.defs[&fid];
+    put_u64(&mut out, fid);
+    out.push(def.safe as u8);
+    for ptrs in [&def.rdex, &def.node] {
+      put_u64(&mut out, ptrs.len() as u64);
+      for &(a, b) in ptrs {
+        put_u64(&mut out, a.0);
+        put_u64(&mut out, b.0);
+      }
+    }
+  }
+  let sum = checksum(&out);
+  put_u64(&mut out, sum);
+  return out;
+}
+
+pub fn book_from_bytes(bytes: &[u8]) -> Result<run::Book, BinaryError> {
+  let mut input = Input::open(bytes, MAGIC)?;
+  let mut book = run::Book::new();
+  let ids = input.symbols(|name| Ok(book.syms.intern(name)))?;
+  let remap = |ptr| remap(&ids, ptr);
+
+  for _ in 0 .. input.u64()? {
+    let fid = input.u64()?;
+    let fid = *ids.get(&fid).ok_or(BinaryError::BadSymbol { id: fid })?;
+    let safe = input.take(1)?[0] != 0;
+    let mut rdex = vec![];
+    let mut node = vec![];
+    for ptrs in [&mut rdex, &mut node] {
+      for _ in 0 .. input.len()? {
+        let a = remap(run::Ptr(input.u64()?))?;
+        let b = remap(run::Ptr(input.u64()?))?;
+        ptrs.push((a, b));
+      }
+    }
+    if !is_valid_def(&rdex, &node) {
+      return Err(BinaryError::BadDefinition { name: book.syms.show(fid) });
+    }
+    book.def(fid, run::Def { safe, rdex, node });
+  }
+  if input.offset != input.bytes.len() {
+    return Err(BinaryError::TrailingBytes);
+  }
+  return Ok(book);
+}
+
+// Snapshots
+// ---------
+
+// Whether some bytes look like a snapshot.
+pub fn is_snapshot(bytes: &[u8]) -> bool {
+  bytes.starts_with(SNAPSHOT_MAGIC)
+}
+
+// Serializes the state of a net between reductions, i.e., when no thread is running. Only used
+// heap nodes are stored, so the size of a snapshot is proportional to the net, not the heap.
+pub fn net_to_bytes(net: &run::Net, book: &run::Book) -> Vec<u8> {
+  let mut used = vec![];
+  for loc in 0 .. net.heap.data.len() as run::Loc {
+    let p1 = net.heap.get(loc, run::P1);
+    let p2 = net.heap.get(loc, run::P2);
+    if !p1.is_nil() || !p2.is_nil() {
+      used.push((loc, p1, p2));
+    }
+  }
+  let mut syms = BTreeSet::new();
+  add_symbols(&mut syms, used.iter().map(|&(_, p1, p2)| (p1, p2)));
+  add_symbols(&mut syms, net.rdex.iter().copied());
+
+  let mut out = Vec::new();
+  out.extend_from_slice(SNAPSHOT_MAGIC);
+  out.extend_from_slice(&VERSION.to_le_bytes());
+  put_symbols(&mut out, &book.syms, syms);
+  for count in [net.rwts.anni, net.rwts.comm, net.rwts.eras, net.rwts.dref, net.rwts.oper, net.next] {
+    put_u64(&mut out, count as u64);
+  }
+  put_u64(&mut out, used.len() as u64);
+  for (loc, p1, p2) in used {
+    put_u64(&mut out, loc as u64);
+    put_u64(&mut out, p1.0);
+    put_u64(&mut out, p2.0);
+  }
+  put_u64(&mut out, net.rdex.len() as u64);
+  for &(a, b) in &net.rdex {
+    put_u64(&mut out, a.0);
+    put_u64(&mut out, b.0);
+  }
+  let sum = checksum(&out);
+  put_u64(&mut out, sum);
+  return out;
+}
+
+// Restores a snapshot into a net, which must have an empty heap. Then, 'normal' and
+// 'parallel_normal' resume the reduction, given the book of the original net.
+pub fn net_from_bytes(net: &mut run::Net, book: &run::Book, bytes: &[u8]) -> Result<(), BinaryError> {
+  let mut input = Input::open(bytes, SNAPSHOT_MAGIC)?;
+  let ids = input.symbols(|name| lookup(&book.syms, name))?;
+  let mut counts = [0; 6];
+  for count in &mut counts {
+    *count = input.u64()? as usize;
+  }
+  // The heap is accessed unchecked, so every location must be in it. The allocation index stays
+  // below two heap sizes, since 'alloc' keeps it there once it sweeps.
+  let size = net.heap.data.len();
+  let fits = |loc: u64| if loc < size as u64 { Ok(()) } else { Err(BinaryError::HeapTooSmall { need: (loc as usize).saturating_add(1), size }) };
+  let located = |ptr: run::Ptr| if ptr.has_loc() { fits(ptr.loc() as u64).map(|_| ptr) } else { Ok(ptr) };
+  if counts[5] / 2 >= size {
+    return Err(BinaryError::HeapTooSmall { need: counts[5] / 2 + 1, size });
+  }
+  let mut used = vec![];
+  for _ in 0 .. input.len()? {
+    let loc = input.u64()?;
+    fits(loc)?;
+    let p1 = located(remap(&ids, run::Ptr(input.u64()?))?)?;
+    let p2 = located(remap(&ids, run::Ptr(input.u64()?))?)?;
+    used.push((loc as run::Loc, p1, p2));
+  }
+  let mut rdex = vec![];
+  for _ in 0 .. input.len()? {
+    let a = located(remap(&ids, run::Ptr(input.u64()?))?)?;
+    let b = located(remap(&ids, run::Ptr(input.u64()?))?)?;
+    rdex.push((a, b));
+  }
+  if input.offset != input.bytes.len() {
+    return Err(BinaryError::TrailingBytes);
+  }
+
+  // Only touches the net once the whole snapshot is known to be valid.
+  for (loc, p1, p2) in used {
+    net.heap.set(loc, run::P1, p1);
+    net.heap.set(loc, run::P2, p2);
+  }
+  let [anni, comm, eras, dref, oper, next] = counts;
+  net.rwts = run::Rewrites { anni, comm, eras, dref, oper };
+  net.next = next;
+  net.rdex = rdex;
+  return Ok(());
+}
+
+pub fn trace_to_bytes(trace: &Trace, book: &run::Book) -> Vec<u8> {
+  let mut syms = BTreeSet::new();
+  add_symbols(&mut syms, trace.steps.iter().map(|step| (step.a, step.b)));
+
+  let mut out = Vec::new();
+  out.extend_from_slice(TRACE_MAGIC);
+  out.extend_from_slice(&VERSION.to_le_bytes());
+  put_symbols(&mut out, &book.syms, syms);
+  put_u64(&mut out, trace.tids as u64);
+  put_u64(&mut out, trace.steps.len() as u64);
+  for step in &trace.steps {
+    out.push(step.rule.code());
+    out.extend_from_slice(&(step.tid as u32).to_le_bytes());
+    out.extend_from_slice(&(step.epoch as u32).to_le_bytes());
+    put_u64(&mut out, step.a.0);
+    put_u64(&mut out, step.b.0);
+  }
+  let sum = checksum(&out);
+  put_u64(&mut out, sum);
+  return out;
+}
+
+pub fn trace_from_bytes(bytes: &[u8], book: &run::Book) -> Result<Trace, BinaryError> {
+  let mut input = Input::open(bytes, TRACE_MAGIC)?;
+  let ids = input.symbols(|name| lookup(&book.syms, name))?;
+  let tids = input.u64()? as usize;
+  let mut steps = vec![];
+  for _ in 0 .. input.len()? {
+    let code = input.take(1)?[0];
+    let rule = Rule::from_code(code).ok_or(BinaryError::BadRule { code })?;
+    let tid = input.u32()? as usize;
+    let epoch = input.u32()? as usize;
+    let a = remap(&ids, run::Ptr(input.u64()?))?;
+    let b = remap(&ids, run::Ptr(input.u64()?))?;
+    steps.push(Step { tid, epoch, rule, a, b });
+  }
+  if input.offset != input.bytes.len() {
+    return Err(BinaryError::TrailingBytes);
+  }
+  return Ok(Trace { tids, steps });
+}
+
+// Helpers
+// -------
+
+// Adds the ids referenced by some pointers to a symbol set.
+fn add_symbols(syms: &mut BTreeSet<run::Val>, ptrs: impl Iterator<Item = (run::Ptr, run::Ptr)>) {
+  for (a, b) in ptrs {
+    for ptr in [a, b] {
+      if ptr.is_ref() {
+        syms.insert(ptr.val());
+      }
+    }
+  }
+}
+
+fn put_symbols(out: &mut Vec<u8>, table: &Symbols, syms: BTreeSet<run::Val>) {
+  put_u64(out, syms.len() as u64);
+  for id in syms {
+    let name = table.show(id);
+    put_u64(out, id);
+    put_u64(out, name.len() as u64);
+    out.extend_from_slice(name.as_bytes());
+  }
+}
+
+// The id of a name in the table of an existing book.
+fn lookup(syms: &Symbols, name: &str) -> Result<run::Val, BinaryError> {
+  syms.id(name).ok_or_else(|| BinaryError::UnknownSymbol { name: name.to_string() })
+}
+
+// Maps a REF from the ids of the file to the ids of the book, and checks operator labels.
+fn remap(ids: &HashMap<run::Val, run::Val>, ptr: run::Ptr) -> Result<run::Ptr, BinaryError> {
+  if (ptr.is_op1() || ptr.is_op2()) && !run::is_op(ptr.lab()) {
+    Err(BinaryError::BadOperator { lab: ptr.lab() })
+  } else if ptr.is_ref() {
+    match ids.get(&ptr.val()) {
+      Some(id) => Ok(run::Ptr::big(run::REF, *id)),
+      None     => Err(BinaryError::BadSymbol { id: ptr.val() }),
+    }
+  } else {
+    Ok(ptr)
+  }
+}
+
+// Whether a definition can be expanded. Expansion indexes the nodes and 'Net::locs' unchecked, so
+// nodes must fit in 'locs', and located pointers must point to a node. Location 0 is the root,
+// which isn't allocated on expansion, so only variables to its second port may point to it.
+fn is_valid_def(rdex: &[(run::Ptr, run::Ptr)], node: &[(run::Ptr, run::Ptr)]) -> bool {
+  if node.len() > MAX_DEF_NODES {
+    return false;
+  }
+  for &(a, b) in rdex.iter().chain(node.iter()) {
+    for ptr in [a, b] {
+      if ptr.has_loc() && !ptr.is_nil() && ptr != run::ROOT && (ptr.loc() == 0 || ptr.loc() as usize >= node.len()) {
+        return false;
+      }
+    }
+  }
+  return true;
+}
+
+struct Input<'a> {
+  bytes: &'a [u8], // input, without its checksum once it's verified
+  offset: usize, // bytes read so far
+}
+
+impl<'a> Input<'a> {
+  // Checks the header and checksum of some bytes, returning an input past the header.
+  fn open(bytes: &'a [u8], magic: &[u8]) -> Result<Self, BinaryError> {
+    if !bytes.starts_with(magic) {
+      return Err(BinaryError::BadMagic);
+    }
+    let mut input = Input { bytes, offset: magic.len() };
+    let version = u32::from_le_bytes(input.take(4)?.try_into().unwrap());
+    if version != VERSION {
+      return Err(BinaryError::BadVersion { version });
+    }
+    if bytes.len() < magic.len() + 4 + 8 {
+      return Err(BinaryError::Truncated);
+    }
+    let (body, sum) = bytes.split_at(bytes.len() - 8);
+    if checksum(body) != u64::from_le_bytes(sum.try_into().unwrap()) {
+      return Err(BinaryError::BadChecksum);
+    }
+    input.bytes = body;
+    return Ok(input);
+  }
+
+  // Reads a symbol table, giving each name an id of the book. Returns a map from the ids of the
+  // file to the ids of the book.
+  fn symbols(&mut self, mut id_of: impl FnMut(&str) -> Result<run::Val, BinaryError>) -> Result<HashMap<run::Val, run::Val>, BinaryError> {
+    let mut ids = HashMap::new();
+    for _ in 0 .. self.u64()? {
+      let id = self.u64()?;
+      let len = self.len()?;
+      let name = std::str::from_utf8(self.take(len)?).map_err(|_| BinaryError::BadSymbol { id })?;
+      ids.insert(id, id_of(name)?);
+    }
+    return Ok(ids);
+  }
+
+  fn take(&mut self, len: usize) -> Result<&'a [u8], BinaryError> {
+    if self.bytes.len() - self.offset < len {
+      return Err(BinaryError::Truncated);
+    }
+    let got = &self.bytes[self.offset .. self.offset + len];
+    self.offset += len;
+    return Ok(got);
+  }
+
+  fn u32(&mut self) -> Result<u32, BinaryError> {
+    Ok(u32::from_le_bytes(self.take(4)?.try_into().unwrap()))
+  }
+
+  fn u64(&mut self) -> Result<u64, BinaryError> {
+    Ok(u64::from_le_bytes(self.take(8)?.try_into().unwrap()))
+  }
+
+  // Reads a length, which can't exceed the remaining input, so that corrupted lengths don't make
+  // loops spin or allocations explode.
+  fn len(&mut self) -> Result<usize, BinaryError> {
+    let len = self.u64()?;
+    if len > (self.bytes.len() - self.offset) as u64 {
+      return Err(BinaryError::Truncated);
+    }
+    return Ok(len as usize);
+  }
+}
+
+fn put_u64(out: &mut Vec<u8>, val: u64) {
+  out.extend_from_slice(&val.to_le_bytes());
+}
+
+// 64-bit FNV-1a.
+fn checksum(bytes: &[u8]) -> u64 {
+  let mut hash: u64 = 0xcbf29ce484222325;
+  for byte in bytes {
+    hash ^= *byte as u64;
+    hash = hash.wrapping_mul(0x100000001b3);
+  }
+  return hash;
+}
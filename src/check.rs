@@ -0,0 +1,243 @@
+// Static book diagnostics
+// -----------------------
+// This file analyzes a book without running it. Besides validation, it reports the size of each
+// compiled definition, whether the runtime can copy it through DUPs ('safe'), which definitions
+// can't be reached from '@main', and which definitions are mutually recursive.
+
+use crate::ast::{net_to_runtime, runtime_net_to_runtime_def, val_to_name, validate_book, Book, Symbols, Tree, ValidationError};
+use crate::run;
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+// Runtime stats of a definition, as compiled by 'runtime_net_to_runtime_def'.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct DefStats {
+  pub nodes: usize, // node count, excluding the root
+  pub rdex: usize, // redex count
+  pub safe: bool, // whether DUPs can copy its REF without expanding it
+  pub blockers: Vec<&'static str>, // what made it unsafe ("DUP", "REF")
+}
+
+// A full diagnostic report of a book.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Report {
+  pub defs: BTreeMap<String, Option<DefStats>>, // stats of each definition (None if it's invalid)
+  pub unreachable: Vec<String>, // definitions not referenced, even indirectly, by '@main'
+  pub cycles: Vec<Vec<String>>, // groups of mutually recursive definitions
+  pub errors: Vec<ValidationError>, // validation errors
+}
+
+pub fn check_book(book: &Book) -> Report {
+  let mut errors = validate_book(book).err().unwrap_or_default();
+  let graph = ref_graph(book);
+
+  // Compiles each valid definition on its own, since invalid nets can't be injected. Definitions
+  // that don't fit in the heap of 'book_to_runtime' are reported as errors, too.
+  let mut defs = BTreeMap::new();
+  for (name, net) in book {
+    let stats = if errors.iter().any(|err| &err.def == name) {
+      None
+    } else {
+      let data = run::Heap::init(1 << 16);
+      let mut rt = run::Net::new(&data);
+      match net_to_runtime(&mut rt, &mut Symbols::new(), net) {
+        Ok(()) => Some(def_stats(&runtime_net_to_runtime_def(&rt))),
+        Err(problem) => {
+          errors.push(ValidationError { def: name.clone(), problem });
+          None
+        }
+      }
+    };
+    defs.insert(name.clone(), stats);
+  }
+
+  // Walks the reference graph from '@main'.
+  let mut seen = BTreeSet::new();
+  let mut todo = if book.contains_key("main") { vec!["main"] } else { vec![] };
+  while let Some(name) = todo.pop() {
+    if seen.insert(name) {
+      todo.extend(graph[name].iter().map(|nam| nam.as_str()));
+    }
+  }
+  let unreachable = book.keys().filter(|name| !seen.contains(name.as_str())).cloned().collect();
+
+  let cycles = ref_cycles(&graph);
+  return Report { defs, unreachable, cycles, errors };
+}
+
+// Builds a map from each definition to the (defined) definitions it references.
+fn ref_graph(book: &Book) -> BTreeMap<&str, BTreeSet<String>> {
+  fn go(tree: &Tree, book: &Book, refs: &mut BTreeSet<String>) {
+    match tree {
+      Tree::Ref { nam } => {
+        let nam = val_to_name(*nam);
+        if book.contains_key(&nam) {
+          refs.insert(nam);
+        }
+      }
+      Tree::Con { lft, rgt } | Tree::Tup { lft, rgt } | Tree::Dup { lft, rgt, .. } | Tree::Op2 { lft, rgt, .. } => {
+        go(lft, book, refs);
+        go(rgt, book, refs);
+      }
+      Tree::Op1 { rgt, .. } => {
+        go(rgt, book, refs);
+      }
+      Tree::Mat { sel, ret } => {
+        go(sel, book, refs);
+        go(ret, book, refs);
+      }
+      Tree::Era | Tree::Var { .. } | Tree::Num { .. } => {}
+    }
+  }
+  let mut graph = BTreeMap::new();
+  for (name, net) in book {
+    let mut refs = BTreeSet::new();
+    go(&net.root, book, &mut refs);
+    for (a, b) in &net.rdex {
+      go(a, book, &mut refs);
+      go(b, book, &mut refs);
+    }
+    graph.insert(name.as_str(), refs);
+  }
+  return graph;
+}
+
+// Finds the strongly connected components of the reference graph that contain a cycle, using
+// Tarjan's algorithm.
+fn ref_cycles(graph: &BTreeMap<&str, BTreeSet<String>>) -> Vec<Vec<String>> {
+  struct State<'a> {
+    index: HashMap<&'a str, usize>, // visit order of each definition
+    lowest: HashMap<&'a str, usize>, // lowest visit order reachable from each definition
+    stack: Vec<&'a str>, // definitions of the components being visited
+    cycles: Vec<Vec<String>>, // components found
+  }
+  fn visit<'a>(graph: &'a BTreeMap<&str, BTreeSet<String>>, name: &'a str, st: &mut State<'a>) {
+    let index = st.index.len();
+    st.index.insert(name, index);
+    st.lowest.insert(name, index);
+    st.stack.push(name);
+    for next in &graph[name] {
+      let next = next.as_str();
+      if !st.index.contains_key(next) {
+        visit(graph, next, st);
+        let low = std::cmp::min(st.lowest[name], st.lowest[next]);
+        st.lowest.insert(name, low);
+      } else if st.stack.contains(&next) {
+        let low = std::cmp::min(st.lowest[name], st.index[next]);
+        st.lowest.insert(name, low);
+      }
+    }
+    if st.lowest[name] == index {
+      let mut group = vec![];
+      loop {
+        let got = st.stack.pop().unwrap();
+        group.push(got.to_string());
+        if got == name {
+          break;
+        }
+      }
+      if group.len() > 1 || graph[name].contains(name) {
+        group.sort();
+        st.cycles.push(group);
+      }
+    }
+  }
+  let mut st = State { index: HashMap::new(), lowest: HashMap::new(), stack: vec![], cycles: vec![] };
+  for name in graph.keys() {
+    if !st.index.contains_key(name) {
+      visit(graph, name, &mut st);
+    }
+  }
+  st.cycles.sort();
+  return st.cycles;
+}
+
+fn def_stats(def: &run::Def) -> DefStats {
+  let ptrs = || def.node.iter().chain(def.rdex.iter()).flat_map(|(a, b)| [*a, *b]);
+  let mut blockers = vec![];
+  if ptrs().any(|ptr| ptr.is_dup()) {
+    blockers.push("DUP");
+  }
+  if ptrs().any(|ptr| ptr.is_ref()) {
+    blockers.push("REF");
+  }
+  DefStats { nodes: def.node.len().saturating_sub(1), rdex: def.rdex.len(), safe: def.safe, blockers }
+}
+
+impl Report {
+  pub fn is_ok(&self) -> bool {
+    self.errors.is_empty()
+  }
+
+  pub fn show(&self) -> String {
+    let mut code = String::new();
+    code.push_str(&format!("definitions: {}\n", self.defs.len()));
+    for (name, stats) in &self.defs {
+      match stats {
+        Some(stats) if stats.safe => {
+          code.push_str(&format!("  @{}: {} nodes, {} redexes, safe\n", name, stats.nodes, stats.rdex));
+        }
+        Some(stats) => {
+          code.push_str(&format!("  @{}: {} nodes, {} redexes, unsafe ({})\n", name, stats.nodes, stats.rdex, stats.blockers.join(", ")));
+        }
+        None => {
+          code.push_str(&format!("  @{}: invalid\n", name));
+        }
+      }
+    }
+    if !self.unreachable.is_empty() {
+      code.push_str(&format!("unreachable from @main: {}\n", show_names(&self.unreachable)));
+    }
+    for cycle in &self.cycles {
+      code.push_str(&format!("recursive: {}\n", show_names(cycle)));
+    }
+    for err in &self.errors {
+      code.push_str(&format!("error: {}\n", err));
+    }
+    return code;
+  }
+
+  pub fn to_json(&self) -> String {
+    let mut defs = vec![];
+    for (name, stats) in &self.defs {
+      defs.push(match stats {
+        Some(stats) => {
+          let blockers = stats.blockers.iter().map(|b| json_str(b)).collect::<Vec<_>>();
+          format!("{{\"name\":{},\"nodes\":{},\"redexes\":{},\"safe\":{},\"blockers\":[{}]}}", json_str(name), stats.nodes, stats.rdex, stats.safe, blockers.join(","))
+        }
+        None => {
+          format!("{{\"name\":{},\"nodes\":null,\"redexes\":null,\"safe\":null,\"blockers\":null}}", json_str(name))
+        }
+      });
+    }
+    let unreachable = self.unreachable.iter().map(|name| json_str(name)).collect::<Vec<_>>();
+    let cycles = self.cycles.iter().map(|cycle| {
+      format!("[{}]", cycle.iter().map(|name| json_str(name)).collect::<Vec<_>>().join(","))
+    }).collect::<Vec<_>>();
+    let errors = self.errors.iter().map(|err| {
+      format!("{{\"def\":{},\"message\":{}}}", json_str(&err.def), json_str(&err.problem.to_string()))
+    }).collect::<Vec<_>>();
+    return format!(
+      "{{\"ok\":{},\"definitions\":{},\"defs\":[{}],\"unreachable\":[{}],\"cycles\":[{}],\"errors\":[{}]}}",
+      self.is_ok(), self.defs.len(), defs.join(","), unreachable.join(","), cycles.join(","), errors.join(",")
+    );
+  }
+}
+
+fn show_names(names: &[String]) -> String {
+  names.iter().map(|name| format!("@{}", name)).collect::<Vec<_>>().join(", ")
+}
+
+fn json_str(text: &str) -> String {
+  let mut code = String::from("\"");
+  for c in text.chars() {
+    match c {
+      '"'  => code.push_str("\\\""),
+      '\\' => code.push_str("\\\\"),
+      '\n' => code.push_str("\\n"),
+      c if (c as u32) < 0x20 => code.push_str(&format!("\\u{:04x}", c as u32)),
+      c    => code.push(c),
+    }
+  }
+  code.push('"');
+  return code;
+}
@@ -0,0 +1,469 @@
+// A lossless concrete syntax tree
+// -------------------------------
+// This file implements a concrete syntax tree for '.hvmc' sources. Unlike the AST, it keeps every
+// token as written, together with the whitespace and comments before it ('trivia'), so printing a
+// parsed book gives back the source byte-for-byte. Comments above a definition or a redex are on
+// the trivia of its '@' or '&' token. It accepts exactly the grammar of the AST parser, reporting
+// the same errors, and can be converted down to the AST.
+
+use crate::ast::{self, Cursor, ParseError};
+use crate::run;
+
+// A token, with the trivia before it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Token {
+  pub trivia: String, // whitespace and comments before the token
+  pub text: String, // the token itself
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum Tree {
+  Era { star: Token },
+  Con { open: Token, lft: Box<Tree>, rgt: Box<Tree>, close: Token },
+  Tup { open: Token, lft: Box<Tree>, rgt: Box<Tree>, close: Token },
+  Dup { open: Token, lab: Token, lft: Box<Tree>, rgt: Box<Tree>, close: Token },
+  Var { nam: Token },
+  Ref { at: Token, nam: Token },
+  Num { hash: Token, val: Token },
+  Op1 { open: Token, lft: Token, opr: Token, rgt: Box<Tree>, close: Token },
+  Op2 { open: Token, opr: Token, lft: Box<Tree>, rgt: Box<Tree>, close: Token },
+  Mat { open: Token, lt: Token, sel: Box<Tree>, ret: Box<Tree>, close: Token },
+  Str { lit: Token }, // the whole literal, quotes included
+  Lst { open: Token, els: Vec<Tree>, commas: Vec<Token>, close: Token },
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Redex {
+  pub amp: Token, // the '&'
+  pub lft: Tree,
+  pub tilde: Token, // the '~'
+  pub rgt: Tree,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Net {
+  pub root: Tree,
+  pub rdex: Vec<Redex>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Def {
+  pub at: Token, // the '@'
+  pub nam: Token,
+  pub eq: Token, // the '='
+  pub net: Net,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Book {
+  pub defs: Vec<Def>, // definitions, in source order
+  pub end: String, // trivia after the last definition
+}
+
+impl Token {
+  // The comments on this token's trivia, without the line breaks.
+  pub fn comments(&self) -> Vec<&str> {
+    self.trivia.lines().map(|line| line.trim()).filter(|line| line.starts_with('/')).collect()
+  }
+}
+
+// Parser
+// ------
+
+// Consumes whitespace and comments, like 'ast::skip', returning them.
+fn trivia(chars: &mut Cursor) -> String {
+  let init = chars.offset;
+  while let Some(c) = chars.peek() {
+    if c == '/' {
+      while chars.peek().map_or(false, |c| c != '\n') {
+        chars.next();
+      }
+    } else if c.is_ascii_whitespace() {
+      chars.next();
+    } else {
+      break;
+    }
+  }
+  chars.code[init .. chars.offset].to_string()
+}
+
+// Builds a token from a trivia and the text consumed since 'init'.
+fn token_from(chars: &Cursor, trivia: String, init: usize) -> Token {
+  Token { trivia, text: chars.code[init .. chars.offset].to_string() }
+}
+
+fn is_name_char(c: char) -> bool {
+  c.is_alphanumeric() || c == '_' || c == '.'
+}
+
+pub fn consume(chars: &mut Cursor, text: &str) -> Result<Token, ParseError> {
+  let trivia = trivia(chars);
+  let init = chars.offset;
+  ast::consume(chars, text)?;
+  Ok(token_from(chars, trivia, init))
+}
+
+pub fn parse_decimal(chars: &mut Cursor) -> Result<Token, ParseError> {
+  let trivia = trivia(chars);
+  let init = chars.offset;
+  ast::parse_decimal(chars)?;
+  Ok(token_from(chars, trivia, init))
+}
+
+pub fn parse_number(chars: &mut Cursor) -> Result<Token, ParseError> {
+  let trivia = trivia(chars);
+  let init = chars.offset;
+  ast::parse_number(chars)?;
+  Ok(token_from(chars, trivia, init))
+}
+
+pub fn parse_name(chars: &mut Cursor) -> Result<Token, ParseError> {
+  let trivia = trivia(chars);
+  let init = chars.offset;
+  ast::parse_name(chars)?;
+  Ok(token_from(chars, trivia, init))
+}
+
+pub fn parse_opr(chars: &mut Cursor) -> Result<Token, ParseError> {
+  let trivia = trivia(chars);
+  let init = chars.offset;
+  ast::parse_opr(chars)?;
+  Ok(token_from(chars, trivia, init))
+}
+
+pub fn parse_tree(chars: &mut Cursor) -> Result<Tree, ParseError> {
+  let trivia = trivia(chars);
+  let init = chars.offset;
+  match chars.peek() {
+    Some('*') => {
+      chars.next();
+      Ok(Tree::Era { star: token_from(chars, trivia, init) })
+    }
+    Some('(') => {
+      chars.next();
+      let open = token_from(chars, trivia, init);
+      let lft = Box::new(parse_tree(chars)?);
+      let rgt = Box::new(parse_tree(chars)?);
+      let close = consume(chars, ")")?;
+      Ok(Tree::Con { open, lft, rgt, close })
+    }
+    Some('[') => {
+      chars.next();
+      let open = token_from(chars, trivia, init);
+      // A list is '[]', '[a]' or '[a, b, ...]'; a tuple is '[a b]'.
+      if next_is(chars, ']') {
+        let close = consume(chars, "]")?;
+        return Ok(Tree::Lst { open, els: vec![], commas: vec![], close });
+      }
+      let lft = parse_tree(chars)?;
+      if next_is(chars, ',') || next_is(chars, ']') {
+        let mut els = vec![lft];
+        let mut commas = vec![];
+        while next_is(chars, ',') {
+          commas.push(consume(chars, ",")?);
+          els.push(parse_tree(chars)?);
+          if !next_is(chars, ',') && !next_is(chars, ']') {
+            self::trivia(chars);
+            return Err(chars.error("',' or ']'"));
+          }
+        }
+        let close = consume(chars, "]")?;
+        return Ok(Tree::Lst { open, els, commas, close });
+      }
+      let rgt = Box::new(parse_tree(chars)?);
+      let close = consume(chars, "]")?;
+      Ok(Tree::Tup { open, lft: Box::new(lft), rgt, close })
+    }
+    Some('{') => {
+      chars.next();
+      let open = token_from(chars, trivia, init);
+      let lab = parse_decimal(chars)?;
+      let lft = Box::new(parse_tree(chars)?);
+      let rgt = Box::new(parse_tree(chars)?);
+      let close = consume(chars, "}")?;
+      Ok(Tree::Dup { open, lab, lft, rgt, close })
+    }
+    Some('@') => {
+      chars.next();
+      let at = token_from(chars, trivia, init);
+      let nam = parse_name(chars)?;
+      Ok(Tree::Ref { at, nam })
+    }
+    Some('#') => {
+      chars.next();
+      let hash = token_from(chars, trivia, init);
+      let val = parse_number(chars)?;
+      Ok(Tree::Num { hash, val })
+    }
+    Some('<') => {
+      chars.next();
+      let open = token_from(chars, trivia, init);
+      if ast::starts_number(chars) {
+        let lft = parse_number(chars)?;
+        let opr = parse_opr(chars)?;
+        let rgt = Box::new(parse_tree(chars)?);
+        let close = consume(chars, ">")?;
+        Ok(Tree::Op1 { open, lft, opr, rgt, close })
+      } else {
+        let opr = parse_opr(chars)?;
+        let lft = Box::new(parse_tree(chars)?);
+        let rgt = Box::new(parse_tree(chars)?);
+        let close = consume(chars, ">")?;
+        Ok(Tree::Op2 { open, opr, lft, rgt, close })
+      }
+    }
+    Some('?') => {
+      chars.next();
+      let open = token_from(chars, trivia, init);
+      let lt = consume(chars, "<")?;
+      let sel = Box::new(parse_tree(chars)?);
+      let ret = Box::new(parse_tree(chars)?);
+      let close = consume(chars, ">")?;
+      Ok(Tree::Mat { open, lt, sel, ret, close })
+    }
+    Some('"') => {
+      ast::parse_string(chars)?;
+      Ok(Tree::Str { lit: token_from(chars, trivia, init) })
+    }
+    _ => {
+      if !chars.peek().map_or(false, is_name_char) {
+        return Err(chars.error("a tree"));
+      }
+      ast::parse_name(chars)?;
+      Ok(Tree::Var { nam: token_from(chars, trivia, init) })
+    },
+  }
+}
+
+// Whether the next char after trivia is 'c'. Consumes nothing.
+fn next_is(chars: &mut Cursor, c: char) -> bool {
+  let init = chars.offset;
+  trivia(chars);
+  let next = chars.peek() == Some(c);
+  chars.offset = init;
+  return next;
+}
+
+pub fn parse_net(chars: &mut Cursor) -> Result<Net, ParseError> {
+  let mut rdex = Vec::new();
+  let root = parse_tree(chars)?;
+  loop {
+    // Trivia before anything but a '&' is left for the caller.
+    let init = chars.offset;
+    let trivia = trivia(chars);
+    if chars.peek() != Some('&') {
+      chars.offset = init;
+      break;
+    }
+    let init = chars.offset;
+    chars.next();
+    let amp = token_from(chars, trivia, init);
+    let lft = parse_tree(chars)?;
+    let tilde = consume(chars, "~")?;
+    let rgt = parse_tree(chars)?;
+    rdex.push(Redex { amp, lft, tilde, rgt });
+  }
+  Ok(Net { root, rdex })
+}
+
+pub fn parse_book(chars: &mut Cursor) -> Result<Book, ParseError> {
+  let mut defs = Vec::new();
+  loop {
+    let trivia = trivia(chars);
+    if chars.peek() != Some('@') {
+      return Ok(Book { defs, end: trivia });
+    }
+    let init = chars.offset;
+    chars.next();
+    let at = token_from(chars, trivia, init);
+    let nam = parse_name(chars)?;
+    let eq = consume(chars, "=")?;
+    let net = parse_net(chars)?;
+    defs.push(Def { at, nam, eq, net });
+  }
+}
+
+pub fn do_parse_book(code: &str) -> Result<Book, ParseError> {
+  let chars = &mut Cursor::new(code);
+  let book = parse_book(chars)?;
+  if chars.peek().is_some() {
+    return Err(chars.error("end of input"));
+  }
+  Ok(book)
+}
+
+// Printer
+// -------
+
+fn show_token(out: &mut String, token: &Token) {
+  out.push_str(&token.trivia);
+  out.push_str(&token.text);
+}
+
+fn show_tree_go(out: &mut String, tree: &Tree) {
+  match tree {
+    Tree::Era { star } => {
+      show_token(out, star);
+    }
+    Tree::Con { open, lft, rgt, close } | Tree::Tup { open, lft, rgt, close } => {
+      show_token(out, open);
+      show_tree_go(out, lft);
+      show_tree_go(out, rgt);
+      show_token(out, close);
+    }
+    Tree::Dup { open, lab, lft, rgt, close } => {
+      show_token(out, open);
+      show_token(out, lab);
+      show_tree_go(out, lft);
+      show_tree_go(out, rgt);
+      show_token(out, close);
+    }
+    Tree::Var { nam } => {
+      show_token(out, nam);
+    }
+    Tree::Ref { at, nam } => {
+      show_token(out, at);
+      show_token(out, nam);
+    }
+    Tree::Num { hash, val } => {
+      show_token(out, hash);
+      show_token(out, val);
+    }
+    Tree::Op1 { open, lft, opr, rgt, close } => {
+      show_token(out, open);
+      show_token(out, lft);
+      show_token(out, opr);
+      show_tree_go(out, rgt);
+      show_token(out, close);
+    }
+    Tree::Op2 { open, opr, lft, rgt, close } => {
+      show_token(out, open);
+      show_token(out, opr);
+      show_tree_go(out, lft);
+      show_tree_go(out, rgt);
+      show_token(out, close);
+    }
+    Tree::Mat { open, lt, sel, ret, close } => {
+      show_token(out, open);
+      show_token(out, lt);
+      show_tree_go(out, sel);
+      show_tree_go(out, ret);
+      show_token(out, close);
+    }
+    Tree::Str { lit } => {
+      show_token(out, lit);
+    }
+    Tree::Lst { open, els, commas, close } => {
+      show_token(out, open);
+      for (i, el) in els.iter().enumerate() {
+        if i > 0 {
+          show_token(out, &commas[i - 1]);
+        }
+        show_tree_go(out, el);
+      }
+      show_token(out, close);
+    }
+  }
+}
+
+pub fn show_tree(tree: &Tree) -> String {
+  let mut out = String::new();
+  show_tree_go(&mut out, tree);
+  return out;
+}
+
+pub fn show_net(net: &Net) -> String {
+  let mut out = String::new();
+  show_tree_go(&mut out, &net.root);
+  for redex in &net.rdex {
+    show_token(&mut out, &redex.amp);
+    show_tree_go(&mut out, &redex.lft);
+    show_token(&mut out, &redex.tilde);
+    show_tree_go(&mut out, &redex.rgt);
+  }
+  return out;
+}
+
+pub fn show_book(book: &Book) -> String {
+  let mut out = String::new();
+  for def in &book.defs {
+    show_token(&mut out, &def.at);
+    show_token(&mut out, &def.nam);
+    show_token(&mut out, &def.eq);
+    out.push_str(&show_net(&def.net));
+  }
+  out.push_str(&book.end);
+  return out;
+}
+
+// Conversion
+// ----------
+
+fn decimal_to_ast(token: &Token) -> u64 {
+  ast::parse_decimal(&mut Cursor::new(&token.text)).unwrap()
+}
+
+fn number_to_ast(token: &Token) -> (run::Lab, run::Val) {
+  ast::parse_number(&mut Cursor::new(&token.text)).unwrap()
+}
+
+fn opr_to_ast(token: &Token) -> run::Lab {
+  ast::parse_opr(&mut Cursor::new(&token.text)).unwrap()
+}
+
+// Converts to an AST tree, desugaring literals in the given encoding, as 'ast::parse_tree' does.
+pub fn tree_to_ast(tree: &Tree, encoding: ast::Encoding, fresh: &mut usize) -> ast::Tree {
+  match tree {
+    Tree::Era { .. } => {
+      ast::Tree::Era
+    }
+    Tree::Con { lft, rgt, .. } => {
+      ast::Tree::Con { lft: Box::new(tree_to_ast(lft, encoding, fresh)), rgt: Box::new(tree_to_ast(rgt, encoding, fresh)) }
+    }
+    Tree::Tup { lft, rgt, .. } => {
+      ast::Tree::Tup { lft: Box::new(tree_to_ast(lft, encoding, fresh)), rgt: Box::new(tree_to_ast(rgt, encoding, fresh)) }
+    }
+    Tree::Dup { lab, lft, rgt, .. } => {
+      ast::Tree::Dup { lab: decimal_to_ast(lab) as run::Lab, lft: Box::new(tree_to_ast(lft, encoding, fresh)), rgt: Box::new(tree_to_ast(rgt, encoding, fresh)) }
+    }
+    Tree::Var { nam } => {
+      ast::Tree::Var { nam: nam.text.clone() }
+    }
+    Tree::Ref { nam, .. } => {
+      ast::Tree::Ref { nam: ast::name_to_val(&nam.text) }
+    }
+    Tree::Num { val, .. } => {
+      let (typ, val) = number_to_ast(val);
+      ast::Tree::Num { typ, val }
+    }
+    Tree::Op1 { lft, opr, rgt, .. } => {
+      ast::Tree::Op1 { opr: opr_to_ast(opr), lft: number_to_ast(lft).1, rgt: Box::new(tree_to_ast(rgt, encoding, fresh)) }
+    }
+    Tree::Op2 { opr, lft, rgt, .. } => {
+      ast::Tree::Op2 { opr: opr_to_ast(opr), lft: Box::new(tree_to_ast(lft, encoding, fresh)), rgt: Box::new(tree_to_ast(rgt, encoding, fresh)) }
+    }
+    Tree::Mat { sel, ret, .. } => {
+      ast::Tree::Mat { sel: Box::new(tree_to_ast(sel, encoding, fresh)), ret: Box::new(tree_to_ast(ret, encoding, fresh)) }
+    }
+    Tree::Str { lit } => {
+      let txt = ast::parse_string(&mut Cursor::new(&lit.text)).unwrap();
+      ast::desugar(ast::string_to_list(&txt), encoding, fresh)
+    }
+    Tree::Lst { els, .. } => {
+      let els = els.iter().map(|el| tree_to_ast(el, encoding, fresh)).collect();
+      ast::desugar(els, encoding, fresh)
+    }
+  }
+}
+
+pub fn net_to_ast(net: &Net, encoding: ast::Encoding, fresh: &mut usize) -> ast::Net {
+  let root = tree_to_ast(&net.root, encoding, fresh);
+  let rdex = net.rdex.iter().map(|redex| (tree_to_ast(&redex.lft, encoding, fresh), tree_to_ast(&redex.rgt, encoding, fresh))).collect();
+  ast::Net { root, rdex }
+}
+
+// Converts to an AST book. As in 'ast::parse_book', later definitions replace earlier ones.
+pub fn book_to_ast(book: &Book, encoding: ast::Encoding) -> ast::Book {
+  let mut fresh = 0;
+  book.defs.iter().map(|def| (def.nam.text.clone(), net_to_ast(&def.net, encoding, &mut fresh))).collect()
+}
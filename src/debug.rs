@@ -0,0 +1,327 @@
+// Step debugger
+// -------------
+// This file lets a net be reduced one step at a time, as 'hvmc debug' does. A step is either an
+// interaction over a pending redex, or the expansion of a head REF, once there are no redexes left.
+// By default, steps follow the order of 'Net::normal': the last redex first, then the head REFs,
+// depth first. Any pending redex can be fired instead, by its index.
+//
+// Breakpoints stop 'continue' before a step that dereferences a given definition, in 'Net::call',
+// or before a step of a given kind (ANNI/COMM/ERAS/DREF/OPER, as counted by the rewrite stats).
+// The debugger is driven by text commands, so that the CLI only has to read lines and print the
+// answers; 'help' lists the commands.
+
+use crate::ast;
+use crate::run::*;
+use crate::trace::Rule;
+
+// The kind of a step, as counted on 'Rewrites'.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Kind {
+  Anni,
+  Comm,
+  Eras,
+  Dref,
+  Oper,
+}
+
+// A condition that stops 'continue'.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub enum Break {
+  Def(String), // a step dereferences the definition of this name
+  Kind(Kind), // a step is of this kind
+}
+
+// A step that can be taken.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Next {
+  Redex(usize), // fires the redex at this index of 'rdex'
+  Head { ptr: Ptr, dir: Ptr }, // expands the head REF 'ptr', held by the port 'dir'
+}
+
+// A net being debugged.
+pub struct Debugger<'a> {
+  pub net: Net<'a>,
+  pub book: &'a Book,
+  pub breaks: Vec<Break>, // active breakpoints
+  pub steps: usize, // steps taken so far
+  pub failed: Option<RuntimeError>, // the error that stopped the reduction, if any
+  pub retry: Option<usize>, // rewrites, as of the last retry of the waiting IO calls
+}
+
+pub const HELP: &str = "\
+Commands:
+  step [n]      (s) Take the next step (or n steps)
+  fire <i>      (f) Fire the redex with index i
+  continue      (c) Take steps until a breakpoint or the normal form
+  redexes       (r) List the pending redexes; the next one is listed first
+  net           (n) Show the current net
+  heap <loc>    (h) Show the ports of the node at loc (in hex, as shown in pointers)
+  break <b>     (b) Stop before dereferencing a definition (@name), or before a step of a kind
+                    (anni, comm, eras, dref, oper)
+  delete <i>    (d) Delete a breakpoint
+  breaks            List the breakpoints
+  stats             Show the rewrite counts
+  help              Show this message
+  quit          (q) Exit";
+
+impl Kind {
+  pub fn of(rule: Rule) -> Option<Kind> {
+    match rule {
+      Rule::Anni                            => Some(Kind::Anni),
+      Rule::Comm | Rule::Copy | Rule::Pass  => Some(Kind::Comm),
+      Rule::Era2 | Rule::Era1 | Rule::Void  => Some(Kind::Eras),
+      Rule::Call | Rule::Expand             => Some(Kind::Dref),
+      Rule::Op2n | Rule::Op1n | Rule::Mtch  => Some(Kind::Oper),
+      Rule::Invalid                         => None,
+    }
+  }
+
+  pub fn parse(name: &str) -> Option<Kind> {
+    match name.to_lowercase().as_str() {
+      "anni" => Some(Kind::Anni),
+      "comm" => Some(Kind::Comm),
+      "eras" => Some(Kind::Eras),
+      "dref" => Some(Kind::Dref),
+      "oper" => Some(Kind::Oper),
+      _      => None,
+    }
+  }
+}
+
+impl std::fmt::Display for Kind {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    f.write_str(&format!("{:?}", self).to_uppercase())
+  }
+}
+
+impl std::fmt::Display for Break {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      Break::Def(name) => write!(f, "@{}", name),
+      Break::Kind(kind) => write!(f, "{}", kind),
+    }
+  }
+}
+
+impl<'a> Debugger<'a> {
+  pub fn new(net: Net<'a>, book: &'a Book) -> Self {
+    Debugger { net, book, breaks: vec![], steps: 0, failed: None, retry: None }
+  }
+
+  // Runs a command, returning its output.
+  pub fn exec(&mut self, line: &str) -> String {
+    let words = line.split_whitespace().collect::<Vec<_>>();
+    let arg = words.get(1).copied();
+    match words.first().copied().unwrap_or("") {
+      "step" | "s" => {
+        let Some(count) = arg.map_or(Some(1), |arg| arg.parse::<usize>().ok()) else {
+          return format!("Invalid step count: {}", arg.unwrap_or(""));
+        };
+        let mut out = vec![];
+        for _ in 0 .. count {
+          let Some(next) = self.next() else { break };
+          out.push(self.fire(next));
+          if self.failed.is_some() {
+            break;
+          }
+        }
+        if out.is_empty() {
+          return self.halted();
+        }
+        return out.join("\n");
+      }
+      "fire" | "f" => {
+        match arg.and_then(|arg| arg.parse::<usize>().ok()) {
+          Some(i) if i < self.net.rdex.len() => self.fire(Next::Redex(self.net.rdex.len() - 1 - i)),
+          _ => format!("No redex with index {} (see 'redexes')", arg.unwrap_or("")),
+        }
+      }
+      "continue" | "c" => {
+        let mut last = None;
+        while let Some(next) = self.next() {
+          // Always takes a step, so that continuing from a breakpoint moves past it.
+          if let (Some(_), Some(hit)) = (&last, self.hit(next)) {
+            return format!("{}\nBreakpoint {}: {}, before step {}", last.unwrap_or_default(), hit, self.breaks[hit], self.steps + 1);
+          }
+          last = Some(self.fire(next));
+          if self.failed.is_some() {
+            return last.unwrap_or_default();
+          }
+        }
+        return format!("{}{}", last.map_or(String::new(), |last| format!("{}\n", last)), self.halted());
+      }
+      "redexes" | "r" => {
+        if self.net.rdex.is_empty() {
+          return "No pending redexes".to_string();
+        }
+        let rdex = self.net.rdex.iter().rev().enumerate();
+        return rdex.map(|(i, (a, b))| format!("{}: {} {} ~ {}", i, Rule::of(*a, *b), show_ptr(*a, &self.book.syms), show_ptr(*b, &self.book.syms))).collect::<Vec<_>>().join("\n");
+      }
+      "net" | "n" => {
+        return ast::show_runtime_net(&self.net, &self.book.syms);
+      }
+      "heap" | "h" => {
+        match arg.and_then(parse_loc) {
+          Some(loc) if (loc as usize) < self.net.heap.data.len() => {
+            let p1 = self.net.heap.get(loc, P1);
+            let p2 = self.net.heap.get(loc, P2);
+            format!("{:08x}: {} {}", loc, show_ptr(p1, &self.book.syms), show_ptr(p2, &self.book.syms))
+          }
+          _ => format!("No heap location {}", arg.unwrap_or("")),
+        }
+      }
+      "break" | "b" => {
+        let brk = match arg {
+          Some(name) if name.starts_with('@') => Break::Def(name[1 ..].to_string()),
+          Some(name) => match Kind::parse(name) {
+            Some(kind) => Break::Kind(kind),
+            None => return format!("Invalid breakpoint: {} (expected @name, anni, comm, eras, dref or oper)", name),
+          },
+          None => return "Usage: break <@name | anni | comm | eras | dref | oper>".to_string(),
+        };
+        self.breaks.push(brk);
+        return format!("Breakpoint {}: {}", self.breaks.len() - 1, self.breaks[self.breaks.len() - 1]);
+      }
+      "delete" | "d" => {
+        match arg.and_then(|arg| arg.parse::<usize>().ok()) {
+          Some(i) if i < self.breaks.len() => format!("Deleted breakpoint {}: {}", i, self.breaks.remove(i)),
+          _ => format!("No breakpoint with index {}", arg.unwrap_or("")),
+        }
+      }
+      "breaks" => {
+        if self.breaks.is_empty() {
+          return "No breakpoints".to_string();
+        }
+        return self.breaks.iter().enumerate().map(|(i, brk)| format!("{}: {}", i, brk)).collect::<Vec<_>>().join("\n");
+      }
+      "stats" => {
+        let rwts = &self.net.rwts;
+        return format!("RWTS: {} (ANNI {}, COMM {}, ERAS {}, DREF {}, OPER {}) in {} steps", self.net.rewrites(), rwts.anni, rwts.comm, rwts.eras, rwts.dref, rwts.oper, self.steps);
+      }
+      "help" => {
+        return HELP.to_string();
+      }
+      "" => {
+        return String::new();
+      }
+      cmd => {
+        return format!("Unknown command: {} (see 'help')", cmd);
+      }
+    }
+  }
+
+  // The step 'step' would take, if any.
+  pub fn next(&self) -> Option<Next> {
+    if self.failed.is_some() {
+      return None;
+    }
+    if !self.net.rdex.is_empty() {
+      return Some(Next::Redex(self.net.rdex.len() - 1));
+    }
+    return self.head(ROOT);
+  }
+
+  // Finds the first head REF under a port, as 'Net::expand' does.
+  fn head(&self, dir: Ptr) -> Option<Next> {
+    let ptr = self.net.get_target(dir);
+    if ptr.is_ctr() {
+      return self.head(Ptr::new(VR1, 0, ptr.loc())).or_else(|| self.head(Ptr::new(VR2, 0, ptr.loc())));
+    } else if ptr.is_ref() {
+      return Some(Next::Head { ptr, dir });
+    }
+    return None;
+  }
+
+  // The index of the first breakpoint that stops before a step, if any.
+  pub fn hit(&self, next: Next) -> Option<usize> {
+    let (rule, a, b) = self.describe(next);
+    let def = match rule {
+      Rule::Call => Some(if a.is_ref() { a.val() } else { b.val() }),
+      Rule::Expand => Some(a.val()),
+      _ => None,
+    };
+    return self.breaks.iter().position(|brk| match brk {
+      Break::Def(name) => def.and_then(|def| self.book.syms.name(def)) == Some(name.as_str()),
+      Break::Kind(kind) => Kind::of(rule) == Some(*kind),
+    });
+  }
+
+  fn describe(&self, next: Next) -> (Rule, Ptr, Ptr) {
+    match next {
+      Next::Redex(i) => {
+        let (a, b) = self.net.rdex[i];
+        (Rule::of(a, b), a, b)
+      }
+      Next::Head { ptr, dir } => (Rule::Expand, ptr, dir),
+    }
+  }
+
+  // Takes a step, returning its description.
+  pub fn fire(&mut self, next: Next) -> String {
+    let (rule, a, b) = self.describe(next);
+    let shown = match next {
+      Next::Redex(_) => format!("{} ~ {}", ast::show_runtime_tree(&self.net, &self.book.syms, a), ast::show_runtime_tree(&self.net, &self.book.syms, b)),
+      Next::Head { .. } => format!("{} at {}", ast::show_runtime_tree(&self.net, &self.book.syms, a), show_ptr(b, &self.book.syms)),
+    };
+    let mut done = match next {
+      Next::Redex(i) => {
+        self.net.rdex.remove(i);
+        self.net.interact(self.book, a, b)
+      }
+      Next::Head { ptr, dir } => {
+        self.net.swap_target(dir, LOCK);
+        self.net.call(self.book, ptr, dir)
+      }
+    };
+    // Retries the waiting IO calls once nothing else is left, as 'normal' does.
+    if done.is_ok() && self.net.rdex.is_empty() && self.head(ROOT).is_none() && !self.net.wait.is_empty() {
+      done = self.net.retry_io(self.net.rewrites(), &mut self.retry);
+    }
+    self.steps += 1;
+    let out = format!("[{}] {}: {}", self.steps, rule, shown);
+    if let Err(err) = done {
+      let err = self.net.read_back(self.book, err);
+      let out = format!("{}\nReduction failed: {}", out, err);
+      self.failed = Some(err);
+      return out;
+    }
+    return out;
+  }
+
+  fn halted(&self) -> String {
+    match &self.failed {
+      Some(err) => format!("Reduction failed: {}", err),
+      None      => format!("Normal form reached after {} steps", self.steps),
+    }
+  }
+}
+
+// Shows a pointer compactly: unboxed ones as in the syntax, others as 'TAG:label@location'.
+pub fn show_ptr(ptr: Ptr, syms: &ast::Symbols) -> String {
+  let name = match ptr.tag() {
+    _ if ptr == NULL => return "NULL".to_string(),
+    _ if ptr == LOCK => return "LOCK".to_string(),
+    ERA => return ast::show_tree(&ast::Tree::Era),
+    REF => return format!("@{}", syms.show(ptr.val())),
+    NUM | INT | FLT => return ast::show_tree(&ast::Tree::Num { typ: ptr.num_type(), val: ptr.val() }),
+    VR1 => "VR1",
+    VR2 => "VR2",
+    RD1 => "RD1",
+    RD2 => "RD2",
+    OP2 => "OP2",
+    OP1 => "OP1",
+    MAT => "MAT",
+    LAM => "LAM",
+    TUP => "TUP",
+    DUP => "DUP",
+    _   => "CTR",
+  };
+  return format!("{}:{}@{:08x}", name, ptr.lab(), ptr.loc());
+}
+
+fn parse_loc(text: &str) -> Option<Loc> {
+  match text.strip_prefix("0x") {
+    Some(hex) => Loc::from_str_radix(hex, 16).ok(),
+    None      => Loc::from_str_radix(text, 16).ok(),
+  }
+}
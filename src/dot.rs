@@ -0,0 +1,99 @@
+// Graphviz export
+// ---------------
+// This file renders nets as DOT graphs. Each node is drawn with its principal port on top and its
+// auxiliary ports on the bottom, so that edges entering a node from above are principal. Wires are
+// undirected. Active pairs are drawn as highlighted edges meeting at a red point.
+
+use crate::ast::{net_from_runtime, show_lit, show_opr, show_tree, val_to_name, Net, Symbols, Tree};
+use crate::run;
+use std::collections::HashMap;
+
+// Edge attributes of active pairs.
+const ACTIVE: &str = " [color=red, penwidth=2]";
+
+// Node shape and label of a non-variable tree.
+fn node_style(tree: &Tree) -> (&'static str, String) {
+  match tree {
+    Tree::Era                  => ("circle", "*".to_string()),
+    Tree::Con { .. }           => ("triangle", "CON".to_string()),
+    Tree::Tup { .. }           => ("trapezium", "TUP".to_string()),
+    Tree::Dup { lab, .. }      => ("house", format!("DUP {}", lab)),
+    Tree::Ref { nam }          => ("box", format!("@{}", val_to_name(*nam))),
+    Tree::Num { .. }           => ("ellipse", show_tree(tree)),
+    Tree::Op1 { opr, lft, .. } => ("diamond", format!("{}{}", show_lit(*opr, *lft), show_opr(*opr))),
+    Tree::Op2 { opr, .. }      => ("Mdiamond", show_opr(*opr)),
+    Tree::Mat { .. }           => ("pentagon", "?".to_string()),
+    Tree::Var { .. }           => unreachable!(),
+  }
+}
+
+// Escapes a label for a quoted DOT string.
+fn escape(label: &str) -> String {
+  label.replace('\\', "\\\\").replace('"', "\\\"")
+}
+
+struct Dot {
+  code: String, // generated statements
+  size: usize, // nodes emitted so far
+  vars: HashMap<String, (String, &'static str)>, // first endpoint of each variable, with its edge attributes
+}
+
+impl Dot {
+  // Draws a tree, wiring its principal port to 'slot' with the given edge attributes.
+  fn tree(&mut self, tree: &Tree, slot: String, attr: &'static str) {
+    if let Tree::Var { nam } = tree {
+      match self.vars.remove(nam) {
+        Some((other, other_attr)) => self.code.push_str(&format!("  {} -- {}{};\n", other, slot, if attr.is_empty() { other_attr } else { attr })),
+        None                      => { self.vars.insert(nam.clone(), (slot, attr)); }
+      }
+      return;
+    }
+    let name = format!("n{}", self.size);
+    self.size += 1;
+    let (shape, label) = node_style(tree);
+    self.code.push_str(&format!("  {} [shape={}, label=\"{}\"];\n", name, shape, escape(&label)));
+    self.code.push_str(&format!("  {} -- {}:n{};\n", slot, name, attr));
+    match tree {
+      Tree::Con { lft, rgt } | Tree::Tup { lft, rgt } | Tree::Dup { lft, rgt, .. } | Tree::Op2 { lft, rgt, .. } => {
+        self.tree(lft, format!("{}:sw", name), "");
+        self.tree(rgt, format!("{}:se", name), "");
+      }
+      Tree::Op1 { rgt, .. } => {
+        self.tree(rgt, format!("{}:s", name), "");
+      }
+      Tree::Mat { sel, ret } => {
+        self.tree(sel, format!("{}:sw", name), "");
+        self.tree(ret, format!("{}:se", name), "");
+      }
+      _ => {}
+    }
+  }
+}
+
+pub fn net_to_dot(net: &Net) -> String {
+  let mut dot = Dot { code: String::new(), size: 0, vars: HashMap::new() };
+  dot.code.push_str("graph net {\n");
+  dot.code.push_str("  node [fontname=\"monospace\"];\n");
+  dot.code.push_str("  root [shape=plaintext, label=\"root\"];\n");
+  dot.tree(&net.root, "root".to_string(), "");
+  for (i, (a, b)) in net.rdex.iter().enumerate() {
+    let pair = format!("r{}", i);
+    dot.code.push_str(&format!("  {} [shape=point, color=red];\n", pair));
+    dot.tree(a, pair.clone(), ACTIVE);
+    dot.tree(b, pair.clone(), ACTIVE);
+  }
+  // Free variables only have one endpoint, so they're drawn as dangling wires.
+  let mut free = dot.vars.drain().collect::<Vec<_>>();
+  free.sort();
+  for (nam, (slot, _)) in free {
+    let name = format!("\"free {}\"", escape(&nam));
+    dot.code.push_str(&format!("  {} [shape=plaintext, label=\"{}\"];\n", name, escape(&nam)));
+    dot.code.push_str(&format!("  {} -- {} [style=dashed];\n", slot, name));
+  }
+  dot.code.push_str("}\n");
+  return dot.code;
+}
+
+pub fn runtime_net_to_dot(rt_net: &run::Net, syms: &Symbols) -> String {
+  net_to_dot(&net_from_runtime(rt_net, syms))
+}
@@ -0,0 +1,102 @@
+// Implements f60: 60-bit floats, stored as f64 without the 4 lowest bits of its mantissa
+
+type F60 = u64;
+
+// Rounds to the nearest f60. NaNs are kept as NaNs, since rounding could turn them into infinities.
+#[inline(always)]
+pub fn new(a: f64) -> F60 {
+  if a.is_nan() {
+    return f64::NAN.to_bits() >> 4;
+  }
+  return (a.to_bits() + 0x8) >> 4;
+}
+
+#[inline(always)]
+pub fn val(a: F60) -> f64 {
+  return f64::from_bits(a << 4);
+}
+
+#[inline(always)]
+pub fn add(a: F60, b: F60) -> F60 {
+  return new(val(a) + val(b));
+}
+
+#[inline(always)]
+pub fn sub(a: F60, b: F60) -> F60 {
+  return new(val(a) - val(b));
+}
+
+#[inline(always)]
+pub fn mul(a: F60, b: F60) -> F60 {
+  return new(val(a) * val(b));
+}
+
+#[inline(always)]
+pub fn div(a: F60, b: F60) -> F60 {
+  return new(val(a) / val(b));
+}
+
+#[inline(always)]
+pub fn rem(a: F60, b: F60) -> F60 {
+  return new(val(a) % val(b));
+}
+
+#[inline(always)]
+pub fn lt(a: F60, b: F60) -> u64 {
+  return if val(a) < val(b) { 1 } else { 0 };
+}
+
+#[inline(always)]
+pub fn gt(a: F60, b: F60) -> u64 {
+  return if val(a) > val(b) { 1 } else { 0 };
+}
+
+#[inline(always)]
+pub fn lte(a: F60, b: F60) -> u64 {
+  return if val(a) <= val(b) { 1 } else { 0 };
+}
+
+#[inline(always)]
+pub fn gte(a: F60, b: F60) -> u64 {
+  return if val(a) >= val(b) { 1 } else { 0 };
+}
+
+#[inline(always)]
+pub fn eq(a: F60, b: F60) -> u64 {
+  return if val(a) == val(b) { 1 } else { 0 };
+}
+
+#[inline(always)]
+pub fn ne(a: F60, b: F60) -> u64 {
+  return if val(a) != val(b) { 1 } else { 0 };
+}
+
+#[inline(always)]
+pub fn min(a: F60, b: F60) -> F60 {
+  return new(val(a).min(val(b)));
+}
+
+#[inline(always)]
+pub fn max(a: F60, b: F60) -> F60 {
+  return new(val(a).max(val(b)));
+}
+
+#[inline(always)]
+pub fn pow(a: F60, b: F60) -> F60 {
+  return new(val(a).powf(val(b)));
+}
+
+// Shows the shortest decimal that rounds back to the same f60, always with a fractional part.
+pub fn show(a: F60) -> String {
+  let x = val(a);
+  if !x.is_finite() {
+    return format!("{}", x);
+  }
+  for prec in 1 .. 1100 {
+    let txt = format!("{:.*}", prec, x);
+    if new(txt.parse::<f64>().unwrap()) == a {
+      return txt;
+    }
+  }
+  return format!("{:?}", x);
+}
@@ -0,0 +1,301 @@
+// Formatter
+// ---------
+// This file pretty-prints '.hvmc' sources in a canonical layout. Definitions keep their source
+// order. Definitions without redexes go on a single line, and definitions with redexes put the
+// root and each redex on its own indented line. Trees wider than the configured width are broken
+// across lines, one subtree per line. Comments are preserved: comments on their own lines stay
+// before the definition or redex that follows them, comments at the end of a line stay there, and
+// comments inside a tree are moved before the definition or redex that contains them. Blank lines
+// between definitions are kept, collapsed to one. Trees are parsed to the concrete syntax tree, so
+// that literals keep the type and sugar they were written with.
+
+use crate::ast::{consume, num_to_str, parse_decimal, parse_name, parse_number, parse_opr, parse_string, show_lit, show_opr, show_string, Cursor, ParseError};
+use crate::cst::{self, Token, Tree};
+use crate::run;
+use std::collections::HashMap;
+
+// Formatter options.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct FmtConfig {
+  pub width: usize, // max line width, when trees can be broken
+  pub rename: bool, // whether to rename variables in first-occurrence order
+}
+
+impl Default for FmtConfig {
+  fn default() -> Self {
+    FmtConfig { width: 100, rename: false }
+  }
+}
+
+// A line of source that isn't code.
+#[derive(Clone, Debug, PartialEq, Eq)]
+enum Trivia {
+  Comment(String), // a '//' comment, including the slashes
+  Blank, // one or more empty lines
+}
+
+// A redex, with its surrounding comments.
+struct Redex {
+  before: Vec<Trivia>, // comments above it
+  after: Option<String>, // comment at the end of its line
+  pair: (Tree, Tree),
+}
+
+// A definition, with its surrounding comments.
+struct Def {
+  before: Vec<Trivia>, // comments above it
+  after: Option<String>, // comment at the end of its root line
+  name: String,
+  root: Tree,
+  rdex: Vec<Redex>,
+}
+
+// Formats a book source, returning it unchanged up to layout, comments included.
+pub fn format_book(code: &str, config: &FmtConfig) -> Result<String, ParseError> {
+  let chars = &mut Cursor::new(code);
+  let mut defs = vec![];
+  let mut before = skip_trivia(chars);
+  while chars.peek() == Some('@') {
+    let init = chars.offset;
+    chars.next();
+    let name = parse_name(chars)?;
+    consume(chars, "=")?;
+    let root = cst::parse_tree(chars)?;
+    before.extend(inner_comments(&code[init .. chars.offset]));
+    let after = end_comment(chars);
+    let mut rdex = vec![];
+    let mut next = skip_trivia(chars);
+    while chars.peek() == Some('&') {
+      let init = chars.offset;
+      chars.next();
+      let a = cst::parse_tree(chars)?;
+      consume(chars, "~")?;
+      let b = cst::parse_tree(chars)?;
+      next.extend(inner_comments(&code[init .. chars.offset]));
+      let after = end_comment(chars);
+      rdex.push(Redex { before: next, after, pair: (a, b) });
+      next = skip_trivia(chars);
+    }
+    defs.push(Def { before, after, name, root, rdex });
+    before = next;
+  }
+  if chars.peek().is_some() {
+    return Err(chars.error("end of input"));
+  }
+
+  let mut out = String::new();
+  for mut def in defs {
+    if config.rename {
+      rename(&mut def);
+    }
+    show_trivia(&mut out, &def.before, "");
+    if def.rdex.is_empty() {
+      let head = format!("@{} = ", def.name);
+      out.push_str(&head);
+      out.push_str(&fmt_tree(&def.root, head.len(), 0, config.width));
+      show_end(&mut out, &def.after);
+    } else {
+      out.push_str(&format!("@{}\n  = ", def.name));
+      out.push_str(&fmt_tree(&def.root, 4, 2, config.width));
+      show_end(&mut out, &def.after);
+      for redex in &def.rdex {
+        show_trivia(&mut out, &redex.before, "  ");
+        out.push_str("  & ");
+        let a = fmt_tree(&redex.pair.0, 4, 2, config.width);
+        let col = match a.rfind('\n') {
+          Some(i) => a.len() - i - 1,
+          None    => 4 + a.len(),
+        };
+        out.push_str(&a);
+        out.push_str(" ~ ");
+        out.push_str(&fmt_tree(&redex.pair.1, col + 3, 2, config.width));
+        show_end(&mut out, &redex.after);
+      }
+    }
+  }
+  show_trivia(&mut out, &before, "");
+  while out.ends_with("\n\n") {
+    out.pop();
+  }
+  return Ok(out);
+}
+
+// Formats a tree starting at column 'col', on a line indented by 'indent'.
+fn fmt_tree(tree: &Tree, col: usize, indent: usize, width: usize) -> String {
+  let flat = show_flat(tree);
+  if col + flat.len() <= width {
+    return flat;
+  }
+  // Lists put one element per line, each followed by a comma but the last.
+  if let Tree::Lst { els, .. } = tree {
+    let els = els.iter().map(|el| format!("\n{}{}", " ".repeat(indent + 2), fmt_tree(el, indent + 2, indent + 2, width)));
+    return format!("[{}\n{}]", els.collect::<Vec<_>>().join(","), " ".repeat(indent));
+  }
+  let (open, kids, close) = match tree {
+    Tree::Con { lft, rgt, .. }      => ("(".to_string(), vec![lft, rgt], ")"),
+    Tree::Tup { lft, rgt, .. }      => ("[".to_string(), vec![lft, rgt], "]"),
+    Tree::Dup { lab, lft, rgt, .. } => (format!("{{{}", show_label(lab)), vec![lft, rgt], "}"),
+    Tree::Op1 { lft, opr, rgt, .. } => (format!("<{}{}", show_number(lft), show_operator(opr)), vec![rgt], ">"),
+    Tree::Op2 { opr, lft, rgt, .. } => (format!("<{}", show_operator(opr)), vec![lft, rgt], ">"),
+    Tree::Mat { sel, ret, .. }      => ("?<".to_string(), vec![sel, ret], ">"),
+    _                               => return flat,
+  };
+  let mut code = open;
+  for kid in kids {
+    code.push('\n');
+    code.push_str(&" ".repeat(indent + 2));
+    code.push_str(&fmt_tree(kid, indent + 2, indent + 2, width));
+  }
+  code.push('\n');
+  code.push_str(&" ".repeat(indent));
+  code.push_str(close);
+  return code;
+}
+
+// Shows a tree on a single line, like 'ast::show_tree' does.
+fn show_flat(tree: &Tree) -> String {
+  match tree {
+    Tree::Era { .. }                => "*".to_string(),
+    Tree::Con { lft, rgt, .. }      => format!("({} {})", show_flat(lft), show_flat(rgt)),
+    Tree::Tup { lft, rgt, .. }      => format!("[{} {}]", show_flat(lft), show_flat(rgt)),
+    Tree::Dup { lab, lft, rgt, .. } => format!("{{{} {} {}}}", show_label(lab), show_flat(lft), show_flat(rgt)),
+    Tree::Var { nam }               => nam.text.clone(),
+    Tree::Ref { nam, .. }           => format!("@{}", nam.text),
+    Tree::Num { val, .. }           => format!("#{}", show_number(val)),
+    Tree::Op1 { lft, opr, rgt, .. } => format!("<{}{} {}>", show_number(lft), show_operator(opr), show_flat(rgt)),
+    Tree::Op2 { opr, lft, rgt, .. } => format!("<{} {} {}>", show_operator(opr), show_flat(lft), show_flat(rgt)),
+    Tree::Mat { sel, ret, .. }      => format!("?<{} {}>", show_flat(sel), show_flat(ret)),
+    Tree::Str { lit }               => show_string(&parse_string(&mut Cursor::new(&lit.text)).unwrap()),
+    Tree::Lst { els, .. }           => format!("[{}]", els.iter().map(show_flat).collect::<Vec<_>>().join(", ")),
+  }
+}
+
+fn show_label(token: &Token) -> String {
+  (parse_decimal(&mut Cursor::new(&token.text)).unwrap() as run::Lab).to_string()
+}
+
+// Shows a number in the numeric type it was written in.
+fn show_number(token: &Token) -> String {
+  let (typ, val) = parse_number(&mut Cursor::new(&token.text)).unwrap();
+  show_lit(typ, val)
+}
+
+fn show_operator(token: &Token) -> String {
+  show_opr(parse_opr(&mut Cursor::new(&token.text)).unwrap())
+}
+
+// Renames the variables of a definition to 'a', 'b', ..., in first-occurrence order.
+fn rename(def: &mut Def) {
+  let mut vars = HashMap::new();
+  rename_vars(&mut def.root, &mut vars);
+  for redex in &mut def.rdex {
+    rename_vars(&mut redex.pair.0, &mut vars);
+    rename_vars(&mut redex.pair.1, &mut vars);
+  }
+}
+
+fn rename_vars(tree: &mut Tree, vars: &mut HashMap<String, String>) {
+  match tree {
+    Tree::Var { nam } => {
+      let len = vars.len();
+      nam.text = vars.entry(nam.text.clone()).or_insert_with(|| num_to_str(len)).clone();
+    }
+    Tree::Con { lft, rgt, .. } | Tree::Tup { lft, rgt, .. } | Tree::Dup { lft, rgt, .. } | Tree::Op2 { lft, rgt, .. } => {
+      rename_vars(lft, vars);
+      rename_vars(rgt, vars);
+    }
+    Tree::Op1 { rgt, .. } => {
+      rename_vars(rgt, vars);
+    }
+    Tree::Mat { sel, ret, .. } => {
+      rename_vars(sel, vars);
+      rename_vars(ret, vars);
+    }
+    Tree::Lst { els, .. } => {
+      for el in els {
+        rename_vars(el, vars);
+      }
+    }
+    Tree::Era { .. } | Tree::Ref { .. } | Tree::Num { .. } | Tree::Str { .. } => {}
+  }
+}
+
+// Skips whitespace and comments, collecting the comments and blank lines.
+fn skip_trivia(chars: &mut Cursor) -> Vec<Trivia> {
+  let mut trivia = vec![];
+  let mut newlines = 0;
+  while let Some(c) = chars.peek() {
+    if c == '/' {
+      if newlines > 1 {
+        trivia.push(Trivia::Blank);
+      }
+      trivia.push(Trivia::Comment(read_comment(chars)));
+      newlines = 0;
+    } else if c.is_ascii_whitespace() {
+      if c == '\n' {
+        newlines += 1;
+      }
+      chars.next();
+    } else {
+      break;
+    }
+  }
+  if newlines > 1 {
+    trivia.push(Trivia::Blank);
+  }
+  return trivia;
+}
+
+// Reads a comment that starts on the current line, if any.
+fn end_comment(chars: &mut Cursor) -> Option<String> {
+  let init = chars.offset;
+  while chars.peek().map_or(false, |c| c == ' ' || c == '\t') {
+    chars.next();
+  }
+  if chars.peek() == Some('/') {
+    return Some(read_comment(chars));
+  }
+  chars.offset = init;
+  return None;
+}
+
+fn read_comment(chars: &mut Cursor) -> String {
+  let init = chars.offset;
+  while chars.peek().map_or(false, |c| c != '\n') {
+    chars.next();
+  }
+  chars.code[init .. chars.offset].trim_end().to_string()
+}
+
+// Collects the comments inside a parsed span. Outside of string literals, '/' always starts one.
+fn inner_comments(code: &str) -> Vec<Trivia> {
+  let mut trivia = vec![];
+  let chars = &mut Cursor::new(code);
+  while let Some(c) = chars.peek() {
+    if c == '"' {
+      let _ = parse_string(chars);
+    } else if c == '/' {
+      trivia.push(Trivia::Comment(read_comment(chars)));
+    } else {
+      chars.next();
+    }
+  }
+  return trivia;
+}
+
+fn show_trivia(out: &mut String, trivia: &[Trivia], indent: &str) {
+  for line in trivia {
+    match line {
+      Trivia::Comment(text) => out.push_str(&format!("{}{}\n", indent, text)),
+      Trivia::Blank         => if !out.is_empty() { out.push('\n') },
+    }
+  }
+}
+
+fn show_end(out: &mut String, comment: &Option<String>) {
+  if let Some(text) = comment {
+    out.push(' ');
+    out.push_str(text);
+  }
+  out.push('\n');
+}
@@ -0,0 +1,130 @@
+// Implements i60: 60-bit signed integers, in two's complement, using u64 and i64
+
+type I60 = u64;
+
+#[inline(always)]
+pub fn new(a: i64) -> I60 {
+  return (a as u64) & 0xFFF_FFFF_FFFF_FFFF;
+}
+
+#[inline(always)]
+pub fn val(a: I60) -> i64 {
+  return ((a << 4) as i64) >> 4;
+}
+
+#[inline(always)]
+pub fn add(a: I60, b: I60) -> I60 {
+  return new(val(a).wrapping_add(val(b)));
+}
+
+#[inline(always)]
+pub fn sub(a: I60, b: I60) -> I60 {
+  return new(val(a).wrapping_sub(val(b)));
+}
+
+#[inline(always)]
+pub fn mul(a: I60, b: I60) -> I60 {
+  return new(val(a).wrapping_mul(val(b)));
+}
+
+// Division by zero returns -1.
+#[inline(always)]
+pub fn div(a: I60, b: I60) -> I60 {
+  return if b == 0 { new(-1) } else { new(val(a) / val(b)) };
+}
+
+// Modulus by zero returns the dividend.
+#[inline(always)]
+pub fn rem(a: I60, b: I60) -> I60 {
+  return if b == 0 { a } else { new(val(a) % val(b)) };
+}
+
+#[inline(always)]
+pub fn and(a: I60, b: I60) -> I60 {
+  return a & b;
+}
+
+#[inline(always)]
+pub fn or(a: I60, b: I60) -> I60 {
+  return a | b;
+}
+
+#[inline(always)]
+pub fn xor(a: I60, b: I60) -> I60 {
+  return a ^ b;
+}
+
+// Shifting by 60 or more bits shifts every bit out.
+#[inline(always)]
+pub fn lsh(a: I60, b: I60) -> I60 {
+  return if b >= 60 { 0 } else { new(val(a) << b) };
+}
+
+// Arithmetic shift, keeping the sign, so shifting by 60 or more bits returns 0 or -1.
+#[inline(always)]
+pub fn rsh(a: I60, b: I60) -> I60 {
+  return new(val(a) >> std::cmp::min(b, 59));
+}
+
+#[inline(always)]
+pub fn lt(a: I60, b: I60) -> I60 {
+  return if val(a) < val(b) { 1 } else { 0 };
+}
+
+#[inline(always)]
+pub fn gt(a: I60, b: I60) -> I60 {
+  return if val(a) > val(b) { 1 } else { 0 };
+}
+
+#[inline(always)]
+pub fn lte(a: I60, b: I60) -> I60 {
+  return if val(a) <= val(b) { 1 } else { 0 };
+}
+
+#[inline(always)]
+pub fn gte(a: I60, b: I60) -> I60 {
+  return if val(a) >= val(b) { 1 } else { 0 };
+}
+
+#[inline(always)]
+pub fn eq(a: I60, b: I60) -> I60 {
+  return if a == b { 1 } else { 0 };
+}
+
+#[inline(always)]
+pub fn ne(a: I60, b: I60) -> I60 {
+  return if a != b { 1 } else { 0 };
+}
+
+#[inline(always)]
+pub fn min(a: I60, b: I60) -> I60 {
+  return if val(a) < val(b) { a } else { b };
+}
+
+#[inline(always)]
+pub fn max(a: I60, b: I60) -> I60 {
+  return if val(a) > val(b) { a } else { b };
+}
+
+// Negative exponents truncate towards zero, like division does.
+#[inline(always)]
+pub fn pow(a: I60, b: I60) -> I60 {
+  if val(b) >= 0 {
+    return crate::u60::pow(a, b);
+  }
+  return match val(a) {
+    1  => new(1),
+    -1 => new(if b & 1 == 0 { 1 } else { -1 }),
+    _  => 0,
+  };
+}
+
+#[inline(always)]
+pub fn not(a: I60) -> I60 {
+  return !a & 0xFFF_FFFF_FFFF_FFFF;
+}
+
+#[inline(always)]
+pub fn show(a: I60) -> String {
+  return format!("{}", val(a));
+}
@@ -0,0 +1,260 @@
+// Effects
+// -------
+
+// References to '@IO.print', '@IO.read_line' and '@IO.read_file' that have no definition of their
+// own are handled by the runtime, if the book has an 'IoHandler'. An IO call is applied like a
+// function, as in '@IO.print ~ (arg ret)'. It waits until its argument is data, so effects are
+// sequenced by data dependency alone: a call whose argument depends on the result of another one
+// happens after it. Calls that are independent of each other may happen in any order.
+//
+// Strings are Scott-encoded lists of character codes, as in hvm-lang and in string literals:
+// - cons: ((head (tail r)) (* r))
+// - nil : (* (r r))
+//
+// The calls are:
+// - '@IO.print': takes a string, prints it, and returns #0.
+// - '@IO.read_line': takes a number, which is ignored, and returns a line of input, without its
+//   line break. At the end of the input, it returns the empty string.
+// - '@IO.read_file': takes a path string, and returns the file contents.
+
+use crate::run::*;
+
+// Names of the IO calls.
+pub const CALLS: &[&str] = &["IO.print", "IO.read_line", "IO.read_file"];
+
+// Performs the effects of IO calls. Tests can substitute an in-memory handler.
+pub trait IoHandler: Send + Sync {
+  fn print(&self, text: &str);
+  fn read_line(&self) -> String;
+  fn read_file(&self, path: &str) -> std::io::Result<String>;
+}
+
+// Performs effects on the standard input and output, and on the file system.
+pub struct StdIo;
+
+impl IoHandler for StdIo {
+  fn print(&self, text: &str) {
+    use std::io::Write;
+    let mut out = std::io::stdout().lock();
+    let _ = out.write_all(text.as_bytes());
+    let _ = out.flush();
+  }
+
+  fn read_line(&self) -> String {
+    let mut line = String::new();
+    let _ = std::io::stdin().read_line(&mut line);
+    if line.ends_with('\n') {
+      line.pop();
+      if line.ends_with('\r') {
+        line.pop();
+      }
+    }
+    return line;
+  }
+
+  fn read_file(&self, path: &str) -> std::io::Result<String> {
+    return std::fs::read_to_string(path);
+  }
+}
+
+// An IO handler, with the ids of the calls it handles.
+pub struct Io {
+  pub handler: Box<dyn IoHandler>, // performs the effects
+  pub print: Val, // id of '@IO.print'
+  pub read_line: Val, // id of '@IO.read_line'
+  pub read_file: Val, // id of '@IO.read_file'
+}
+
+impl Io {
+  // Handles the IO calls of a book, with the ids of its symbol table.
+  pub fn new(handler: impl IoHandler + 'static, syms: &mut crate::ast::Symbols) -> Self {
+    Io {
+      handler: Box::new(handler),
+      print: syms.intern("IO.print"),
+      read_line: syms.intern("IO.read_line"),
+      read_file: syms.intern("IO.read_file"),
+    }
+  }
+
+  // Whether a reference is one of these calls.
+  pub fn calls(&self, id: Val) -> bool {
+    return [self.print, self.read_line, self.read_file].contains(&id);
+  }
+}
+
+// Why an argument can't be read yet.
+enum Wait {
+  Ref(Ptr), // it holds a reference, on this port, which must be expanded
+  Wire, // it holds a wire, which reduction elsewhere may fill
+  Bad, // it has the wrong shape
+}
+
+impl<'a> Net<'a> {
+  // Expands a reference to one of the calls of 'io', which has no definition.
+  pub fn call_io(&mut self, book: &Book, io: &Io, ptr: Ptr, trg: Ptr) -> Result<(), RuntimeError> {
+    // Like closed nets, IO calls can be copied.
+    if trg.is_dup() {
+      self.copy(trg, ptr);
+      return Ok(());
+    }
+    if trg.tag() != LAM {
+      return Err(InteractionError { a: ptr, b: trg, rule: "IO call is not applied to an argument", trees: None }.into());
+    }
+    let (text, locs) = match self.read_io_arg(io, ptr, trg) {
+      Ok(got) => got,
+      Err(Wait::Ref(dir)) => {
+        let got = self.swap_target(dir, LOCK);
+        self.call(book, got, dir)?;
+        self.postpone(ptr, trg);
+        return Ok(());
+      }
+      Err(Wait::Wire) => {
+        self.postpone(ptr, trg);
+        return Ok(());
+      }
+      Err(Wait::Bad) => {
+        let rule = if ptr.val() == io.read_line { "IO argument is not a number" } else { "IO argument is not a string" };
+        return Err(InteractionError { a: ptr, b: trg, rule, trees: None }.into());
+      }
+    };
+    // Performs the effect.
+    let ret = if ptr.val() == io.print {
+      io.handler.print(&text);
+      Ptr::big(NUM, 0)
+    } else if ptr.val() == io.read_line {
+      let line = io.handler.read_line();
+      self.make_io_str(&line)?
+    } else {
+      match io.handler.read_file(&text) {
+        Ok(data) => self.make_io_str(&data)?,
+        Err(err) => return Err(IoError { call: "IO.read_file", message: format!("{}: {}", text, err) }.into()),
+      }
+    };
+    // Frees the argument and returns.
+    for loc in locs {
+      self.heap.set(loc, P1, NULL);
+      self.heap.set(loc, P2, NULL);
+    }
+    self.heap.set(trg.loc(), P1, NULL);
+    self.half_atomic_link(Ptr::new(VR2, 0, trg.loc()), ret);
+    Ok(())
+  }
+
+  // Retries an IO call once the other redexes were reduced. Retries aren't rewrites.
+  fn postpone(&mut self, ptr: Ptr, trg: Ptr) {
+    self.rwts.dref -= 1;
+    self.wait.push((ptr, trg));
+  }
+
+  // Moves the postponed IO calls back to the redexes, once no thread has other redexes left.
+  // 'spent' counts the rewrites of every thread, and 'last' is its value on the previous retry: if
+  // nothing was reduced since, the arguments the calls wait for never become data.
+  pub fn retry_io(&mut self, spent: usize, last: &mut Option<usize>) -> Result<(), RuntimeError> {
+    if *last == Some(spent) {
+      if let Some(&(a, b)) = self.wait.first() {
+        return Err(InteractionError { a, b, rule: "IO argument never becomes data", trees: None }.into());
+      }
+    }
+    *last = Some(spent);
+    self.rdex.append(&mut self.wait);
+    return Ok(());
+  }
+
+  // Reads the argument of an IO call.
+  fn read_io_arg(&self, io: &Io, ptr: Ptr, trg: Ptr) -> Result<(String, Vec<Loc>), Wait> {
+    let arg = Ptr::new(VR1, 0, trg.loc());
+    if ptr.val() == io.read_line {
+      return self.read_io_num(arg).map(|_| (String::new(), vec![]));
+    } else {
+      return self.read_io_str(arg);
+    }
+  }
+
+  // Gets the data on a port, if it's there yet.
+  fn io_data(&self, dir: Ptr) -> Result<Ptr, Wait> {
+    let got = self.get_target(dir);
+    if got == LOCK || got == GONE || got.is_var() || got.is_red() {
+      return Err(Wait::Wire);
+    }
+    if got.is_ref() {
+      return Err(Wait::Ref(dir));
+    }
+    return Ok(got);
+  }
+
+  // Gets the node on a port, if it's a CON.
+  fn io_con(&self, dir: Ptr) -> Result<Loc, Wait> {
+    let got = self.io_data(dir)?;
+    return if got.tag() == LAM { Ok(got.loc()) } else { Err(Wait::Bad) };
+  }
+
+  // Checks that two ports are wired to each other.
+  fn io_wire(&self, a: Ptr, b: Ptr) -> Result<(), Wait> {
+    let (a_got, b_got) = (self.get_target(a), self.get_target(b));
+    if a_got == b && b_got == a {
+      return Ok(());
+    }
+    return if a_got.is_ctr() || b_got.is_ctr() || a_got.is_num() || b_got.is_num() { Err(Wait::Bad) } else { Err(Wait::Wire) };
+  }
+
+  fn read_io_num(&self, dir: Ptr) -> Result<Val, Wait> {
+    let got = self.io_data(dir)?;
+    return if got.is_num() { Ok(got.val()) } else { Err(Wait::Bad) };
+  }
+
+  // Reads a string, returning it with the locations of its nodes.
+  fn read_io_str(&self, dir: Ptr) -> Result<(String, Vec<Loc>), Wait> {
+    let mut text = String::new();
+    let mut locs = vec![];
+    let mut dir = dir;
+    loop {
+      let a = self.io_con(dir)?;
+      // nil: (* (r r))
+      if self.io_data(Ptr::new(VR1, 0, a))?.is_era() {
+        let f = self.io_con(Ptr::new(VR2, 0, a))?;
+        self.io_wire(Ptr::new(VR1, 0, f), Ptr::new(VR2, 0, f))?;
+        locs.extend([a, f]);
+        return Ok((text, locs));
+      }
+      // cons: ((head (tail r)) (* r))
+      let b = self.io_con(Ptr::new(VR1, 0, a))?;
+      let d = self.io_con(Ptr::new(VR2, 0, a))?;
+      let c = self.io_con(Ptr::new(VR2, 0, b))?;
+      let head = self.read_io_num(Ptr::new(VR1, 0, b))?;
+      if !self.io_data(Ptr::new(VR1, 0, d))?.is_era() {
+        return Err(Wait::Bad);
+      }
+      self.io_wire(Ptr::new(VR2, 0, c), Ptr::new(VR2, 0, d))?;
+      text.push(u32::try_from(head).ok().and_then(char::from_u32).unwrap_or(char::REPLACEMENT_CHARACTER));
+      locs.extend([a, b, c, d]);
+      dir = Ptr::new(VR1, 0, c);
+    }
+  }
+
+  // Allocates a string.
+  fn make_io_str(&mut self, text: &str) -> Result<Ptr, RuntimeError> {
+    let e = self.alloc()?;
+    let f = self.alloc()?;
+    self.heap.set(e, P1, ERAS);
+    self.heap.set(e, P2, Ptr::new(LAM, 0, f));
+    self.heap.set(f, P1, Ptr::new(VR2, 0, f));
+    self.heap.set(f, P2, Ptr::new(VR1, 0, f));
+    let mut list = Ptr::new(LAM, 0, e);
+    for chr in text.chars().rev() {
+      let a = self.alloc()?;
+      let b = self.alloc()?;
+      let c = self.alloc()?;
+      let d = self.alloc()?;
+      self.heap.set(a, P1, Ptr::new(LAM, 0, b));
+      self.heap.set(a, P2, Ptr::new(LAM, 0, d));
+      self.heap.set(b, P1, Ptr::big(NUM, chr as Val));
+      self.heap.set(b, P2, Ptr::new(LAM, 0, c));
+      self.heap.set(c, P1, list);
+      self.heap.set(c, P2, Ptr::new(VR2, 0, d));
+      self.heap.set(d, P1, ERAS);
+      self.heap.set(d, P2, Ptr::new(VR2, 0, c));
+      list = Ptr::new(LAM, 0, a);
+    }
+    return Ok(list);
+  }
+}
@@ -5,48 +5,165 @@ use crate::ast;
 
 use std::collections::HashMap;
 
-pub fn compile_book(book: &run::Book) -> String {
+// Compiles a book to Rust, as the 'fns.rs' of an executable. With 'tco', self-recursive definitions
+// loop instead of allocating a redex for each recursive call (see 'self_call').
+pub fn compile_book(book: &run::Book, tco: bool) -> String {
   let mut code = String::new();
 
   code.push_str(&format!("use crate::run::{{*}};\n"));
   code.push_str(&format!("\n"));
+  code.push_str(&compile_ids(book));
+
+  code.push_str(&format!("impl<'a> Net<'a> {{\n"));
+  code.push_str(&format!("\n"));
+
+  code.push_str(&format!("{}pub fn call_native(&mut self, book: &Book, ptr: Ptr, x: Ptr) -> Result<bool, RuntimeError> {{\n", ident(1)));
+  code.push_str(&compile_dispatch(book, 2, "self"));
+  code.push_str(&format!("{}}}\n", ident(1)));
+  code.push_str(&format!("\n"));
 
   for (fid, def) in book.defs.iter() {
     if def.node.len() > 0 {
-      let name = &ast::val_to_name(*fid as run::Val);
-      code.push_str(&format!("pub const F_{:4} : Val = 0x{:06x};\n", name, fid));
+      code.push_str(&compile_term(&book, 1, *fid, tco));
+      code.push_str(&format!("\n"));
     }
   }
 
+  code.push_str(&format!("}}"));
+
+  return code;
+
+}
+
+// Compiles a book to Rust, as the 'lib.rs' of a library crate depending on hvm-core. Definitions
+// are methods of a 'Compiled' trait, implemented for 'Net', and 'call_native' dispatches to them,
+// as a 'run::CallNative'. The crate must also have the book, in the binary format, as 'book.bin'.
+pub fn compile_lib(book: &run::Book, tco: bool) -> String {
+  let mut code = String::new();
+
+  code.push_str(&format!("#![allow(dead_code)]\n"));
+  code.push_str(&format!("#![allow(unused_variables)]\n"));
+  code.push_str(&format!("#![allow(non_snake_case)]\n"));
+  code.push_str(&format!("#![allow(non_upper_case_globals)]\n"));
+  code.push_str(&format!("\n"));
+  code.push_str(&format!("use hvmc::run::{{*}};\n"));
   code.push_str(&format!("\n"));
+  code.push_str(&compile_ids(book));
 
-  code.push_str(&format!("impl<'a> Net<'a> {{\n"));
+  code.push_str(&format!("pub const BOOK: &[u8] = include_bytes!(\"book.bin\");\n"));
   code.push_str(&format!("\n"));
 
-  code.push_str(&format!("{}pub fn call_native(&mut self, book: &Book, ptr: Ptr, x: Ptr) -> bool {{\n", ident(1)));
-  code.push_str(&format!("{}match ptr.val() {{\n", ident(2)));
+  code.push_str(&format!("// Loads the book, dispatching to its compiled definitions. These refer to definitions by the ids\n"));
+  code.push_str(&format!("// of 'SYMBOLS', which the book's own symbol table gives back.\n"));
+  code.push_str(&format!("pub fn book() -> Book {{\n"));
+  code.push_str(&format!("{}let mut book = hvmc::binary::book_from_bytes(BOOK).unwrap();\n", ident(1)));
+  code.push_str(&format!("{}book.call_native = Some(call_native);\n", ident(1)));
+  code.push_str(&format!("{}return book;\n", ident(1)));
+  code.push_str(&format!("}}\n"));
+  code.push_str(&format!("\n"));
+
+  code.push_str(&format!("// Dispatches a REF to its compiled definition, as a 'run::CallNative'.\n"));
+  code.push_str(&format!("pub fn call_native(net: &mut Net, book: &Book, ptr: Ptr, x: Ptr) -> Result<bool, RuntimeError> {{\n"));
+  code.push_str(&compile_dispatch(book, 1, "net"));
+  code.push_str(&format!("}}\n"));
+  code.push_str(&format!("\n"));
+
+  // A dynamic library has its own copy of hvm-core, so its loader must load the book itself. The
+  // entry points return Rust types, whose layout is only stable within one compiler version, so the
+  // loader must be built with the same rustc and hvm-core as the library.
+  code.push_str(&format!("// Entry points for loading this crate as a dynamic library. They return Rust types, so the\n"));
+  code.push_str(&format!("// loader must be built with the same rustc and hvm-core as this crate.\n"));
+  for (name, ty, val) in [("book", "&'static [u8]", "BOOK"), ("call_native", "CallNative", "call_native")] {
+    code.push_str(&format!("#[no_mangle]\n"));
+    code.push_str(&format!("#[allow(improper_ctypes_definitions)]\n"));
+    code.push_str(&format!("pub extern \"C\" fn hvmc_{}() -> {} {{\n", name, ty));
+    code.push_str(&format!("{}return {};\n", ident(1), val));
+    code.push_str(&format!("}}\n"));
+    code.push_str(&format!("\n"));
+  }
+
+  code.push_str(&format!("pub trait Compiled {{\n"));
   for (fid, def) in book.defs.iter() {
     if def.node.len() > 0 {
-      let fun = ast::val_to_name(*fid);
-      code.push_str(&format!("{}F_{} => {{ return self.F_{}(ptr, Trg::Ptr(x)); }}\n", ident(3), fun, fun));
+      code.push_str(&format!("{}fn {}(&mut self, ptr: Ptr, trg: Trg) -> Result<bool, RuntimeError>;\n", ident(1), fun_name(book, *fid)));
     }
   }
-  code.push_str(&format!("{}_ => {{ return false; }}\n", ident(3)));
-  code.push_str(&format!("{}}}\n", ident(2)));
-  code.push_str(&format!("{}}}\n", ident(1)));
+  code.push_str(&format!("}}\n"));
   code.push_str(&format!("\n"));
 
+  code.push_str(&format!("impl<'a> Compiled for Net<'a> {{\n"));
+  code.push_str(&format!("\n"));
   for (fid, def) in book.defs.iter() {
     if def.node.len() > 0 {
-      code.push_str(&compile_term(&book, 1, *fid));
+      // Trait methods take no visibility.
+      code.push_str(&compile_term(&book, 1, *fid, tco).replacen("pub fn", "fn", 1));
       code.push_str(&format!("\n"));
     }
   }
-
   code.push_str(&format!("}}"));
 
   return code;
+}
+
+// The symbols of a book, and the id constants of its definitions.
+fn compile_ids(book: &run::Book) -> String {
+  let mut code = String::new();
+
+  // Compiled code refers to definitions by id, so it carries the names of the book's ids.
+  code.push_str(&format!("pub const SYMBOLS: &[&str] = &{:?};\n", book.syms.names()));
+  code.push_str(&format!("\n"));
+
+  for (fid, def) in book.defs.iter() {
+    if def.node.len() > 0 {
+      code.push_str(&format!("pub const {:6} : Val = 0x{:06x};\n", fun_name(book, *fid), fid));
+    }
+  }
+
+  // References without a compiled definition, like IO calls, still need their id constants.
+  let mut refs = std::collections::BTreeSet::new();
+  for def in book.defs.values() {
+    for (a, b) in def.node.iter().chain(def.rdex.iter()) {
+      for ptr in [a, b] {
+        if ptr.is_ref() && !book.defs.get(&ptr.val()).map_or(false, |def| def.node.len() > 0) {
+          refs.insert(ptr.val());
+        }
+      }
+    }
+  }
+  for fid in refs {
+    code.push_str(&format!("pub const {:6} : Val = 0x{:06x};\n", fun_name(book, fid), fid));
+  }
+
+  code.push_str(&format!("\n"));
+
+  return code;
+}
+
+// Matches a REF with its compiled definition, calling it on 'net'.
+fn compile_dispatch(book: &run::Book, tab: usize, net: &str) -> String {
+  let mut arms = String::new();
+  for (fid, def) in book.defs.iter() {
+    if def.node.len() > 0 {
+      let fun = fun_name(book, *fid);
+      arms.push_str(&format!("{}{} => {{ return {}.{}(ptr, Trg::Ptr(x)); }}\n", ident(tab+1), fun, net, fun));
+    }
+  }
+  // Without compiled definitions, the match would only have its default arm.
+  if arms.is_empty() {
+    return format!("{}return Ok(false);\n", ident(tab));
+  }
+  let mut code = String::new();
+  code.push_str(&format!("{}match ptr.val() {{\n", ident(tab)));
+  code.push_str(&arms);
+  code.push_str(&format!("{}_ => {{ return Ok(false); }}\n", ident(tab+1)));
+  code.push_str(&format!("{}}}\n", ident(tab)));
+  return code;
+}
 
+// The identifier of a definition's function and id constant. Names may contain '.', which isn't
+// allowed in identifiers, so '_' is escaped as '__' and '.' as '_0', which can't collide.
+pub fn fun_name(book: &run::Book, fid: run::Val) -> String {
+  return format!("F_{}", book.syms.show(fid).replace('_', "__").replace('.', "_0"));
 }
 
 pub fn ident(tab: usize) -> String {
@@ -62,6 +179,8 @@ pub fn tag(tag: run::Tag) -> &'static str {
     run::REF => "REF",
     run::ERA => "ERA",
     run::NUM => "NUM",
+    run::INT => "INT",
+    run::FLT => "FLT",
     run::OP2 => "OP2",
     run::OP1 => "OP1",
     run::MAT => "MAT",
@@ -72,9 +191,9 @@ pub fn tag(tag: run::Tag) -> &'static str {
   }
 }
 
-pub fn atom(ptr: run::Ptr) -> String {
+pub fn atom(book: &run::Book, ptr: run::Ptr) -> String {
   if ptr.is_ref() {
-    return format!("Ptr::big(REF, F_{})", ast::val_to_name(ptr.val()));
+    return format!("Ptr::big(REF, {})", fun_name(book, ptr.val()));
   } else {
     return format!("Ptr::new({}, 0x{:x}, 0x{:x})", tag(ptr.tag()), ptr.lab(), ptr.loc());
   }
@@ -102,7 +221,7 @@ impl Target {
   }
 }
 
-pub fn compile_term(book: &run::Book, tab: usize, fid: run::Val) -> String {
+pub fn compile_term(book: &run::Book, tab: usize, fid: run::Val, tco: bool) -> String {
 
   // returns a fresh variable: 'v<NUM>'
   fn fresh(newx: &mut usize) -> String {
@@ -110,6 +229,12 @@ pub fn compile_term(book: &run::Book, tab: usize, fid: run::Val) -> String {
     format!("k{}", newx)
   }
 
+  // The book, and the def whose tail calls are compiled to loops, if any (see 'self_call')
+  struct Ctx<'a> {
+    book: &'a run::Book,
+    tail: Option<run::Val>,
+  }
+
   fn call_redex(
     book : &run::Book,
     tab  : usize,
@@ -121,15 +246,14 @@ pub fn compile_term(book: &run::Book, tab: usize, fid: run::Val) -> String {
     let (rf, rx) = adjust_redex(rdex.0, rdex.1);
     let rf_name  = format!("_{}", fresh(newx));
     let mut code = String::new();
-    code.push_str(&format!("{}let {} : Trg = Trg::Ptr({});\n", ident(tab), rf_name, &atom(rf)));
-    code.push_str(&burn(book, tab, None, newx, vars, def, rx, &Target { nam: rf_name }));
+    code.push_str(&format!("{}let {} : Trg = Trg::Ptr({});\n", ident(tab), rf_name, &atom(book, rf)));
+    code.push_str(&burn(&Ctx { book, tail: None }, tab, newx, vars, def, rx, &Target { nam: rf_name }));
     return code;
   }
 
   fn call(
-    book : &run::Book,
+    ctx  : &Ctx,
     tab  : usize,
-    tail : Option<run::Val>,
     newx : &mut usize,
     vars : &mut HashMap<run::Ptr, String>,
     fid  : run::Val,
@@ -138,37 +262,70 @@ pub fn compile_term(book: &run::Book, tab: usize, fid: run::Val) -> String {
     //let newx = &mut 0;
     //let vars = &mut HashMap::new();
 
+    let (book, tail) = (ctx.book, ctx.tail);
     let def = &book.get(fid).unwrap();
 
     // Tail call
-    // TODO: when I manually edited a file to implement tail call, the single-core performance
-    // increased a lot, but it resulted in a single thread withholding all redexes and, thus, 
-    // the program went single-core mode again. I believe a smarter redex sharing structure is
-    // necessary for us to implement tail calls in a way that doesn't sacrify parallelism.
-    //if tail.is_some() && def.rdex.len() > 0 && def.rdex[0].0.is_ref() && def.rdex[0].0.loc() == tail.unwrap() {
-      //println!("tco {}", ast::val_to_name(tail.unwrap() as run::Val));
-      //let mut code = String::new();
-      //for rdex in &def.rdex[1..] {
-        //code.push_str(&call_redex(book, tab, newx, vars, def, *rdex));
-      //}
-      //code.push_str(&burn(book, tab, Some(fid), newx, vars, def, def.node[0].1, &trg));
-      //code.push_str(&call_redex(book, tab, newx, vars, def, def.rdex[0]));
-      //return code;
-    //}
+    // This def was inlined in the successor branch of 'tail', and calls back into it: its other
+    // redexes and its root are burned first, then the recursive call loops to the start of 'tail'.
+    // The loop yields after TAIL_LIMIT iterations, or once it left TAIL_LIMIT redexes behind, by
+    // pushing the recursive call below them, so that they are reduced (or shared) first, as they
+    // would be without the loop.
+    if let Some(idx) = tail.and_then(|tail| self_call(book, tail, fid)) {
+      let tail = tail.unwrap();
+      let mut code = String::new();
+      for (i, rdex) in def.rdex.iter().enumerate() {
+        if i != idx {
+          code.push_str(&call_redex(book, tab, newx, vars, def, *rdex));
+        }
+      }
+      code.push_str(&burn(&Ctx { book, tail: None }, tab, newx, vars, def, def.node[0].1, trg));
+      let (_, rx) = adjust_redex(def.rdex[idx].0, def.rdex[idx].1);
+      let arg = fresh(newx);
+      let p1 = def.node[rx.loc() as usize].0;
+      let p2 = def.node[rx.loc() as usize].1;
+      let nod = format!("Ptr::new({}, {}, {})", tag(rx.tag()), rx.lab(), arg);
+      code.push_str(&format!("{}let {} = self.alloc()?;\n", ident(tab), arg));
+      code.push_str(&make(book, tab, newx, vars, def, p2, &format!("Trg::Ptr(Ptr::new(VR2, 0, {}))", arg)));
+      code.push_str(&make(book, tab, newx, vars, def, p1, &format!("Trg::Ptr(Ptr::new(VR1, 0, {}))", arg)));
+      code.push_str(&format!("{}// tail call @{}\n", ident(tab), book.syms.show(tail)));
+      code.push_str(&format!("{}if tails < TAIL_LIMIT && self.rdex.len() - base < TAIL_LIMIT {{\n", ident(tab)));
+      code.push_str(&format!("{}tails += 1;\n", ident(tab+1)));
+      code.push_str(&format!("{}self.dref({});\n", ident(tab+1), fun_name(book, tail)));
+      code.push_str(&format!("{}trg = Trg::Ptr({});\n", ident(tab+1), nod));
+      code.push_str(&format!("{}continue 'tail;\n", ident(tab+1)));
+      code.push_str(&format!("{}}} else {{\n", ident(tab)));
+      code.push_str(&format!("{}self.rdex.insert(base, (Ptr::big(REF, {}), {}));\n", ident(tab+1), fun_name(book, tail), nod));
+      code.push_str(&format!("{}}}\n", ident(tab)));
+      return code;
+    }
 
     // Normal call
     let mut code = String::new();
     for rdex in &def.rdex {
       code.push_str(&call_redex(book, tab, newx, vars, def, *rdex));
     }
-    code.push_str(&burn(book, tab, Some(fid), newx, vars, def, def.node[0].1, &trg));
+    code.push_str(&burn(ctx, tab, newx, vars, def, def.node[0].1, &trg));
     return code;
   }
+
+  // The redex through which 'fid', called from the successor branch of 'tail', calls back into it,
+  // if any. This is the form tail recursion takes: '@sum = (?<(@sumZ @sumS) a> a)', where '@sumS'
+  // has a redex '@sum ~ (...)'. The argument must be a node, for the loop to burn it.
+  fn self_call(book: &run::Book, tail: run::Val, fid: run::Val) -> Option<usize> {
+    if fid == tail {
+      return None;
+    }
+    let def = book.get(fid).filter(|def| def.node.len() > 0)?;
+    return def.rdex.iter().position(|(a, b)| {
+      let (rf, rx) = adjust_redex(*a, *b);
+      rf.is_ref() && rf.val() == tail && rx.is_nod()
+    });
+  }
   
   fn burn(
-    book : &run::Book,
+    ctx  : &Ctx,
     tab  : usize,
-    tail : Option<run::Val>,
     newx : &mut usize,
     vars : &mut HashMap<run::Ptr, String>,
     def  : &run::Def,
@@ -176,6 +333,7 @@ pub fn compile_term(book: &run::Book, tab: usize, fid: run::Val) -> String {
     trg  : &Target,
   ) -> String {
     //println!("burn {:08x} {}", ptr.0, x);
+    let (book, tail) = (ctx.book, ctx.tail);
     let mut code = String::new();
 
     // (<?(ifz ifs) ret> ret) ~ (#X R)
@@ -186,7 +344,7 @@ pub fn compile_term(book: &run::Book, tab: usize, fid: run::Val) -> String {
     // else:
     //   ifz ~ *
     //   ifs ~ (#(X-1) R)
-    // When ifs is REF, tail-call optimization is applied.
+    // When ifs is a REF that calls back into this def, it is inlined as a tail call (see 'self_call').
     if ptr.tag() == run::LAM {
       let mat = def.node[ptr.loc() as usize].0;
       let rty = def.node[ptr.loc() as usize].1;
@@ -219,14 +377,14 @@ pub fn compile_term(book: &run::Book, tab: usize, fid: run::Val) -> String {
           code.push_str(&format!("{}{} = {};\n", ident(tab+2), &c_z.show(), res.show()));
           code.push_str(&format!("{}{} = Trg::Ptr({});\n", ident(tab+2), &c_s.show(), "ERAS"));
           code.push_str(&format!("{}}} else {{\n", ident(tab+1)));
-          code.push_str(&format!("{}{};\n", ident(tab+2), num.swap(&format!("Ptr::big(NUM, {}.val() - 1)", num.get()))));
+          code.push_str(&format!("{}{};\n", ident(tab+2), num.swap(&format!("Ptr::big({}.tag(), {}.val() - 1)", num.get(), num.get()))));
           code.push_str(&format!("{}{} = Trg::Ptr({});\n", ident(tab+2), &c_z.show(), "ERAS"));
           code.push_str(&format!("{}{} = {};\n", ident(tab+2), &c_s.show(), trg.show()));
           code.push_str(&format!("{}}}\n", ident(tab+1)));
           code.push_str(&format!("{}}} else {{\n", ident(tab)));
-          code.push_str(&format!("{}let {} = self.alloc();\n", ident(tab+1), lam));
-          code.push_str(&format!("{}let {} = self.alloc();\n", ident(tab+1), mat));
-          code.push_str(&format!("{}let {} = self.alloc();\n", ident(tab+1), cse));
+          code.push_str(&format!("{}let {} = self.alloc()?;\n", ident(tab+1), lam));
+          code.push_str(&format!("{}let {} = self.alloc()?;\n", ident(tab+1), mat));
+          code.push_str(&format!("{}let {} = self.alloc()?;\n", ident(tab+1), cse));
           code.push_str(&format!("{}self.heap.set({}, P1, Ptr::new(MAT, 0, {}));\n", ident(tab+1), lam, mat));
           code.push_str(&format!("{}self.heap.set({}, P2, Ptr::new(VR2, 0, {}));\n", ident(tab+1), lam, mat));
           code.push_str(&format!("{}self.heap.set({}, P1, Ptr::new(LAM, 0, {}));\n", ident(tab+1), mat, cse));
@@ -235,8 +393,8 @@ pub fn compile_term(book: &run::Book, tab: usize, fid: run::Val) -> String {
           code.push_str(&format!("{}{} = Trg::Ptr(Ptr::new(VR1, 0, {}));\n", ident(tab+1), &c_z.show(), cse));
           code.push_str(&format!("{}{} = Trg::Ptr(Ptr::new(VR2, 0, {}));\n", ident(tab+1), &c_s.show(), cse));
           code.push_str(&format!("{}}}\n", ident(tab)));
-          code.push_str(&burn(book, tab, None, newx, vars, def, ifz, &c_z));
-          code.push_str(&burn(book, tab, tail, newx, vars, def, ifs, &c_s));
+          code.push_str(&burn(&Ctx { book, tail: None }, tab, newx, vars, def, ifz, &c_z));
+          code.push_str(&burn(ctx, tab, newx, vars, def, ifs, &c_s));
           return code;
         }
       }
@@ -258,14 +416,14 @@ pub fn compile_term(book: &run::Book, tab: usize, fid: run::Val) -> String {
         code.push_str(&format!("{}self.rwts.oper += 2;\n", ident(tab+1))); // OP2 + OP1
         code.push_str(&format!("{}let vx = {};\n", ident(tab+1), trg.take()));
         code.push_str(&format!("{}let vy = {};\n", ident(tab+1), val.take()));
-        code.push_str(&format!("{}{} = Trg::Ptr(Ptr::big(NUM, self.op({},vx.val(),vy.val())));\n", ident(tab+1), &nxt.show(), ptr.lab()));
+        code.push_str(&format!("{}{} = Trg::Ptr(Ptr::num(0x{:x}, self.checked_op({},vx.val(),vy.val(),vx,vy)?));\n", ident(tab+1), &nxt.show(), run::result_type(ptr.lab()), ptr.lab()));
         code.push_str(&format!("{}}} else {{\n", ident(tab)));
-        code.push_str(&format!("{}let {} = self.alloc();\n", ident(tab+1), op2));
+        code.push_str(&format!("{}let {} = self.alloc()?;\n", ident(tab+1), op2));
         code.push_str(&format!("{}self.safe_link(Trg::Ptr(Ptr::new(VR1, 0, {})), {});\n", ident(tab+1), op2, val.show()));
         code.push_str(&format!("{}self.safe_link(Trg::Ptr(Ptr::new(OP2, {}, {})), {});\n", ident(tab+1), ptr.lab(), op2, trg.show()));
         code.push_str(&format!("{}{} = Trg::Ptr(Ptr::new(VR2, 0, {}));\n", ident(tab+1), &nxt.show(), op2));
         code.push_str(&format!("{}}}\n", ident(tab)));
-        code.push_str(&burn(book, tab, None, newx, vars, def, ret, &nxt));
+        code.push_str(&burn(&Ctx { book, tail: None }, tab, newx, vars, def, ret, &nxt));
         return code;
       }
     }
@@ -283,19 +441,19 @@ pub fn compile_term(book: &run::Book, tab: usize, fid: run::Val) -> String {
       code.push_str(&format!("{}let {} : Trg;\n", ident(tab), &x1.show()));
       code.push_str(&format!("{}let {} : Trg;\n", ident(tab), &x2.show()));
       code.push_str(&format!("{}// fast copy\n", ident(tab)));
-      code.push_str(&format!("{}if {}.tag() == NUM {{\n", ident(tab), trg.get()));
+      code.push_str(&format!("{}if {}.is_num() {{\n", ident(tab), trg.get()));
       code.push_str(&format!("{}self.rwts.comm += 1;\n", ident(tab+1)));
       code.push_str(&format!("{}let got = {};\n", ident(tab+1), trg.take()));
       code.push_str(&format!("{}{} = Trg::Ptr(got);\n", ident(tab+1), &x1.show()));
       code.push_str(&format!("{}{} = Trg::Ptr(got);\n", ident(tab+1), &x2.show()));
       code.push_str(&format!("{}}} else {{\n", ident(tab)));
-      code.push_str(&format!("{}let {} = self.alloc();\n", ident(tab+1), lc));
+      code.push_str(&format!("{}let {} = self.alloc()?;\n", ident(tab+1), lc));
       code.push_str(&format!("{}{} = Trg::Ptr(Ptr::new(VR1, 0, {}));\n", ident(tab+1), &x1.show(), lc));
       code.push_str(&format!("{}{} = Trg::Ptr(Ptr::new(VR2, 0, {}));\n", ident(tab+1), &x2.show(), lc));
       code.push_str(&format!("{}self.safe_link(Trg::Ptr(Ptr::new({}, {}, {})), {});\n", ident(tab+1), tag(ptr.tag()), ptr.lab(), lc, trg.show()));
       code.push_str(&format!("{}}}\n", ident(tab)));
-      code.push_str(&burn(book, tab, None, newx, vars, def, p2, &x2));
-      code.push_str(&burn(book, tab, None, newx, vars, def, p1, &x1));
+      code.push_str(&burn(&Ctx { book, tail: None }, tab, newx, vars, def, p2, &x2));
+      code.push_str(&burn(&Ctx { book, tail: None }, tab, newx, vars, def, p1, &x1));
       return code;
     }
 
@@ -318,20 +476,42 @@ pub fn compile_term(book: &run::Book, tab: usize, fid: run::Val) -> String {
       code.push_str(&format!("{}{} = Trg::Dir(Ptr::new(VR1, 0, got.loc()));\n", ident(tab+1), &x1.show()));
       code.push_str(&format!("{}{} = Trg::Dir(Ptr::new(VR2, 0, got.loc()));\n", ident(tab+1), &x2.show()));
       code.push_str(&format!("{}}} else {{\n", ident(tab)));
-      code.push_str(&format!("{}let {} = self.alloc();\n", ident(tab+1), lc));
+      code.push_str(&format!("{}let {} = self.alloc()?;\n", ident(tab+1), lc));
       code.push_str(&format!("{}{} = Trg::Ptr(Ptr::new(VR1, 0, {}));\n", ident(tab+1), &x1.show(), lc));
       code.push_str(&format!("{}{} = Trg::Ptr(Ptr::new(VR2, 0, {}));\n", ident(tab+1), &x2.show(), lc));
       code.push_str(&format!("{}self.safe_link(Trg::Ptr(Ptr::new({}, 0, {})), {});\n", ident(tab+1), tag(ptr.tag()), lc, trg.show()));
       code.push_str(&format!("{}}}\n", ident(tab)));
-      code.push_str(&burn(book, tab, None, newx, vars, def, p2, &x2));
-      code.push_str(&burn(book, tab, None, newx, vars, def, p1, &x1));
+      code.push_str(&burn(&Ctx { book, tail: None }, tab, newx, vars, def, p2, &x2));
+      code.push_str(&burn(&Ctx { book, tail: None }, tab, newx, vars, def, p1, &x1));
+      return code;
+    }
+
+    // @F <~ x
+    // -------------- tail call
+    // if x is a node:
+    //   inline F ~ x
+    // @F is the successor branch of 'tail', and calls back into it. It is only inlined if it would
+    // be called anyway, so branches that end up erased or linked to wires aren't expanded.
+    if ptr.is_ref() && tail.map_or(false, |tail| self_call(book, tail, ptr.val()).is_some()) {
+      let safe = book.get(ptr.val()).unwrap().safe;
+      code.push_str(&format!("{}// inline @{}\n", ident(tab), book.syms.show(ptr.val())));
+      if safe {
+        code.push_str(&format!("{}if {}.is_nod() && !{}.is_dup() {{\n", ident(tab), trg.get(), trg.get()));
+      } else {
+        code.push_str(&format!("{}if {}.is_nod() {{\n", ident(tab), trg.get()));
+      }
+      code.push_str(&format!("{}self.dref({});\n", ident(tab+1), fun_name(book, ptr.val())));
+      code.push_str(&call(ctx, tab+1, newx, &mut HashMap::new(), ptr.val(), trg));
+      code.push_str(&format!("{}}} else {{\n", ident(tab)));
+      code.push_str(&make(book, tab+1, newx, vars, def, ptr, &trg.show()));
+      code.push_str(&format!("{}}}\n", ident(tab)));
       return code;
     }
 
     //// TODO: implement inlining correctly
     //// NOTE: enabling this makes dec_bits_tree hang; investigate
     //if ptr.is_ref() && tail.is_some() {
-      //code.push_str(&format!("{}// inline @{}\n", ident(tab), ast::val_to_name(ptr.loc() as run::Val)));
+      //code.push_str(&format!("{}// inline @{}\n", ident(tab), book.syms.show(ptr.loc() as run::Val)));
       //code.push_str(&format!("{}if !{}.is_skp() {{\n", ident(tab), trg.get()));
       //code.push_str(&format!("{}self.rwts.dref += 1;\n", ident(tab+1)));
       //code.push_str(&call(book, tab+1, tail, newx, &mut HashMap::new(), ptr.loc(), trg));
@@ -350,16 +530,17 @@ pub fn compile_term(book: &run::Book, tab: usize, fid: run::Val) -> String {
       code.push_str(&format!("{}{};\n", ident(tab+1), trg.take()));
       code.push_str(&format!("{}self.rwts.eras += 1;\n", ident(tab+1)));
       code.push_str(&format!("{}}} else {{\n", ident(tab)));
-      code.push_str(&make(tab+1, newx, vars, def, ptr, &trg.show()));
+      code.push_str(&make(book, tab+1, newx, vars, def, ptr, &trg.show()));
       code.push_str(&format!("{}}}\n", ident(tab)));
       return code;
     }
 
-    code.push_str(&make(tab, newx, vars, def, ptr, &trg.show()));
+    code.push_str(&make(book, tab, newx, vars, def, ptr, &trg.show()));
     return code;
   }
 
   fn make(
+    book : &run::Book,
     tab  : usize,
     newx : &mut usize,
     vars : &mut HashMap<run::Ptr, String>,
@@ -373,9 +554,9 @@ pub fn compile_term(book: &run::Book, tab: usize, fid: run::Val) -> String {
       let lc = fresh(newx);
       let p1 = def.node[ptr.loc() as usize].0;
       let p2 = def.node[ptr.loc() as usize].1;
-      code.push_str(&format!("{}let {} = self.alloc();\n", ident(tab), lc));
-      code.push_str(&make(tab, newx, vars, def, p2, &format!("Trg::Ptr(Ptr::new(VR2, 0, {}))", lc)));
-      code.push_str(&make(tab, newx, vars, def, p1, &format!("Trg::Ptr(Ptr::new(VR1, 0, {}))", lc)));
+      code.push_str(&format!("{}let {} = self.alloc()?;\n", ident(tab), lc));
+      code.push_str(&make(book, tab, newx, vars, def, p2, &format!("Trg::Ptr(Ptr::new(VR2, 0, {}))", lc)));
+      code.push_str(&make(book, tab, newx, vars, def, p1, &format!("Trg::Ptr(Ptr::new(VR1, 0, {}))", lc)));
       code.push_str(&format!("{}self.safe_link(Trg::Ptr(Ptr::new({}, {}, {})), {});\n", ident(tab), tag(ptr.tag()), ptr.lab(), lc, trg));
     } else if ptr.is_var() {
       match got(vars, def, ptr) {
@@ -389,7 +570,7 @@ pub fn compile_term(book: &run::Book, tab: usize, fid: run::Val) -> String {
         }
       }
     } else {
-      code.push_str(&format!("{}self.safe_link({}, Trg::Ptr({}));\n", ident(tab), trg, atom(ptr)));
+      code.push_str(&format!("{}self.safe_link({}, Trg::Ptr({}));\n", ident(tab), trg, atom(book, ptr)));
     }
     return code;
   }
@@ -408,19 +589,36 @@ pub fn compile_term(book: &run::Book, tab: usize, fid: run::Val) -> String {
     }
   }
 
-  let fun = ast::val_to_name(fid);
+  let fun = fun_name(book, fid);
   let def = &book.get(fid).unwrap();
 
+  // Compiles the body, then wraps it in a loop if it has a tail call.
+  let body = |tab: usize| {
+    let mut code = String::new();
+    if def.safe {
+      code.push_str(&format!("{}if self.get(trg).is_dup() {{\n", ident(tab)));
+      code.push_str(&format!("{}self.copy(self.swap(trg, NULL), ptr);\n", ident(tab+1)));
+      code.push_str(&format!("{}return Ok(true);\n", ident(tab+1)));
+      code.push_str(&format!("{}}}\n", ident(tab)));
+    }
+    let tail = if tco { Some(fid) } else { None };
+    code.push_str(&call(&Ctx { book, tail }, tab, &mut 0, &mut HashMap::new(), fid, &Target { nam: "trg".to_string() }));
+    code.push_str(&format!("{}return Ok(true);\n", ident(tab)));
+    return code;
+  };
+
   let mut code = String::new();
-  code.push_str(&format!("{}pub fn F_{}(&mut self, ptr: Ptr, trg: Trg) -> bool {{\n", ident(tab), fun));
-  if def.safe {
-    code.push_str(&format!("{}if self.get(trg).is_dup() {{\n", ident(tab+1)));
-    code.push_str(&format!("{}self.copy(self.swap(trg, NULL), ptr);\n", ident(tab+2)));
-    code.push_str(&format!("{}return true;\n", ident(tab+2)));
+  code.push_str(&format!("{}pub fn {}(&mut self, ptr: Ptr, trg: Trg) -> Result<bool, RuntimeError> {{\n", ident(tab), fun));
+  if body(tab+1).contains("continue 'tail;") {
+    code.push_str(&format!("{}let mut trg = trg;\n", ident(tab+1)));
+    code.push_str(&format!("{}let mut tails = 0;\n", ident(tab+1)));
+    code.push_str(&format!("{}let base = self.rdex.len();\n", ident(tab+1)));
+    code.push_str(&format!("{}'tail: loop {{\n", ident(tab+1)));
+    code.push_str(&body(tab+2));
     code.push_str(&format!("{}}}\n", ident(tab+1)));
+  } else {
+    code.push_str(&body(tab+1));
   }
-  code.push_str(&call(book, tab+1, None, &mut 0, &mut HashMap::new(), fid, &Target { nam: "trg".to_string() }));
-  code.push_str(&format!("{}return true;\n", ident(tab+1)));
   code.push_str(&format!("{}}}\n", ident(tab)));
 
   return code;
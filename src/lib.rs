@@ -1,11 +1,22 @@
-#![allow(dead_code)]
-#![allow(unused_variables)]
-#![allow(unused_imports)]
-#![allow(non_snake_case)]
-#![allow(non_upper_case_globals)]
-
-pub mod ast;
-pub mod fns;
-pub mod jit;
-pub mod run;
-pub mod u60;
+#![allow(dead_code)]
+#![allow(unused_variables)]
+#![allow(unused_imports)]
+#![allow(non_snake_case)]
+#![allow(non_upper_case_globals)]
+
+pub mod ast;
+pub mod binary;
+pub mod check;
+pub mod cst;
+pub mod debug;
+pub mod dot;
+pub mod f60;
+pub mod fmt;
+pub mod fns;
+pub mod i60;
+pub mod io;
+pub mod jit;
+pub mod profile;
+pub mod run;
+pub mod trace;
+pub mod u60;
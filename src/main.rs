@@ -8,9 +8,17 @@ use std::env;
 use std::fs;
 
 use hvmc::ast;
+use hvmc::binary;
+use hvmc::check;
+use hvmc::debug;
+use hvmc::dot;
+use hvmc::fmt;
 use hvmc::fns;
+use hvmc::io;
 use hvmc::jit;
+use hvmc::profile;
 use hvmc::run;
+use hvmc::trace;
 use hvmc::u60;
 
 use std::collections::HashSet;
@@ -19,60 +27,265 @@ use std::collections::HashSet;
 fn main() {
   let args: Vec<String> = env::args().collect();
   let opts = args.iter().skip(3).map(|s| s.as_str()).collect::<HashSet<_>>();
-  let book = run::Book::new();
-  let data = run::Heap::init(1 << 28);
+  let config = parse_config(args.get(3..).unwrap_or(&[]));
+  let mut book = run::Book::new();
+  book.syms = match ast::Symbols::load(fns::SYMBOLS) {
+    Ok(syms) => syms,
+    Err(err) => {
+      eprintln!("{}", err);
+      std::process::exit(1);
+    }
+  };
+  if opts.contains("--io") {
+    book.set_io(io::StdIo);
+  }
+  let data = run::Heap::init(config.heap);
   let mut net = run::Net::new(&data);
-  net.boot(ast::name_to_val("main"));
+  net.boot(main_id(&book));
+  net.strict = opts.contains("--strict-arith");
   let start_time = std::time::Instant::now();
-  if opts.contains("-1") {
-    net.normal(&book);
+  let result = if opts.contains("-1") {
+    net.normal(&book)
   } else {
-    net.parallel_normal(&book);
+    net.parallel_normal(&book, &config)
+  };
+  if let Err(err) = result {
+    eprintln!("{}", err);
+    std::process::exit(1);
   }
-  println!("{}", ast::show_runtime_net(&net));
+  println!("{}", ast::show_runtime_net(&net, &book.syms));
   print_stats(&net, start_time);
 }
 
 #[cfg(feature = "hvm_cli_options")]
 fn main() -> Result<(), Box<dyn std::error::Error>> {
-  let data = run::Heap::init(1 << 28);
   let args: Vec<String> = env::args().collect();
   let help = "help".to_string();
   let opts = args.iter().skip(3).map(|s| s.as_str()).collect::<HashSet<_>>();
+  let config = parse_config(args.get(3..).unwrap_or(&[]));
+  let encoding = if opts.contains("--church") { ast::Encoding::Church } else { ast::Encoding::Scott };
   let action = args.get(1).unwrap_or(&help);
   let f_name = args.get(2);
   match action.as_str() {
     "run" => {
       if let Some(file_name) = f_name {
-        let (book, mut net) = load(&data, file_name);
+        let (every, resume) = parse_checkpoint_args(&args[3..]);
+        let trace_name = parse_trace_arg(&args[3..]);
+        let data = run::Heap::init(config.heap);
+        let (mut book, mut net) = load(&data, file_name, encoding);
+        net.strict = opts.contains("--strict-arith");
+        if opts.contains("--io") {
+          book.set_io(io::StdIo);
+        }
+        if trace_name.is_some() {
+          // A trace covers a single reduction, from the booted net.
+          if every != usize::MAX || resume.is_some() {
+            eprintln!("Traces can't be combined with checkpoints");
+            std::process::exit(1);
+          }
+          net.tracer = Some(trace::Tracer::new());
+        }
+        if opts.contains("--profile") {
+          net.profiler = Some(profile::Profiler::new(data.len()));
+        }
+        if let Some(snap_name) = resume {
+          resume_snapshot(&mut net, &book, snap_name);
+        }
+        // Snapshots overwrite the resumed one, if any, so that runs can be resumed repeatedly.
+        let snap_name = resume.cloned().unwrap_or_else(|| format!("{}.snap", file_name));
         let start_time = std::time::Instant::now();
-        if opts.contains("-1") {
-          net.normal(&book);
+        loop {
+          let result = if opts.contains("-1") {
+            net.normal_with_fuel(&book, every)
+          } else {
+            net.parallel_normal_with_fuel(&book, &config, every)
+          };
+          match result {
+            Ok(true) => break,
+            Ok(false) => save_snapshot(&net, &book, &snap_name)?,
+            Err(err) => {
+              eprintln!("{}", err);
+              save_trace(&mut net, &book, trace_name)?;
+              std::process::exit(1);
+            }
+          }
+        }
+        save_trace(&mut net, &book, trace_name)?;
+        if opts.contains("--sugar") {
+          println!("{}", ast::show_runtime_net_sugared(&net, &book.syms, encoding));
         } else {
-          net.parallel_normal(&book);
+          println!("{}", ast::show_runtime_net(&net, &book.syms));
         }
-        println!("{}", ast::show_runtime_net(&net));
         if opts.contains("-s") {
           print_stats(&net, start_time);
         }
+        save_profile(&mut net, &book, &format!("{}.folded", file_name))?;
       } else {
-        println!("Usage: hvmc run <file.hvmc> [-s]");
+        println!("Usage: hvmc run <file.hvmc> [-s] [-1] [-t <threads>] [--scheduler <split|steal>] [-m <size>] [--checkpoint-every <rewrites>] [--resume <file.snap>] [--strict-arith] [--io] [--church] [--sugar] [--trace <file.trace>] [--profile]");
+        std::process::exit(1);
+      }
+    }
+    "replay" => {
+      if let (Some(file_name), Some(trace_name)) = (f_name, args.get(3)) {
+        let Ok(bytes) = fs::read(trace_name) else {
+          eprintln!("Trace file not found");
+          std::process::exit(1);
+        };
+        let data = run::Heap::init(config.heap);
+        let (mut book, mut net) = load(&data, file_name, encoding);
+        let trace = match binary::trace_from_bytes(&bytes, &book) {
+          Ok(trace) => trace,
+          Err(err) => {
+            eprintln!("{}", err);
+            std::process::exit(1);
+          }
+        };
+        net.strict = opts.contains("--strict-arith");
+        if opts.contains("--io") {
+          book.set_io(io::StdIo);
+        }
+        if let Err(err) = trace::replay(&mut net, &book, &trace) {
+          eprintln!("{}", err);
+          std::process::exit(1);
+        }
+        println!("{}", ast::show_runtime_net(&net, &book.syms));
+        println!("Replayed {} steps of {} thread(s)", trace.steps.len(), trace.tids);
+      } else {
+        println!("Usage: hvmc replay <file.hvmc> <file.trace> [-m <size>] [--strict-arith] [--io]");
+        std::process::exit(1);
+      }
+    }
+    "debug" => {
+      if let Some(file_name) = f_name {
+        let data = run::Heap::init(config.heap);
+        let (mut book, net) = load(&data, file_name, encoding);
+        if opts.contains("--io") {
+          book.set_io(io::StdIo);
+        }
+        let mut dbg = debug::Debugger::new(net, &book);
+        dbg.net.strict = opts.contains("--strict-arith");
+        println!("Debugging {} (type 'help' for commands)", file_name);
+        // An empty line repeats the last command, so that steps can be taken by pressing enter.
+        let mut last = "step".to_string();
+        loop {
+          print!("(hvmc) ");
+          std::io::Write::flush(&mut std::io::stdout())?;
+          let mut line = String::new();
+          if std::io::stdin().read_line(&mut line)? == 0 {
+            break;
+          }
+          let line = line.trim();
+          if line == "quit" || line == "q" {
+            break;
+          }
+          if !line.is_empty() {
+            last = line.to_string();
+          }
+          println!("{}", dbg.exec(&last));
+        }
+      } else {
+        println!("Usage: hvmc debug <file.hvmc> [-m <size>] [--strict-arith] [--io]");
         std::process::exit(1);
       }
     }
     "compile" => {
       if let Some(file_name) = f_name {
-        let (book, _) = load(&data, file_name);
-        compile_book_to_rust_crate(file_name, &book)?;
-        compile_rust_crate_to_executable(file_name)?;
+        let book = load_runtime_book(file_name, encoding);
+        let tco = opts.contains("--tco");
+        if opts.contains("--lib") || opts.contains("--cdylib") {
+          if let Err(err) = compile_book_to_rust_lib(file_name, &book, tco) {
+            eprintln!("{}", err);
+            std::process::exit(1);
+          }
+          if opts.contains("--cdylib") {
+            compile_rust_lib_to_cdylib(file_name)?;
+          }
+        } else {
+          compile_book_to_rust_crate(file_name, &book, tco)?;
+          compile_rust_crate_to_executable(file_name)?;
+        }
+      } else {
+        println!("Usage: hvmc compile <file.hvmc> [--tco] [--lib] [--cdylib]");
+        std::process::exit(1);
+      }
+    }
+    "check" => {
+      if let Some(file_name) = f_name {
+        let report = check::check_book(&load_book(file_name, encoding));
+        if opts.contains("--json") {
+          println!("{}", report.to_json());
+        } else {
+          print!("{}", report.show());
+        }
+        if !report.is_ok() {
+          std::process::exit(1);
+        }
+      } else {
+        println!("Usage: hvmc check <file.hvmc> [--json]");
+        std::process::exit(1);
+      }
+    }
+    "dot" => {
+      if let Some(file_name) = f_name {
+        let book = load_book(file_name, encoding);
+        let name = args.get(3).map(|def| def.trim_start_matches('@')).unwrap_or("main");
+        let Some(net) = book.get(name) else {
+          eprintln!("Definition not found: @{}", name);
+          std::process::exit(1);
+        };
+        print!("{}", dot::net_to_dot(net));
       } else {
-        println!("Usage: hvmc compile <file.hvmc>");
+        println!("Usage: hvmc dot <file.hvmc> [@def]");
+        std::process::exit(1);
+      }
+    }
+    "fmt" => {
+      let (config, check, file_name) = parse_fmt_args(&args[2..]);
+      if let Some(file_name) = file_name {
+        let Ok(code) = fs::read_to_string(file_name) else {
+          eprintln!("Input file not found");
+          std::process::exit(1);
+        };
+        let formatted = match fmt::format_book(&code, &config) {
+          Ok(formatted) => formatted,
+          Err(err) => {
+            eprintln!("{}", err.render(&code));
+            std::process::exit(1);
+          }
+        };
+        if check {
+          if formatted != code {
+            eprintln!("{} is not formatted", file_name);
+            std::process::exit(1);
+          }
+        } else if formatted != code {
+          fs::write(file_name, formatted)?;
+        }
+      } else {
+        println!("Usage: hvmc fmt [--check] [--rename] [--width <n>] <file.hvmc>");
+        std::process::exit(1);
+      }
+    }
+    "convert" => {
+      if let (Some(file_name), Some(out_name)) = (f_name, args.get(3)) {
+        let Ok(bytes) = fs::read(file_name) else {
+          eprintln!("Input file not found");
+          std::process::exit(1);
+        };
+        let book = load_runtime_book(file_name, encoding);
+        if binary::is_binary(&bytes) {
+          fs::write(out_name, ast::show_runtime_book(&book))?;
+        } else {
+          fs::write(out_name, binary::book_to_bytes(&book))?;
+        }
+      } else {
+        println!("Usage: hvmc convert <file.hvmc> <file.hvmcb> (or the other way around)");
         std::process::exit(1);
       }
     }
     "gen-cuda-book" => {
       if let Some(file_name) = f_name {
-        let book = load(&data, file_name).0;
+        let book = load_runtime_book(file_name, encoding);
         println!("{}", gen_cuda_book(&book));
       } else {
         println!("Usage: hvmc gen-cuda-book <file.hvmc>");
@@ -83,16 +296,136 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
       println!("Usage: hvmc <cmd> <file.hvmc> [-s]");
       println!("Commands:");
       println!("  run           - Run the given file");
-      println!("  compile       - Compile the given file to an executable");
+      println!("  replay        - Replay a trace of the given file on a single thread, checking every step");
+      println!("  debug         - Step through the reduction of the given file, with breakpoints");
+      println!("  compile       - Compile the given file to an executable (or a library crate, with --lib)");
+      println!("  check         - Validate the given file and report stats, without running it");
+      println!("  fmt           - Format the given file in place (--check to only verify it)");
+      println!("  dot           - Print a definition (default: @main) as a Graphviz DOT graph");
+      println!("  convert       - Convert the given file between the text and binary formats");
       println!("  gen-cuda-book - Generate a CUDA book from the given file");
       println!("Options:");
       println!("  [-s] Show stats, including rewrite count");
       println!("  [-1] Single-core mode (no parallelism)");
+      println!("  [-t <threads>] Number of threads (default: available cores)");
+      println!("  [--scheduler <split|steal>] How threads share redexes: pairwise on barriers (default), or by work stealing");
+      println!("  [-m <size>] Heap size in bytes, with an optional K/M/G suffix (default: 4G)");
+      println!("  [--checkpoint-every <rewrites>] Save a snapshot to <file.hvmc>.snap every given rewrites");
+      println!("  [--resume <file.snap>] Resume from a snapshot (saving later ones over it)");
+      println!("  [--strict-arith] Fail on division by zero and shifts by 60+ bits, instead of defining them");
+      println!("  [--io] Enable the @IO.print, @IO.read_line and @IO.read_file effects");
+      println!("  [--church] Encode list and string literals as Church lists, instead of Scott lists");
+      println!("  [--sugar] Show the lists in the result as list and string literals");
+      println!("  [--trace <file.trace>] Record every interaction, for 'replay' (with the same -m)");
+      println!("  [--profile] Show the rewrites of each definition, and save them to <file.hvmc>.folded, for flamegraphs");
+      println!("  [--tco] When compiling, turn tail-recursive calls into loops, which yield to other threads periodically");
+      println!("  [--lib] When compiling, generate a library crate depending on hvm-core, in a directory named after the file");
+      println!("  [--cdylib] Like --lib, and also build it as a dynamic library, to load at runtime");
     }
   }
   Ok(())
 }
 
+// Parses the runtime options ('-t <threads>', '-m <size>'), exiting on invalid values.
+fn parse_config(opts: &[String]) -> run::RuntimeConfig {
+  let mut config = run::RuntimeConfig::default();
+  for (opt, val) in opts.iter().zip(opts.iter().skip(1)) {
+    match opt.as_str() {
+      "-t" => match val.parse::<usize>() {
+        Ok(threads) if threads > 0 => config.threads = threads,
+        _ => {
+          eprintln!("Invalid thread count: {}", val);
+          std::process::exit(1);
+        }
+      },
+      "--scheduler" => match val.as_str() {
+        "split" => config.scheduler = run::Scheduler::Split,
+        "steal" => config.scheduler = run::Scheduler::Steal,
+        _ => {
+          eprintln!("Invalid scheduler: {} (expected split or steal)", val);
+          std::process::exit(1);
+        }
+      },
+      "-m" => match parse_size(val) {
+        // Each node holds two 8-byte pointers.
+        Some(size) if size >= 16 => config.heap = size / 16,
+        _ => {
+          eprintln!("Invalid heap size: {}", val);
+          std::process::exit(1);
+        }
+      },
+      _ => {}
+    }
+  }
+  // Each thread allocates in its own area of the heap, which can't be empty.
+  if config.heap < config.threads {
+    eprintln!("Heap of {} nodes is too small for {} threads", config.heap, config.threads);
+    std::process::exit(1);
+  }
+  return config;
+}
+
+// Parses the formatter arguments ('--check', '--rename', '--width <n>' and the file name), exiting
+// on invalid values.
+fn parse_fmt_args(args: &[String]) -> (fmt::FmtConfig, bool, Option<&String>) {
+  let mut config = fmt::FmtConfig::default();
+  let mut check = false;
+  let mut file = None;
+  let mut args = args.iter();
+  while let Some(arg) = args.next() {
+    match arg.as_str() {
+      "--check"  => check = true,
+      "--rename" => config.rename = true,
+      "--width"  => match args.next().and_then(|val| val.parse::<usize>().ok()) {
+        Some(width) => config.width = width,
+        None => {
+          eprintln!("Invalid width");
+          std::process::exit(1);
+        }
+      },
+      _ => file = Some(arg),
+    }
+  }
+  return (config, check, file);
+}
+
+// Parses the checkpoint options ('--checkpoint-every <rewrites>', '--resume <file>'), exiting on
+// invalid values. Without checkpoints, the rewrite interval is unbounded.
+fn parse_checkpoint_args(opts: &[String]) -> (usize, Option<&String>) {
+  let mut every = usize::MAX;
+  let mut resume = None;
+  for (opt, val) in opts.iter().zip(opts.iter().skip(1)) {
+    match opt.as_str() {
+      "--checkpoint-every" => match val.parse::<usize>() {
+        Ok(rewrites) if rewrites > 0 => every = rewrites,
+        _ => {
+          eprintln!("Invalid checkpoint interval: {}", val);
+          std::process::exit(1);
+        }
+      },
+      "--resume" => resume = Some(val),
+      _ => {}
+    }
+  }
+  return (every, resume);
+}
+
+// Parses the trace option ('--trace <file>').
+fn parse_trace_arg(opts: &[String]) -> Option<&String> {
+  opts.iter().zip(opts.iter().skip(1)).find(|(opt, _)| *opt == "--trace").map(|(_, val)| val)
+}
+
+// Parses a size in bytes, like '512', '64K', '256M' or '1G'.
+fn parse_size(text: &str) -> Option<usize> {
+  let (num, unit) = match text.char_indices().last()? {
+    (i, 'K' | 'k') => (&text[..i], 1 << 10),
+    (i, 'M' | 'm') => (&text[..i], 1 << 20),
+    (i, 'G' | 'g') => (&text[..i], 1 << 30),
+    _              => (text, 1),
+  };
+  num.parse::<usize>().ok()?.checked_mul(unit)
+}
+
 fn print_stats(net: &run::Net, start_time: std::time::Instant) {
   println!("RWTS   : {}", net.rewrites());
   println!("- ANNI : {}", net.rwts.anni);
@@ -105,19 +438,110 @@ fn print_stats(net: &run::Net, start_time: std::time::Instant) {
 }
 
 // Load file and generate net
-fn load<'a>(data: &'a run::Data, file: &str) -> (run::Book, run::Net<'a>) {
+fn load<'a>(data: &'a run::Data, file: &str, encoding: ast::Encoding) -> (run::Book, run::Net<'a>) {
+  let book = load_runtime_book(file, encoding);
+  let mut net = run::Net::new(&data);
+  net.boot(main_id(&book));
+  return (book, net);
+}
+
+// The id of '@main', exiting if the book has none
+fn main_id(book: &run::Book) -> run::Val {
+  match book.syms.id("main") {
+    Some(fid) => fid,
+    None => {
+      eprintln!("Definition not found: @main");
+      std::process::exit(1);
+    }
+  }
+}
+
+// Saves the trace of a net, if it was traced
+fn save_trace(net: &mut run::Net, book: &run::Book, file: Option<&String>) -> std::io::Result<()> {
+  if let (Some(file), Some(tracer)) = (file, net.tracer.take()) {
+    fs::write(file, binary::trace_to_bytes(&tracer.finish(), book))?;
+  }
+  Ok(())
+}
+
+// Shows the profile of a net, if it was profiled, and saves its folded stacks
+fn save_profile(net: &mut run::Net, book: &run::Book, file: &str) -> std::io::Result<()> {
+  if let Some(profiler) = net.profiler.take() {
+    let profile = profiler.finish(book);
+    print!("{}", profile.show());
+    fs::write(file, profile.folded())?;
+  }
+  Ok(())
+}
+
+// Load a snapshot into a freshly loaded net
+fn resume_snapshot(net: &mut run::Net, book: &run::Book, file: &str) {
+  let Ok(bytes) = fs::read(file) else {
+    eprintln!("Snapshot file not found");
+    std::process::exit(1);
+  };
+  // Clears the booted root, since the snapshot has its own
+  net.heap.set_root(run::NULL);
+  if let Err(err) = binary::net_from_bytes(net, book, &bytes) {
+    eprintln!("{}", err);
+    std::process::exit(1);
+  }
+}
+
+// Save a snapshot, replacing the previous one only once it's fully written
+fn save_snapshot(net: &run::Net, book: &run::Book, file: &str) -> Result<(), std::io::Error> {
+  let temp = format!("{}.tmp", file);
+  fs::write(&temp, binary::net_to_bytes(net, book))?;
+  fs::rename(&temp, file)?;
+  return Ok(());
+}
+
+// Load file, validate and convert book. Binary books are loaded as they are.
+fn load_runtime_book(file: &str, encoding: ast::Encoding) -> run::Book {
+  if let Ok(bytes) = fs::read(file) {
+    if binary::is_binary(&bytes) {
+      match binary::book_from_bytes(&bytes) {
+        Ok(book) => return book,
+        Err(err) => {
+          eprintln!("{}", err);
+          std::process::exit(1);
+        }
+      }
+    }
+  }
+  let book = load_book(file, encoding);
+  if let Err(errs) = ast::validate_book(&book) {
+    for err in errs {
+      eprintln!("{}", err);
+    }
+    std::process::exit(1);
+  }
+  match ast::book_to_runtime(&book) {
+    Ok(book) => book,
+    Err(err) => {
+      eprintln!("{}", err);
+      std::process::exit(1);
+    }
+  }
+}
+
+// Load file and parse book
+fn load_book(file: &str, encoding: ast::Encoding) -> ast::Book {
     let Ok(file) = fs::read_to_string(file) else {
         eprintln!("Input file not found");
         std::process::exit(1);
     };
-  let book = ast::book_to_runtime(&ast::do_parse_book(&file));
-  let mut net = run::Net::new(&data);
-  net.boot(ast::name_to_val("main"));
-  return (book, net);
+  match ast::do_parse_book_with_encoding(&file, encoding) {
+    Ok(book) => book,
+    Err(err) => {
+      eprintln!("{}", err.render(&file));
+      std::process::exit(1);
+    }
+  }
 }
 
-pub fn compile_book_to_rust_crate(f_name: &str, book: &run::Book) -> Result<(), std::io::Error> {
-  let fns_rs = jit::compile_book(book);
+pub fn compile_book_to_rust_crate(f_name: &str, book: &run::Book, tco: bool) -> Result<(), std::io::Error> {
+  let fns_rs = jit::compile_book(book, tco);
   let outdir = ".hvm";
   if std::path::Path::new(&outdir).exists() {
     fs::remove_dir_all(&outdir)?;
@@ -128,10 +552,21 @@ pub fn compile_book_to_rust_crate(f_name: &str, book: &run::Book) -> Result<(),
   fs::create_dir_all(&format!("{}/src", outdir))?;
   fs::write(".hvm/Cargo.toml", cargo_toml)?;
   fs::write(".hvm/src/ast.rs", include_str!("../src/ast.rs"))?;
+  fs::write(".hvm/src/binary.rs", include_str!("../src/binary.rs"))?;
+  fs::write(".hvm/src/check.rs", include_str!("../src/check.rs"))?;
+  fs::write(".hvm/src/cst.rs", include_str!("../src/cst.rs"))?;
+  fs::write(".hvm/src/debug.rs", include_str!("../src/debug.rs"))?;
+  fs::write(".hvm/src/dot.rs", include_str!("../src/dot.rs"))?;
+  fs::write(".hvm/src/f60.rs", include_str!("../src/f60.rs"))?;
+  fs::write(".hvm/src/fmt.rs", include_str!("../src/fmt.rs"))?;
+  fs::write(".hvm/src/i60.rs", include_str!("../src/i60.rs"))?;
+  fs::write(".hvm/src/io.rs", include_str!("../src/io.rs"))?;
   fs::write(".hvm/src/jit.rs", include_str!("../src/jit.rs"))?;
   fs::write(".hvm/src/lib.rs", include_str!("../src/lib.rs"))?;
   fs::write(".hvm/src/main.rs", include_str!("../src/main.rs"))?;
+  fs::write(".hvm/src/profile.rs", include_str!("../src/profile.rs"))?;
   fs::write(".hvm/src/run.rs", include_str!("../src/run.rs"))?;
+  fs::write(".hvm/src/trace.rs", include_str!("../src/trace.rs"))?;
   fs::write(".hvm/src/u60.rs", include_str!("../src/u60.rs"))?;
   fs::write(".hvm/src/fns.rs", fns_rs)?;
   return Ok(());
@@ -147,6 +582,62 @@ pub fn compile_rust_crate_to_executable(f_name: &str) -> Result<(), std::io::Err
   return Ok(());
 }
 
+// Generates a library crate, in a directory named after the file, which depends on the hvm-core
+// this was built from (see 'jit::compile_lib'). Regenerating it keeps its build directory.
+pub fn compile_book_to_rust_lib(f_name: &str, book: &run::Book, tco: bool) -> Result<(), std::io::Error> {
+  let lib_rs = jit::compile_lib(book, tco);
+  let outdir = lib_dir(f_name)?;
+  let mut cargo_toml = String::new();
+  cargo_toml.push_str("[package]\n");
+  cargo_toml.push_str(&format!("name = \"{}\"\n", lib_name(f_name)));
+  cargo_toml.push_str("version = \"0.1.0\"\n");
+  cargo_toml.push_str("edition = \"2021\"\n");
+  cargo_toml.push_str("\n");
+  cargo_toml.push_str("[lib]\n");
+  cargo_toml.push_str("crate-type = [\"rlib\", \"cdylib\"]\n");
+  cargo_toml.push_str("\n");
+  cargo_toml.push_str("[dependencies]\n");
+  // Compiled code uses the runtime's internals, which releases don't keep stable, so it depends on
+  // this source tree.
+  cargo_toml.push_str(&format!("hvm-core = {{ path = {:?} }}\n", env!("CARGO_MANIFEST_DIR")));
+  fs::create_dir_all(outdir.join("src"))?;
+  fs::write(outdir.join("Cargo.toml"), cargo_toml)?;
+  fs::write(outdir.join("src/lib.rs"), lib_rs)?;
+  fs::write(outdir.join("src/book.bin"), binary::book_to_bytes(book))?;
+  return Ok(());
+}
+
+// Builds a generated library crate, copying its dynamic library next to the file.
+pub fn compile_rust_lib_to_cdylib(f_name: &str) -> Result<(), std::io::Error> {
+  let outdir = lib_dir(f_name)?;
+  let output = std::process::Command::new("cargo").current_dir(&outdir).arg("build").arg("--release").output()?;
+  if !output.status.success() {
+    eprintln!("{}", String::from_utf8_lossy(&output.stderr));
+    std::process::exit(1);
+  }
+  let lib = format!("{}{}{}", std::env::consts::DLL_PREFIX, lib_name(f_name), std::env::consts::DLL_SUFFIX);
+  fs::copy(outdir.join("target/release").join(&lib), outdir.with_file_name(&lib))?;
+  return Ok(());
+}
+
+// The directory of a generated library: the file's path, without its extension. Files without an
+// extension are rejected, since the directory would take their place.
+fn lib_dir(f_name: &str) -> Result<std::path::PathBuf, std::io::Error> {
+  let path = std::path::Path::new(f_name);
+  let outdir = path.with_extension("");
+  if path.file_stem().is_none() || outdir == path {
+    let msg = format!("can't name a library directory after '{}', which has no extension", f_name);
+    return Err(std::io::Error::new(std::io::ErrorKind::InvalidInput, msg));
+  }
+  return Ok(outdir);
+}
+
+// The crate name of a generated library: the file name, without characters crates can't have.
+fn lib_name(f_name: &str) -> String {
+  let stem = std::path::Path::new(f_name).file_stem().unwrap_or_default().to_string_lossy();
+  return stem.chars().map(|c| if c.is_ascii_alphanumeric() { c } else { '_' }).collect();
+}
+
 // TODO: move to hvm-cuda repo
 pub fn gen_cuda_book(book: &run::Book) -> String {
   use std::collections::BTreeMap;
@@ -164,7 +655,7 @@ pub fn gen_cuda_book(book: &run::Book) -> String {
 
   // Generate function ids
   for (i, id) in defs.keys().enumerate() {
-    code.push_str(&format!("const u32 F_{} = 0x{:x};\n", crate::ast::val_to_name(**id), id));
+    code.push_str(&format!("const u32 F_{} = 0x{:x};\n", book.syms.show(**id), id));
   }
   code.push_str("\n");
 
@@ -176,7 +667,7 @@ pub fn gen_cuda_book(book: &run::Book) -> String {
     let node_len = net.node.len();
     let rdex_len = net.rdex.len();
 
-    code.push_str(&format!("  // @{}\n", crate::ast::val_to_name(**id)));
+    code.push_str(&format!("  // @{}\n", book.syms.show(**id)));
 
     // Collect all pointers from root, nodes and rdex into a single buffer
     code.push_str(&format!("  // .nlen\n"));
@@ -217,7 +708,7 @@ pub fn gen_cuda_book(book: &run::Book) -> String {
 
   let mut index = 0;
   for (i, fid) in defs.keys().enumerate() {
-    code.push_str(&format!("  0x{:08X}, 0x{:08X}, // @{}\n", fid, index, crate::ast::val_to_name(**fid)));
+    code.push_str(&format!("  0x{:08X}, 0x{:08X}, // @{}\n", fid, index, book.syms.show(**fid)));
     index += 2 + 2 * defs[fid].node.len() as u32 + 2 * defs[fid].rdex.len() as u32;
   }
 
@@ -0,0 +1,253 @@
+// Per-definition profiling
+// ------------------------
+// This file attributes the work of a reduction to the definitions of the book. While a net has a
+// 'Profiler', every node remembers the call stack it was allocated under: a dereference in
+// 'Net::call' pushes its definition on the stack of the node it interacts with, and the nodes it
+// allocates (and the nodes later allocated by their interactions) get that stack. Each interaction
+// is then attributed to the stack of the nodes it involves; dereferences to the stack they push.
+// Head REFs start from the empty stack, and interactions between unboxed pointers, which have no
+// stack, are left unattributed.
+//
+// Recursion is folded: pushing a definition that is already on a stack returns to its frame, so
+// a recursive definition is a single frame, and a long reduction has few distinct stacks. Stacks
+// are interned in a table shared by all threads, and the counters of each thread are summed once
+// the reduction ends. A 'Profile' shows them as a table of definitions, sorted by the rewrites they
+// are responsible for, or as folded stacks ('main;sum;add 42'), which flamegraph tools accept.
+
+use crate::ast;
+use crate::run::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicU32, Ordering};
+use std::sync::{Arc, Mutex};
+
+// The counters of a stack.
+#[derive(Copy, Clone, Debug, Default, Eq, PartialEq)]
+pub struct Stats {
+  pub drefs: usize, // dereferences that pushed this stack
+  pub nodes: usize, // nodes those dereferences allocated
+  pub rwts : usize, // rewrites attributed to this stack, including its dereferences
+}
+
+// The counters of a definition, over every stack it leads.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct DefStats {
+  pub name : String,
+  pub drefs: usize, // times it was dereferenced
+  pub nodes: usize, // nodes its dereferences allocated
+  pub rwts : usize, // rewrites on its own nodes
+  pub total: usize, // rewrites on its own nodes, and on the nodes of all it called
+}
+
+// Interned stacks. A stack is its top frame: a definition, on top of a parent stack.
+struct Stacks {
+  frames: Vec<(u32, Val)>, // parent and definition of each stack, the first being the empty one
+  ids: HashMap<(u32, Val), u32>, // stack of each push
+}
+
+// The empty stack.
+pub const EMPTY: u32 = 0;
+
+// Profiles the steps of one thread.
+pub struct Profiler {
+  stacks: Arc<Mutex<Stacks>>, // shared by all threads
+  origin: Arc<[AtomicU32]>, // stack each heap node was allocated under, shared by all threads
+  cache: HashMap<(u32, Val), u32>, // pushes this thread already interned
+  pub stack: u32, // stack of the current step
+  pub stats: Vec<Stats>, // counters of each stack, by id
+}
+
+// A finished profile.
+#[derive(Clone, Debug)]
+pub struct Profile {
+  pub frames: Vec<(u32, Val)>, // parent and definition of each stack, as in 'Stacks'
+  pub stats: Vec<Stats>, // counters of each stack, by id
+  pub syms: ast::Symbols, // names of the definitions, from the profiled book
+}
+
+impl Profiler {
+  // A profiler for a heap with the given number of nodes.
+  pub fn new(size: usize) -> Self {
+    let stacks = Stacks { frames: vec![(EMPTY, 0)], ids: HashMap::new() };
+    Profiler {
+      stacks: Arc::new(Mutex::new(stacks)),
+      origin: (0 .. size).map(|_| AtomicU32::new(EMPTY)).collect(),
+      cache: HashMap::new(),
+      stack: EMPTY,
+      stats: vec![],
+    }
+  }
+
+  // A profiler for a child thread, sharing the stacks and the heap origins.
+  pub fn fork(&self) -> Self {
+    Profiler {
+      stacks: Arc::clone(&self.stacks),
+      origin: Arc::clone(&self.origin),
+      cache: self.cache.clone(),
+      stack: EMPTY,
+      stats: vec![],
+    }
+  }
+
+  // Adds the counters of a child thread's profiler.
+  pub fn join(&mut self, child: Profiler) {
+    for (id, stats) in child.stats.into_iter().enumerate() {
+      let own = self.get(id as u32);
+      own.drefs += stats.drefs;
+      own.nodes += stats.nodes;
+      own.rwts  += stats.rwts;
+    }
+  }
+
+  fn get(&mut self, stack: u32) -> &mut Stats {
+    if self.stats.len() <= stack as usize {
+      self.stats.resize(stack as usize + 1, Stats::default());
+    }
+    return &mut self.stats[stack as usize];
+  }
+
+  // The stack of a definition pushed on another, folding recursion.
+  fn push(&mut self, parent: u32, def: Val) -> u32 {
+    if let Some(id) = self.cache.get(&(parent, def)) {
+      return *id;
+    }
+    let mut stacks = self.stacks.lock().unwrap();
+    let id = match stacks.ids.get(&(parent, def)) {
+      Some(id) => *id,
+      None => {
+        let mut frame = parent;
+        while frame != EMPTY && stacks.frames[frame as usize].1 != def {
+          frame = stacks.frames[frame as usize].0;
+        }
+        let id = if frame != EMPTY {
+          frame
+        } else {
+          stacks.frames.push((parent, def));
+          (stacks.frames.len() - 1) as u32
+        };
+        stacks.ids.insert((parent, def), id);
+        id
+      }
+    };
+    self.cache.insert((parent, def), id);
+    return id;
+  }
+
+  // Starts an interaction, attributing it to the stack of its nodes. Calls are attributed by
+  // 'call', to the stack they push.
+  #[inline(always)]
+  pub fn interact(&mut self, a: Ptr, b: Ptr) {
+    let node = if a.is_nod() { a } else { b };
+    self.stack = if node.is_nod() { self.origin[node.loc() as usize].load(Ordering::Relaxed) } else { EMPTY };
+    let call = a.is_ref() && b.is_nod() || b.is_ref() && a.is_nod();
+    if !call {
+      self.get(self.stack).rwts += 1;
+    }
+  }
+
+  // Starts the expansion of a head REF.
+  #[inline(always)]
+  pub fn expand(&mut self) {
+    self.stack = EMPTY;
+  }
+
+  // Dereferences a definition, pushing it on the current stack.
+  #[inline(always)]
+  pub fn call(&mut self, def: Val) {
+    self.stack = self.push(self.stack, def);
+    let stats = self.get(self.stack);
+    stats.drefs += 1;
+    stats.rwts  += 1;
+  }
+
+  // Counts the nodes allocated by the current dereference.
+  #[inline(always)]
+  pub fn nodes(&mut self, count: usize) {
+    self.get(self.stack).nodes += count;
+  }
+
+  // Marks a node as allocated under the current stack.
+  #[inline(always)]
+  pub fn alloc(&mut self, loc: Loc) {
+    self.origin[loc as usize].store(self.stack, Ordering::Relaxed);
+  }
+
+  // Ends the profile of a reduction of the given book.
+  pub fn finish(self, book: &Book) -> Profile {
+    let frames = self.stacks.lock().unwrap().frames.clone();
+    let mut stats = self.stats;
+    stats.resize(std::cmp::max(stats.len(), frames.len()), Stats::default());
+    return Profile { frames, stats, syms: book.syms.clone() };
+  }
+}
+
+impl Profile {
+  // The definitions of a stack, from the bottom.
+  pub fn defs_of(&self, stack: u32) -> Vec<Val> {
+    let mut defs = vec![];
+    let mut frame = stack;
+    while frame != EMPTY {
+      defs.push(self.frames[frame as usize].1);
+      frame = self.frames[frame as usize].0;
+    }
+    defs.reverse();
+    return defs;
+  }
+
+  // Rewrites that couldn't be attributed to any definition.
+  pub fn unattributed(&self) -> usize {
+    return self.stats[EMPTY as usize].rwts;
+  }
+
+  // The counters of each definition, sorted by total rewrites, most first.
+  pub fn defs(&self) -> Vec<DefStats> {
+    let mut defs: HashMap<Val, DefStats> = HashMap::new();
+    for stack in 1 .. self.frames.len() as u32 {
+      let stats = self.stats[stack as usize];
+      let path = self.defs_of(stack);
+      for (i, def) in path.iter().enumerate() {
+        let entry = defs.entry(*def).or_insert_with(|| {
+          DefStats { name: self.syms.show(*def), drefs: 0, nodes: 0, rwts: 0, total: 0 }
+        });
+        // Folding keeps definitions unique on a stack, so its rewrites are counted once per definition.
+        entry.total += stats.rwts;
+        if i == path.len() - 1 {
+          entry.drefs += stats.drefs;
+          entry.nodes += stats.nodes;
+          entry.rwts  += stats.rwts;
+        }
+      }
+    }
+    let mut defs = defs.into_values().collect::<Vec<_>>();
+    defs.sort_by(|a, b| b.total.cmp(&a.total).then(b.rwts.cmp(&a.rwts)).then(a.name.cmp(&b.name)));
+    return defs;
+  }
+
+  // Shows the counters of each definition as a table.
+  pub fn show(&self) -> String {
+    let defs = self.defs();
+    let width = defs.iter().map(|def| def.name.len() + 1).max().unwrap_or(0).max(3);
+    let mut out = format!("{:<width$}  {:>10}  {:>10}  {:>12}  {:>12}\n", "DEF", "DREF", "NODES", "RWTS", "TOTAL", width = width);
+    for def in &defs {
+      let name = format!("@{}", def.name);
+      out.push_str(&format!("{:<width$}  {:>10}  {:>10}  {:>12}  {:>12}\n", name, def.drefs, def.nodes, def.rwts, def.total, width = width));
+    }
+    if self.unattributed() > 0 {
+      out.push_str(&format!("({} rewrites couldn't be attributed to a definition)\n", self.unattributed()));
+    }
+    return out;
+  }
+
+  // Shows the rewrites of each stack in the folded format of flamegraph tools.
+  pub fn folded(&self) -> String {
+    let mut lines = vec![];
+    for stack in 1 .. self.frames.len() as u32 {
+      let rwts = self.stats[stack as usize].rwts;
+      if rwts > 0 {
+        let names = self.defs_of(stack).into_iter().map(|def| self.syms.show(def)).collect::<Vec<_>>();
+        lines.push(format!("{} {}\n", names.join(";"), rwts));
+      }
+    }
+    lines.sort();
+    return lines.concat();
+  }
+}
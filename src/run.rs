@@ -7,10 +7,13 @@
 // they interact with nodes, and are cleared when they interact with ERAs, allowing for constant
 // space evaluation of recursive functions on Scott encoded datatypes.
 
-use std::sync::atomic::{AtomicU64, AtomicUsize, Ordering};
+use std::sync::atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering};
 use std::sync::{Arc, Barrier};
+use crossbeam_deque::{Steal, Stealer, Worker};
 use std::collections::HashMap;
 use crate::u60;
+use crate::i60;
+use crate::f60;
 
 pub type Tag  = u8;
 pub type Lab  = u32;
@@ -25,13 +28,15 @@ pub const RD1: Tag = 0x2; // Redirect to aux port 1
 pub const RD2: Tag = 0x3; // Redirect to aux port 2
 pub const REF: Tag = 0x4; // Lazy closed net
 pub const ERA: Tag = 0x5; // Unboxed eraser
-pub const NUM: Tag = 0x6; // Unboxed number
-pub const OP2: Tag = 0x7; // Binary numeric operation
-pub const OP1: Tag = 0x8; // Unary numeric operation
-pub const MAT: Tag = 0x9; // Numeric pattern-matching
-pub const LAM: Tag = 0xA; // Main port of lam node
-pub const TUP: Tag = 0xB; // Main port of tup node
-pub const DUP: Tag = 0xC; // Main port of dup node
+pub const NUM: Tag = 0x6; // Unboxed u60 number
+pub const INT: Tag = 0x7; // Unboxed i60 number
+pub const FLT: Tag = 0x8; // Unboxed f60 number
+pub const OP2: Tag = 0x9; // Binary numeric operation
+pub const OP1: Tag = 0xA; // Unary numeric operation
+pub const MAT: Tag = 0xB; // Numeric pattern-matching
+pub const LAM: Tag = 0xC; // Main port of lam node
+pub const TUP: Tag = 0xD; // Main port of tup node
+pub const DUP: Tag = 0xE; // Main port of dup node
 pub const END: Tag = 0xE; // Last pointer tag
 
 // Numeric operations.
@@ -52,6 +57,35 @@ pub const XOR: Lab = 0x0D; // logical-xor
 pub const LSH: Lab = 0x0E; // left-shift
 pub const RSH: Lab = 0x0F; // right-shift
 pub const NOT: Lab = 0x10; // logical-not
+pub const MIN: Lab = 0x11; // minimum
+pub const MAX: Lab = 0x12; // maximum
+pub const POW: Lab = 0x13; // exponentiation
+pub const ROL: Lab = 0x14; // rotate-left
+pub const ROR: Lab = 0x15; // rotate-right
+pub const POP: Lab = 0x16; // population count (number of set bits)
+pub const CLZ: Lab = 0x17; // count leading zeros
+pub const CTZ: Lab = 0x18; // count trailing zeros
+
+// Numeric types, on the high bits of operation labels. Numbers are 60-bit words, tagged with their
+// type only so that they can be read back; the operation decides how to read them. Comparisons and
+// bit counts always return an u60, and other operations a number of their type.
+pub const U60: Lab = 0x000; // unsigned integers
+pub const I60: Lab = 0x100; // signed integers, in two's complement
+pub const F60: Lab = 0x200; // floats (an f64 without the 4 lowest bits of its mantissa)
+pub const OPS: Lab = 0x0FF; // operation bits of a label
+
+// Whether an OP1/OP2 label has a known numeric type and operation.
+pub fn is_op(lab: Lab) -> bool {
+  return matches!(lab & !OPS, U60 | I60 | F60) && lab & OPS <= CTZ;
+}
+
+// The numeric type of the result of an operation.
+pub fn result_type(lab: Lab) -> Lab {
+  match lab & OPS {
+    EQ | NE | LT | GT | LTE | GTE | POP | CLZ | CTZ => U60,
+    _ => lab & !OPS,
+  }
+}
 
 pub const ERAS: Ptr = Ptr::new(ERA, 0, 0);
 pub const ROOT: Ptr = Ptr::new(VR2, 0, 0);
@@ -110,16 +144,99 @@ pub struct Area {
   pub size: usize, // total nodes in area
 }
 
+// An allocation failure: a full sweep of the thread's area found no free node.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct OutOfMemory {
+  pub tid : usize, // thread id
+  pub init: usize, // first index of the exhausted area
+  pub size: usize, // total nodes in the exhausted area
+}
+
+// An invalid redex: an active pair that no interaction rule applies to.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct InteractionError {
+  pub a    : Ptr, // first pointer of the pair
+  pub b    : Ptr, // second pointer of the pair
+  pub rule : &'static str, // why the pair can't interact
+  pub trees: Option<(String, String)>, // both trees, read back once reduction stops
+}
+
+// An undefined numeric operation, like a division by zero, reported on strict nets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ArithmeticError {
+  pub opr  : Lab, // operator label
+  pub lft  : Val, // left operand
+  pub rgt  : Val, // right operand
+  pub rule : &'static str, // why the operation is undefined
+  pub a    : Ptr, // first pointer of the pair
+  pub b    : Ptr, // second pointer of the pair
+  pub trees: Option<(String, String)>, // both trees, read back once reduction stops
+}
+
+// A failed effect, like reading a missing file.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct IoError {
+  pub call   : &'static str, // name of the IO call
+  pub message: String, // what went wrong
+}
+
+// A failure of a thread of a parallel reduction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ThreadError {
+  pub tid: usize, // thread id
+  pub err: RuntimeError, // why it stopped
+}
+
+// A failure that stops the reduction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RuntimeError {
+  OutOfMemory(OutOfMemory),
+  Interaction(InteractionError),
+  Arithmetic(ArithmeticError),
+  Io(IoError),
+  Threads(Vec<ThreadError>), // every thread that failed, in thread order
+}
+
+// Runtime configuration.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RuntimeConfig {
+  pub threads: usize, // thread count
+  pub heap: usize, // heap size, in nodes
+  pub share_limit: usize, // max share redexes per split
+  pub local_limit: usize, // max local rewrites per epoch
+  pub scheduler: Scheduler, // how threads share redexes
+}
+
+// How 'parallel_normal' shares redexes between threads.
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+pub enum Scheduler {
+  Split, // threads meet on barriers every epoch, and exchange redexes pairwise, in a butterfly pattern
+  Steal, // threads keep their redexes on deques, and idle ones steal batches from busy ones
+}
+
+// Rewrites a thread performs between shares, on the work-stealing scheduler.
+pub const STEAL_CHUNK: usize = 1 << 10;
+
+// Iterations a compiled tail-recursive loop performs, and redexes it leaves behind, before yielding
+// its call back to 'rdex'. The whole loop counts as a single interaction to the schedulers, so it
+// must be short enough for them to share those redexes, but long enough to amortize the yield.
+pub const TAIL_LIMIT: usize = 1 << 6;
+
 // A interaction combinator net.
 pub struct Net<'a> {
   pub tid : usize, // thread id
   pub tids: usize, // thread count
   pub heap: Heap<'a>, // nodes
   pub rdex: Vec<(Ptr,Ptr)>, // redexes
+  pub wait: Vec<(Ptr,Ptr)>, // IO calls waiting for their arguments, retried once no redex is left
   pub locs: Vec<Loc>,
   pub area: Area, // allocation area
   pub next: usize, // next allocation index within area
+  pub ends: Option<(usize, Vec<usize>)>, // own and threads' allocation indices after the last parallel run
   pub rwts: Rewrites, // rewrite count
+  pub strict: bool, // whether undefined numeric operations stop the reduction
+  pub tracer: Option<crate::trace::Tracer>, // records the steps of the reduction, if tracing
+  pub profiler: Option<crate::profile::Profiler>, // attributes the reduction to definitions, if profiling
 }
 
 // A compact closed net, used for dereferences.
@@ -130,9 +247,22 @@ pub struct Def {
   pub node: Vec<(Ptr, Ptr)>,
 }
 
+// A native definition. It receives the REF being expanded and the port it interacts with, and
+// returns whether it handled the interaction. If not, the REF expands to its definition.
+pub type Native = Box<dyn Fn(&mut Net, Ptr, Trg) -> bool + Send + Sync>;
+
+// Dispatches REFs to compiled definitions, like the 'call_native' of an executable's 'fns.rs', but
+// for books compiled to a library (see 'jit::compile_lib'). It receives the REF being expanded and
+// the port it interacts with, and returns whether it handled the interaction.
+pub type CallNative = fn(&mut Net, &Book, Ptr, Ptr) -> Result<bool, RuntimeError>;
+
 // A map of id to definitions (closed nets).
 pub struct Book {
   pub defs: HashMap<Val, Def, nohash_hasher::BuildNoHashHasher<Val>>,
+  pub natives: HashMap<Val, Native, nohash_hasher::BuildNoHashHasher<Val>>,
+  pub io: Option<crate::io::Io>, // handles the IO calls, if effects are enabled
+  pub call_native: Option<CallNative>, // dispatches to the definitions of a compiled library, if loaded
+  pub syms: crate::ast::Symbols, // names of the definition ids
 }
 
 impl Ptr {
@@ -146,6 +276,16 @@ impl Ptr {
     Ptr((val << 4) | (tag as Val))
   }
 
+  // A number of a numeric type ('U60', 'I60' or 'F60').
+  #[inline(always)]
+  pub const fn num(typ: Lab, val: Val) -> Self {
+    match typ {
+      I60 => Ptr::big(INT, val),
+      F60 => Ptr::big(FLT, val),
+      _   => Ptr::big(NUM, val),
+    }
+  }
+
   #[inline(always)]
   pub const fn tag(&self) -> Tag {
     (self.0 & 0xF) as Tag
@@ -208,7 +348,17 @@ impl Ptr {
 
   #[inline(always)]
   pub fn is_num(&self) -> bool {
-    return matches!(self.tag(), NUM);
+    return matches!(self.tag(), NUM..=FLT);
+  }
+
+  // The numeric type of a number.
+  #[inline(always)]
+  pub fn num_type(&self) -> Lab {
+    match self.tag() {
+      INT => I60,
+      FLT => F60,
+      _   => U60,
+    }
   }
 
   #[inline(always)]
@@ -223,7 +373,7 @@ impl Ptr {
 
   #[inline(always)]
   pub fn is_skp(&self) -> bool {
-    return matches!(self.tag(), ERA | NUM | REF);
+    return matches!(self.tag(), ERA | NUM..=FLT | REF);
   }
 
   #[inline(always)]
@@ -277,6 +427,10 @@ impl Book {
   pub fn new() -> Self {
     Book {
       defs: HashMap::with_hasher(std::hash::BuildHasherDefault::default()),
+      natives: HashMap::with_hasher(std::hash::BuildHasherDefault::default()),
+      io: None,
+      call_native: None,
+      syms: crate::ast::Symbols::new(),
     }
   }
 
@@ -285,12 +439,122 @@ impl Book {
     self.defs.insert(name, def);
   }
 
+  // Registers a native implementation of a definition, consulted before expanding it. A native that
+  // may decline an interaction needs a definition to fall back to.
+  pub fn register_native(&mut self, name: &str, fun: impl Fn(&mut Net, Ptr, Trg) -> bool + Send + Sync + 'static) {
+    let fid = self.syms.intern(name);
+    self.natives.insert(fid, Box::new(fun));
+  }
+
+  // Enables the IO calls (see 'io'), performing their effects with the given handler.
+  pub fn set_io(&mut self, handler: impl crate::io::IoHandler + 'static) {
+    self.io = Some(crate::io::Io::new(handler, &mut self.syms));
+  }
+
   #[inline(always)]
   pub fn get(&self, name: Val) -> Option<&Def> {
     self.defs.get(&name)
   }
 }
 
+impl std::fmt::Display for OutOfMemory {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(f, "out of memory: thread {} has no free node in area [{}, {})", self.tid, self.init, self.init + self.size)
+  }
+}
+
+impl std::error::Error for OutOfMemory {}
+
+impl std::fmt::Display for InteractionError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match &self.trees {
+      Some((a, b)) => write!(f, "invalid interaction ({}): {} ~ {}", self.rule, a, b),
+      None         => write!(f, "invalid interaction ({}): {:016x} ~ {:016x}", self.rule, self.a.0, self.b.0),
+    }
+  }
+}
+
+impl std::error::Error for InteractionError {}
+
+impl std::fmt::Display for ArithmeticError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match &self.trees {
+      Some((a, b)) => write!(f, "arithmetic error ({}): {} ~ {}", self.rule, a, b),
+      None         => write!(f, "arithmetic error ({}): {:016x} ~ {:016x}", self.rule, self.a.0, self.b.0),
+    }
+  }
+}
+
+impl std::error::Error for ArithmeticError {}
+
+impl std::fmt::Display for IoError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(f, "IO error (@{}): {}", self.call, self.message)
+  }
+}
+
+impl std::error::Error for IoError {}
+
+impl std::fmt::Display for ThreadError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(f, "thread {}: {}", self.tid, self.err)
+  }
+}
+
+impl std::error::Error for ThreadError {}
+
+impl std::fmt::Display for RuntimeError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    match self {
+      RuntimeError::OutOfMemory(err) => err.fmt(f),
+      RuntimeError::Interaction(err) => err.fmt(f),
+      RuntimeError::Arithmetic(err)  => err.fmt(f),
+      RuntimeError::Io(err)          => err.fmt(f),
+      RuntimeError::Threads(errs)    => write!(f, "{}", errs.iter().map(|err| err.to_string()).collect::<Vec<_>>().join("\n")),
+    }
+  }
+}
+
+impl std::error::Error for RuntimeError {}
+
+impl From<OutOfMemory> for RuntimeError {
+  fn from(err: OutOfMemory) -> Self {
+    RuntimeError::OutOfMemory(err)
+  }
+}
+
+impl From<InteractionError> for RuntimeError {
+  fn from(err: InteractionError) -> Self {
+    RuntimeError::Interaction(err)
+  }
+}
+
+impl From<ArithmeticError> for RuntimeError {
+  fn from(err: ArithmeticError) -> Self {
+    RuntimeError::Arithmetic(err)
+  }
+}
+
+impl From<IoError> for RuntimeError {
+  fn from(err: IoError) -> Self {
+    RuntimeError::Io(err)
+  }
+}
+
+impl Default for RuntimeConfig {
+  // Uses the available cores (rounded down to a power of two) and a 4 GB heap.
+  fn default() -> Self {
+    let cores = std::thread::available_parallelism().map_or(1, |n| n.get());
+    RuntimeConfig {
+      threads: 1 << cores.ilog2(),
+      heap: 1 << 28,
+      share_limit: 1 << 12,
+      local_limit: 1 << 18,
+      scheduler: Scheduler::Split,
+    }
+  }
+}
+
 impl Def {
   pub fn new() -> Self {
     Def {
@@ -417,10 +681,15 @@ impl<'a> Net<'a> {
       tids: 1,
       heap: Heap { data },
       rdex: vec![],
+      wait: vec![],
       locs: vec![0; 1 << 16],
       area: Area { init: 0, size: data.len() },
       next: 0,
+      ends: None,
       rwts: Rewrites::new(),
+      strict: false,
+      tracer: None,
+      profiler: None,
     }
   }
 
@@ -435,16 +704,22 @@ impl<'a> Net<'a> {
   }
 
   #[inline(always)]
-  pub fn alloc(&mut self) -> Loc {
+  pub fn alloc(&mut self) -> Result<Loc, OutOfMemory> {
     // On the first pass, just alloc without checking.
     // Note: we add 1 to avoid overwritting root.
-    let index = if self.next < self.area.size - 1 {
+    let index = if self.next + 1 < self.area.size {
       self.next += 1;
       self.area.init as Loc + self.next as Loc
-    // On later passes, search for an available slot.
+    // On later passes, search for an available slot, giving up after a full sweep.
     } else {
+      let mut tries = 0;
       loop {
-        self.next += 1;
+        if tries >= self.area.size {
+          return Err(OutOfMemory { tid: self.tid, init: self.area.init, size: self.area.size });
+        }
+        tries += 1;
+        // Stays in [size, 2 * size), visiting the same indices as counting up forever would.
+        self.next = self.area.size + (self.next + 1) % self.area.size;
         let index = (self.area.init + self.next % self.area.size) as Loc;
         if self.heap.get(index, P1).is_nil() && self.heap.get(index, P2).is_nil() {
           break index;
@@ -453,7 +728,10 @@ impl<'a> Net<'a> {
     };
     self.heap.set(index, P1, LOCK);
     self.heap.set(index, P2, LOCK);
-    index
+    if let Some(profiler) = &mut self.profiler {
+      profiler.alloc(index);
+    }
+    Ok(index)
   }
 
   // Gets a pointer's target.
@@ -689,44 +967,51 @@ impl<'a> Net<'a> {
   
   // Performs an interaction over a redex.
   #[inline(always)]
-  pub fn interact(&mut self, book: &Book, a: Ptr, b: Ptr) {
+  pub fn interact(&mut self, book: &Book, a: Ptr, b: Ptr) -> Result<(), RuntimeError> {
+    if let Some(tracer) = &mut self.tracer {
+      tracer.record(self.tid, crate::trace::Rule::of(a, b), a, b);
+    }
+    if let Some(profiler) = &mut self.profiler {
+      profiler.interact(a, b);
+    }
     match (a.tag(), b.tag()) {
-      (REF   , OP2..) => self.call(book, a, b),
-      (OP2.. , REF  ) => self.call(book, b, a),
-      (LAM.. , LAM..) if a.lab() == b.lab() => self.anni(a, b),
-      (LAM.. , LAM..) => self.comm(a, b),
-      (LAM.. , ERA  ) => self.era2(a),
-      (ERA   , LAM..) => self.era2(b),
-      (REF   , ERA  ) => self.rwts.eras += 1,
-      (ERA   , REF  ) => self.rwts.eras += 1,
-      (REF   , NUM  ) => self.rwts.eras += 1,
-      (NUM   , REF  ) => self.rwts.eras += 1,
-      (ERA   , ERA  ) => self.rwts.eras += 1,
-      (LAM.. , NUM  ) => self.copy(a, b),
-      (NUM   , LAM..) => self.copy(b, a),
-      (NUM   , ERA  ) => self.rwts.eras += 1,
-      (ERA   , NUM  ) => self.rwts.eras += 1,
-      (NUM   , NUM  ) => self.rwts.eras += 1,
-      (OP2   , NUM  ) => self.op2n(a, b),
-      (NUM   , OP2  ) => self.op2n(b, a),
-      (OP1   , NUM  ) => self.op1n(a, b),
-      (NUM   , OP1  ) => self.op1n(b, a),
-      (OP2   , LAM..) => self.comm(a, b),
-      (LAM.. , OP2  ) => self.comm(b, a),
-      (OP1   , LAM..) => self.pass(a, b),
-      (LAM.. , OP1  ) => self.pass(b, a),
-      (OP2   , ERA  ) => self.era2(a),
-      (ERA   , OP2  ) => self.era2(b),
-      (OP1   , ERA  ) => self.era1(a),
-      (ERA   , OP1  ) => self.era1(b),
-      (MAT   , NUM  ) => self.mtch(a, b),
-      (NUM   , MAT  ) => self.mtch(b, a),
-      (MAT   , LAM..) => self.comm(a, b),
-      (LAM.. , MAT  ) => self.comm(b, a),
-      (MAT   , ERA  ) => self.era2(a),
-      (ERA   , MAT  ) => self.era2(b),
-      _               => { println!("{:016x} {:016x}", a.0, b.0); unreachable!() },
+      (REF      , OP2..    ) => self.call(book, a, b)?,
+      (OP2..    , REF      ) => self.call(book, b, a)?,
+      (LAM..    , LAM..    ) if a.lab() == b.lab() => self.anni(a, b),
+      (LAM..    , LAM..    ) => self.comm(a, b)?,
+      (LAM..    , ERA      ) => self.era2(a),
+      (ERA      , LAM..    ) => self.era2(b),
+      (REF      , ERA      ) => self.rwts.eras += 1,
+      (ERA      , REF      ) => self.rwts.eras += 1,
+      (REF      , NUM..=FLT) => self.rwts.eras += 1,
+      (NUM..=FLT, REF      ) => self.rwts.eras += 1,
+      (ERA      , ERA      ) => self.rwts.eras += 1,
+      (LAM..    , NUM..=FLT) => self.copy(a, b),
+      (NUM..=FLT, LAM..    ) => self.copy(b, a),
+      (NUM..=FLT, ERA      ) => self.rwts.eras += 1,
+      (ERA      , NUM..=FLT) => self.rwts.eras += 1,
+      (NUM..=FLT, NUM..=FLT) => self.rwts.eras += 1,
+      (OP2      , NUM..=FLT) => self.op2n(a, b)?,
+      (NUM..=FLT, OP2      ) => self.op2n(b, a)?,
+      (OP1      , NUM..=FLT) => self.op1n(a, b)?,
+      (NUM..=FLT, OP1      ) => self.op1n(b, a)?,
+      (OP2      , LAM..    ) => self.comm(a, b)?,
+      (LAM..    , OP2      ) => self.comm(b, a)?,
+      (OP1      , LAM..    ) => self.pass(a, b)?,
+      (LAM..    , OP1      ) => self.pass(b, a)?,
+      (OP2      , ERA      ) => self.era2(a),
+      (ERA      , OP2      ) => self.era2(b),
+      (OP1      , ERA      ) => self.era1(a),
+      (ERA      , OP1      ) => self.era1(b),
+      (MAT      , NUM..=FLT) => self.mtch(a, b)?,
+      (NUM..=FLT, MAT      ) => self.mtch(b, a)?,
+      (MAT      , LAM..    ) => self.comm(a, b)?,
+      (LAM..    , MAT      ) => self.comm(b, a)?,
+      (MAT      , ERA      ) => self.era2(a),
+      (ERA      , MAT      ) => self.era2(b),
+      _                     => return Err(InteractionError { a, b, rule: "no rule for this pair", trees: None }.into()),
     };
+    Ok(())
   }
 
   pub fn anni(&mut self, a: Ptr, b: Ptr) {
@@ -739,12 +1024,12 @@ impl<'a> Net<'a> {
     self.atomic_link(a2, b2);
   }
 
-  pub fn comm(&mut self, a: Ptr, b: Ptr) {
+  pub fn comm(&mut self, a: Ptr, b: Ptr) -> Result<(), RuntimeError> {
     self.rwts.comm += 1;
-    let loc0 = self.alloc();
-    let loc1 = self.alloc();
-    let loc2 = self.alloc();
-    let loc3 = self.alloc();
+    let loc0 = self.alloc()?;
+    let loc1 = self.alloc()?;
+    let loc2 = self.alloc()?;
+    let loc3 = self.alloc()?;
     self.heap.set(loc0, P1, Ptr::new(VR1, 0, loc2));
     self.heap.set(loc0, P2, Ptr::new(VR1, 0, loc3));
     self.heap.set(loc1, P1, Ptr::new(VR2, 0, loc2));
@@ -761,6 +1046,7 @@ impl<'a> Net<'a> {
     self.half_atomic_link(a2, Ptr::new(b.tag(), b.lab(), loc1));
     let b2 = Ptr::new(VR2, 0, b.loc());
     self.half_atomic_link(b2, Ptr::new(a.tag(), a.lab(), loc3));
+    Ok(())
   }
 
   pub fn era2(&mut self, a: Ptr) {
@@ -777,11 +1063,11 @@ impl<'a> Net<'a> {
     self.half_atomic_link(a2, ERAS);
   }
 
-  pub fn pass(&mut self, a: Ptr, b: Ptr) {
+  pub fn pass(&mut self, a: Ptr, b: Ptr) -> Result<(), RuntimeError> {
     self.rwts.comm += 1;
-    let loc0 = self.alloc();
-    let loc1 = self.alloc();
-    let loc2 = self.alloc();
+    let loc0 = self.alloc()?;
+    let loc1 = self.alloc()?;
+    let loc2 = self.alloc()?;
     self.heap.set(loc0, P1, Ptr::new(VR2, 0, loc1));
     self.heap.set(loc0, P2, Ptr::new(VR2, 0, loc2));
     self.heap.set(loc1, P1, self.heap.get(a.loc(), P1));
@@ -794,6 +1080,7 @@ impl<'a> Net<'a> {
     self.half_atomic_link(b1, Ptr::new(a.tag(), b.lab(), loc1));
     let b2 = Ptr::new(VR2, 0, b.loc());
     self.half_atomic_link(b2, Ptr::new(a.tag(), b.lab(), loc2));
+    Ok(())
   }
 
   pub fn copy(&mut self, a: Ptr, b: Ptr) {
@@ -804,92 +1091,212 @@ impl<'a> Net<'a> {
     self.half_atomic_link(a2, b);
   }
 
-  pub fn mtch(&mut self, a: Ptr, b: Ptr) {
+  pub fn mtch(&mut self, a: Ptr, b: Ptr) -> Result<(), RuntimeError> {
     self.rwts.oper += 1;
     let a1 = Ptr::new(VR1, 0, a.loc()); // branch
     let a2 = Ptr::new(VR2, 0, a.loc()); // return
     if b.val() == 0 {
-      let loc0 = self.alloc();
+      let loc0 = self.alloc()?;
       self.heap.set(loc0, P2, ERAS);
       self.half_atomic_link(a1, Ptr::new(LAM, 0, loc0));
       self.half_atomic_link(a2, Ptr::new(VR1, 0, loc0));
     } else {
-      let loc0 = self.alloc();
-      let loc1 = self.alloc();
+      let loc0 = self.alloc()?;
+      let loc1 = self.alloc()?;
       self.heap.set(loc0, P1, ERAS);
       self.heap.set(loc0, P2, Ptr::new(LAM, 0, loc1));
-      self.heap.set(loc1, P1, Ptr::big(NUM, b.val() - 1));
+      self.heap.set(loc1, P1, Ptr::big(b.tag(), b.val() - 1));
       self.half_atomic_link(a1, Ptr::new(LAM, 0, loc0));
       self.half_atomic_link(a2, Ptr::new(VR2, 0, loc1));
     }
+    Ok(())
   }
 
-  pub fn op2n(&mut self, a: Ptr, b: Ptr) {
+  pub fn op2n(&mut self, a: Ptr, b: Ptr) -> Result<(), RuntimeError> {
     self.rwts.oper += 1;
-    let loc0 = self.alloc();
+    let loc0 = self.alloc()?;
     let a1 = Ptr::new(VR1, 0, a.loc());
     let a2 = Ptr::new(VR2, 0, a.loc());
     self.heap.set(loc0, P1, b);
     self.half_atomic_link(a2, Ptr::new(VR2, 0, loc0));
     self.half_atomic_link(a1, Ptr::new(OP1, a.lab(), loc0));
+    Ok(())
   }
 
-  pub fn op1n(&mut self, a: Ptr, b: Ptr) {
+  pub fn op1n(&mut self, a: Ptr, b: Ptr) -> Result<(), RuntimeError> {
+    let p1 = self.heap.get(a.loc(), P1);
+    if !p1.is_num() {
+      return Err(InteractionError { a, b, rule: "OP1 operand is not a number", trees: None }.into());
+    }
     self.rwts.oper += 1;
     let op = a.lab();
-    let v0 = self.heap.get(a.loc(), P1).val();
+    let v0 = p1.val();
     let v1 = b.val();
-    let v2 = self.op(op, v0, v1);
+    let v2 = self.checked_op(op, v0, v1, a, b)?;
     let a2 = Ptr::new(VR2, 0, a.loc());
-    self.half_atomic_link(a2, Ptr::big(NUM, v2));
+    self.half_atomic_link(a2, Ptr::num(result_type(op), v2));
+    Ok(())
+  }
+
+  // Performs a numeric operation on the operands of the redex 'x ~ y', failing if it's undefined and
+  // the net is strict. Otherwise, undefined operations have defined results (see 'u60').
+  #[inline(always)]
+  pub fn checked_op(&self, op: Lab, a: Val, b: Val, x: Ptr, y: Ptr) -> Result<Val, RuntimeError> {
+    if self.strict {
+      let rule = match op & OPS {
+        DIV | MOD if b == 0 && op & !OPS != F60 => Some("division by zero"),
+        LSH | RSH if b >= 60 => Some("shift amount out of range"),
+        _ => None,
+      };
+      if let Some(rule) = rule {
+        return Err(ArithmeticError { opr: op, lft: a, rgt: b, rule, a: x, b: y, trees: None }.into());
+      }
+    }
+    match self.op(op, a, b) {
+      Some(val) => Ok(val),
+      None      => Err(ArithmeticError { opr: op, lft: a, rgt: b, rule: "unknown operator", a: x, b: y, trees: None }.into()),
+    }
+  }
+
+  // Performs a numeric operation, or returns None if its label isn't a known type and operation.
+  #[inline(always)]
+  pub fn op(&self, op: Lab, a: Val, b: Val) -> Option<Val> {
+    match op & !OPS {
+      U60 => self.op_u60(op & OPS, a, b),
+      I60 => self.op_i60(op & OPS, a, b),
+      F60 => self.op_f60(op & OPS, a, b),
+      _   => None,
+    }
   }
 
   #[inline(always)]
-  pub fn op(&self, op: Lab, a: Val, b: Val) -> Val {
+  fn op_u60(&self, op: Lab, a: Val, b: Val) -> Option<Val> {
     match op {
-      ADD => { u60::add(a, b) }
-      SUB => { u60::sub(a, b) }
-      MUL => { u60::mul(a, b) }
-      DIV => { u60::div(a, b) }
-      MOD => { u60::rem(a, b) }
-      EQ  => { u60::eq(a, b) }
-      NE  => { u60::ne(a, b) }
-      LT  => { u60::lt(a, b) }
-      GT  => { u60::gt(a, b) }
-      LTE => { u60::lte(a, b) }
-      GTE => { u60::gte(a, b) }
-      AND => { u60::and(a, b) }
-      OR  => { u60::or(a, b) }
-      XOR => { u60::xor(a, b) }
-      NOT => { u60::not(a) }
-      LSH => { u60::lsh(a, b) }
-      RSH => { u60::rsh(a, b) }
-      _   => { unreachable!() }
+      ADD => { Some(u60::add(a, b)) }
+      SUB => { Some(u60::sub(a, b)) }
+      MUL => { Some(u60::mul(a, b)) }
+      DIV => { Some(u60::div(a, b)) }
+      MOD => { Some(u60::rem(a, b)) }
+      EQ  => { Some(u60::eq(a, b)) }
+      NE  => { Some(u60::ne(a, b)) }
+      LT  => { Some(u60::lt(a, b)) }
+      GT  => { Some(u60::gt(a, b)) }
+      LTE => { Some(u60::lte(a, b)) }
+      GTE => { Some(u60::gte(a, b)) }
+      AND => { Some(u60::and(a, b)) }
+      OR  => { Some(u60::or(a, b)) }
+      XOR => { Some(u60::xor(a, b)) }
+      NOT => { Some(u60::not(a)) }
+      LSH => { Some(u60::lsh(a, b)) }
+      RSH => { Some(u60::rsh(a, b)) }
+      MIN => { Some(u60::min(a, b)) }
+      MAX => { Some(u60::max(a, b)) }
+      POW => { Some(u60::pow(a, b)) }
+      ROL => { Some(u60::rol(a, b)) }
+      ROR => { Some(u60::ror(a, b)) }
+      POP => { Some(u60::pop(a)) }
+      CLZ => { Some(u60::clz(a)) }
+      CTZ => { Some(u60::ctz(a)) }
+      _   => { None }
+    }
+  }
+
+  // Rotations and bit counts on i60s act on their bits, as u60s.
+  #[inline(always)]
+  fn op_i60(&self, op: Lab, a: Val, b: Val) -> Option<Val> {
+    match op {
+      ADD => { Some(i60::add(a, b)) }
+      SUB => { Some(i60::sub(a, b)) }
+      MUL => { Some(i60::mul(a, b)) }
+      DIV => { Some(i60::div(a, b)) }
+      MOD => { Some(i60::rem(a, b)) }
+      EQ  => { Some(i60::eq(a, b)) }
+      NE  => { Some(i60::ne(a, b)) }
+      LT  => { Some(i60::lt(a, b)) }
+      GT  => { Some(i60::gt(a, b)) }
+      LTE => { Some(i60::lte(a, b)) }
+      GTE => { Some(i60::gte(a, b)) }
+      AND => { Some(i60::and(a, b)) }
+      OR  => { Some(i60::or(a, b)) }
+      XOR => { Some(i60::xor(a, b)) }
+      NOT => { Some(i60::not(a)) }
+      LSH => { Some(i60::lsh(a, b)) }
+      RSH => { Some(i60::rsh(a, b)) }
+      MIN => { Some(i60::min(a, b)) }
+      MAX => { Some(i60::max(a, b)) }
+      POW => { Some(i60::pow(a, b)) }
+      _   => { self.op_u60(op, a, b) }
+    }
+  }
+
+  // Bitwise operations on floats act on their bits, as u60s.
+  #[inline(always)]
+  fn op_f60(&self, op: Lab, a: Val, b: Val) -> Option<Val> {
+    match op {
+      ADD => { Some(f60::add(a, b)) }
+      SUB => { Some(f60::sub(a, b)) }
+      MUL => { Some(f60::mul(a, b)) }
+      DIV => { Some(f60::div(a, b)) }
+      MOD => { Some(f60::rem(a, b)) }
+      EQ  => { Some(f60::eq(a, b)) }
+      NE  => { Some(f60::ne(a, b)) }
+      LT  => { Some(f60::lt(a, b)) }
+      GT  => { Some(f60::gt(a, b)) }
+      LTE => { Some(f60::lte(a, b)) }
+      GTE => { Some(f60::gte(a, b)) }
+      MIN => { Some(f60::min(a, b)) }
+      MAX => { Some(f60::max(a, b)) }
+      POW => { Some(f60::pow(a, b)) }
+      _   => { self.op_u60(op, a, b) }
     }
   }
 
   // Expands a closed net.
   #[inline(always)]
-  pub fn call(&mut self, book: &Book, ptr: Ptr, trg: Ptr) {
+  pub fn call(&mut self, book: &Book, ptr: Ptr, trg: Ptr) -> Result<(), RuntimeError> {
     self.rwts.dref += 1;
     let mut ptr = ptr;
     // FIXME: change "while" to "if" once lang prevents refs from returning refs
     if ptr.is_ref() {
-      // Intercepts with a native function, if available.
-      if self.call_native(book, ptr, trg) {
-        return;
+      if let Some(profiler) = &mut self.profiler {
+        profiler.call(ptr.val());
+      }
+      // Intercepts with a registered native function, if available.
+      if let Some(native) = book.natives.get(&ptr.val()) {
+        if native(self, ptr, Trg::Ptr(trg)) {
+          return Ok(());
+        }
+      }
+      // Intercepts with a compiled library, if loaded.
+      if let Some(call_native) = book.call_native {
+        if call_native(self, book, ptr, trg)? {
+          return Ok(());
+        }
+      }
+      // Intercepts with a compiled native function, if available.
+      if self.call_native(book, ptr, trg)? {
+        return Ok(());
       }
       // Load the closed net.
       //println!("{:08x?}", book.defs);
-      //println!("{:08x} {}", ptr.0, crate::ast::val_to_name(ptr.val()));
-      let got = book.get(ptr.val()).unwrap();
+      //println!("{:08x} {}", ptr.0, book.syms.show(ptr.val()));
+      // References with no definition can only be IO calls.
+      let got = match (book.get(ptr.val()), &book.io) {
+        (Some(got), _) => got,
+        (None, Some(io)) if io.calls(ptr.val()) => return self.call_io(book, io, ptr, trg),
+        (None, _) => return Err(InteractionError { a: ptr, b: trg, rule: "reference has no definition", trees: None }.into()),
+      };
       if got.safe && trg.is_dup() {
-        return self.copy(trg, ptr);
+        self.copy(trg, ptr);
+        return Ok(());
       } else if got.node.len() > 0 {
         let len = got.node.len() - 1;
+        if let Some(profiler) = &mut self.profiler {
+          profiler.nodes(len);
+        }
         // Allocate space.
         for i in 0 .. len {
-          *unsafe { self.locs.get_unchecked_mut(1 + i) } = self.alloc();
+          *unsafe { self.locs.get_unchecked_mut(1 + i) } = self.alloc()?;
         }
         // Load nodes, adjusted.
         for i in 0 .. len {
@@ -910,6 +1317,16 @@ impl<'a> Net<'a> {
       }
     }
     self.link(ptr, trg);
+    Ok(())
+  }
+
+  // Counts a dereference that compiled code performs without 'call', by inlining it.
+  #[inline(always)]
+  pub fn dref(&mut self, fid: Val) {
+    self.rwts.dref += 1;
+    if let Some(profiler) = &mut self.profiler {
+      profiler.call(fid);
+    }
   }
 
   // Adjusts dereferenced pointer locations.
@@ -924,50 +1341,115 @@ impl<'a> Net<'a> {
 
   // Reduces all redexes.
   #[inline(always)]
-  pub fn reduce(&mut self, book: &Book, limit: usize) -> usize {
+  pub fn reduce(&mut self, book: &Book, limit: usize) -> Result<usize, RuntimeError> {
     let mut count = 0;
     while let Some((a, b)) = self.rdex.pop() {
       //if !a.is_nil() && !b.is_nil() {
-        self.interact(book, a, b);
+        self.interact(book, a, b)?;
         count += 1;
         if count >= limit {
           break;
         }
       //}
     }
-    return count;
+    return Ok(count);
   }
 
   // Expands heads.
   #[inline(always)]
-  pub fn expand(&mut self, book: &Book) {
-    fn go(net: &mut Net, book: &Book, dir: Ptr, len: usize, key: usize) {
+  pub fn expand(&mut self, book: &Book) -> Result<(), RuntimeError> {
+    // Checks if the subtree at 'pos' of a level with 'len' subtrees contains a subtree owned by
+    // this thread, on the first level with at least 'tids' subtrees (where 'pos % tids == tid').
+    fn owns(net: &Net, len: usize, pos: usize) -> bool {
+      let alt = net.tid + net.tids;
+      net.tid % len == pos || alt < net.tids.next_power_of_two() && alt % len == pos
+    }
+    fn go(net: &mut Net, book: &Book, dir: Ptr, len: usize, pos: usize) -> Result<(), RuntimeError> {
       //println!("[{:04x}] expand dir: {:016x}", net.tid, dir.0);
       let ptr = net.get_target(dir);
       if ptr.is_ctr() {
-        if len >= net.tids || key % 2 == 0 {
-          go(net, book, Ptr::new(VR1, 0, ptr.loc()), len * 2, key / 2);
-        }
-        if len >= net.tids || key % 2 == 1 {
-          go(net, book, Ptr::new(VR2, 0, ptr.loc()), len * 2, key / 2);
+        if len >= net.tids {
+          go(net, book, Ptr::new(VR1, 0, ptr.loc()), len, pos)?;
+          go(net, book, Ptr::new(VR2, 0, ptr.loc()), len, pos)?;
+        } else {
+          if owns(net, len * 2, pos) {
+            go(net, book, Ptr::new(VR1, 0, ptr.loc()), len * 2, pos)?;
+          }
+          if owns(net, len * 2, pos + len) {
+            go(net, book, Ptr::new(VR2, 0, ptr.loc()), len * 2, pos + len)?;
+          }
         }
       } else if ptr.is_ref() {
         let got = net.swap_target(dir, LOCK);
         if got != LOCK {
           //println!("[{:08x}] expand {:08x}", net.tid, dir.0);
-          net.call(book, ptr, dir);
+          if let Some(tracer) = &mut net.tracer {
+            tracer.record(net.tid, crate::trace::Rule::Expand, ptr, dir);
+          }
+          if let Some(profiler) = &mut net.profiler {
+            profiler.expand();
+          }
+          net.call(book, ptr, dir)?;
         }
       }
+      Ok(())
     }
-    return go(self, book, ROOT, 1, self.tid);
+    return go(self, book, ROOT, 1, 0);
   }
 
   // Reduce a net to normal form.
-  pub fn normal(&mut self, book: &Book) {
-    self.expand(book);
-    while self.rdex.len() > 0 {
-      self.reduce(book, usize::MAX);
-      self.expand(book);
+  pub fn normal(&mut self, book: &Book) -> Result<(), RuntimeError> {
+    self.normal_with_fuel(book, usize::MAX).map(|_| ())
+  }
+
+  // Reduce a net towards normal form, stopping once 'fuel' rewrites were performed. Returns
+  // whether it finished; if not, the pending redexes are left on 'rdex', and it can be resumed.
+  pub fn normal_with_fuel(&mut self, book: &Book, fuel: usize) -> Result<bool, RuntimeError> {
+    fn go(net: &mut Net, book: &Book, fuel: usize) -> Result<bool, RuntimeError> {
+      let init = net.rewrites();
+      let mut last = None;
+      net.expand(book)?;
+      loop {
+        while net.rdex.len() > 0 {
+          let spent = net.rewrites() - init;
+          if spent >= fuel {
+            net.rdex.append(&mut net.wait);
+            return Ok(false);
+          }
+          net.reduce(book, fuel - spent)?;
+          if let Some(tracer) = &mut net.tracer {
+            tracer.epoch += 1;
+          }
+          net.expand(book)?;
+        }
+        if net.wait.is_empty() {
+          return Ok(true);
+        }
+        net.retry_io(net.rewrites(), &mut last)?;
+      }
+    }
+    return go(self, book, fuel).map_err(|err| self.read_back(book, err));
+  }
+
+  // Reads back the trees of an invalid redex (of each thread, for parallel failures). Must only be
+  // called once reduction stopped, since the failed pair is left untouched on the heap, but other
+  // threads may be linking to it.
+  pub fn read_back(&self, book: &Book, err: RuntimeError) -> RuntimeError {
+    match err {
+      RuntimeError::Threads(errs) => {
+        RuntimeError::Threads(errs.into_iter().map(|ThreadError { tid, err }| ThreadError { tid, err: self.read_back(book, err) }).collect())
+      }
+      RuntimeError::Interaction(err) if err.trees.is_none() => {
+        let a = crate::ast::show_runtime_tree(self, &book.syms, err.a);
+        let b = crate::ast::show_runtime_tree(self, &book.syms, err.b);
+        RuntimeError::Interaction(InteractionError { trees: Some((a, b)), ..err })
+      }
+      RuntimeError::Arithmetic(err) if err.trees.is_none() => {
+        let a = crate::ast::show_runtime_tree(self, &book.syms, err.a);
+        let b = crate::ast::show_runtime_tree(self, &book.syms, err.b);
+        RuntimeError::Arithmetic(ArithmeticError { trees: Some((a, b)), ..err })
+      }
+      err => err,
     }
   }
 
@@ -976,6 +1458,9 @@ impl<'a> Net<'a> {
     let mut net = Net::new(self.heap.data);
     net.tid  = tid;
     net.tids = tids;
+    net.strict = self.strict;
+    net.tracer = self.tracer.as_ref().map(|tracer| tracer.fork());
+    net.profiler = self.profiler.as_ref().map(|profiler| profiler.fork());
     net.area = Area {
       init: self.heap.data.len() * tid / tids,
       size: self.heap.data.len() / tids,
@@ -985,23 +1470,60 @@ impl<'a> Net<'a> {
     for i in from .. upto {
       net.rdex.push((self.rdex[i].0, self.rdex[i].1));
     }
-    if tid == 0 {
-      net.next = self.next;
-    }
+    // Resumes where this thread stopped on the last parallel run, if the parent didn't allocate
+    // since. Otherwise, skips the nodes the parent may have used in this area, so that resumed nets
+    // don't overwrite them.
+    net.next = match &self.ends {
+      Some((next, ends)) if *next == self.next && ends.len() == tids => ends[tid],
+      _ => (self.area.init + self.next).saturating_sub(net.area.init).min(net.area.size.saturating_sub(1)),
+    };
     return net;
   }
 
-  // Evaluates a term to normal form in parallel
-  pub fn parallel_normal(&mut self, book: &Book) {
+  // Takes back the pending redexes, traced steps and profile of a forked thread.
+  fn join(&mut self, tids: usize, rdex: Vec<(Ptr, Ptr)>, tracer: Option<crate::trace::Tracer>, profiler: Option<crate::profile::Profiler>) {
+    self.rdex.extend(rdex);
+    if let (Some(parent), Some(child)) = (&mut self.tracer, tracer) {
+      parent.tids = tids;
+      parent.join(child);
+    }
+    if let (Some(parent), Some(child)) = (&mut self.profiler, profiler) {
+      parent.join(child);
+    }
+  }
+
+  // Records where each forked thread stopped allocating. Threads allocated all over the heap, so
+  // later allocations by this net must search for free slots.
+  pub fn join_ends(&mut self, ends: Vec<usize>) {
+    self.next = std::cmp::max(self.next, self.area.size - 1);
+    self.ends = Some((self.next, ends));
+  }
 
-    const SHARE_LIMIT : usize = 1 << 12; // max share redexes per split 
-    const LOCAL_LIMIT : usize = 1 << 18; // max local rewrites per epoch
+  // Evaluates a term to normal form in parallel
+  pub fn parallel_normal(&mut self, book: &Book, config: &RuntimeConfig) -> Result<(), RuntimeError> {
+    self.parallel_normal_with_fuel(book, config, usize::MAX).map(|_| ())
+  }
+
+  // Evaluates a term towards normal form in parallel, stopping once 'fuel' rewrites were performed
+  // by all threads combined. Returns whether it finished; if not, the pending redexes of every
+  // thread are moved back to 'rdex', and it can be resumed. Fails with the error of every thread
+  // that failed.
+  pub fn parallel_normal_with_fuel(&mut self, book: &Book, config: &RuntimeConfig, fuel: usize) -> Result<bool, RuntimeError> {
+    // Each thread allocates in its own area of the heap, so there must be a node per thread.
+    let tids = std::cmp::max(config.threads, 1);
+    if self.heap.data.len() < tids {
+      return Err(OutOfMemory { tid: 0, init: 0, size: self.heap.data.len() / tids }.into());
+    }
+    if config.scheduler == Scheduler::Steal {
+      return self.steal_normal_with_fuel(book, config, fuel);
+    }
 
     // Local thread context
     struct ThreadContext<'a> {
       tid: usize, // thread id
       tids: usize, // thread count
-      tlog2: usize, // log2 of thread count
+      tlog2: usize, // log2 of thread count, rounded up
+      conf: &'a RuntimeConfig, // runtime configuration
       tick: usize, // current tick
       net: Net<'a>, // thread's own net object
       book: &'a Book, // definition book
@@ -1009,87 +1531,172 @@ impl<'a> Net<'a> {
       share: &'a Vec<(APtr, APtr)>, // global share buffer
       rlens: &'a Vec<AtomicUsize>, // global redex lengths
       total: &'a AtomicUsize, // total redex length
+      waits: &'a AtomicUsize, // total IO calls waiting for their arguments
+      retry: Option<usize>, // total rewrites, as of the last retry of those calls
+      halt: &'a AtomicBool, // set when some thread failed
+      fail: Option<RuntimeError>, // thread's own failure
+      fuel: usize, // max total rewrites
+      spent: &'a AtomicUsize, // total rewrites, as of the last count
+      last: usize, // own rewrites, as of the last count
+      stop: bool, // set when the last count stopped the reduction
       barry: Arc<Barrier>, // synchronization barrier
     }
 
     // Initialize global objects
-    let cores = std::thread::available_parallelism().unwrap().get() as usize;
-    let tlog2 = cores.ilog2() as usize;
-    let tids  = 1 << tlog2;
+    let tlog2 = tids.next_power_of_two().ilog2() as usize;
     let delta = AtomicRewrites::new(); // delta rewrite counter
     let rlens = (0..tids).map(|_| AtomicUsize::new(0)).collect::<Vec<_>>();
-    let share = (0..SHARE_LIMIT*tids).map(|_| (APtr(AtomicU64::new(0)), APtr(AtomicU64::new(0)))).collect::<Vec<_>>();
+    let share = (0..config.share_limit*tids).map(|_| (APtr(AtomicU64::new(0)), APtr(AtomicU64::new(0)))).collect::<Vec<_>>();
     let total = AtomicUsize::new(0); // sum of redex bag length
+    let waits = AtomicUsize::new(0); // sum of waiting IO calls
+    let halt  = AtomicBool::new(false); // global failure flag
+    let spent = AtomicUsize::new(0); // total rewrites
     let barry = Arc::new(Barrier::new(tids)); // global barrier
+    let mut done = true; // whether every thread finished
+    let mut errs = vec![]; // failures of each thread
+    let mut ends = vec![]; // where each thread stopped allocating
 
     // Perform parallel reductions
     std::thread::scope(|s| {
+      let mut handles = vec![];
       for tid in 0 .. tids {
         let mut ctx = ThreadContext {
-          tid: tid,
-          tids: tids,
+          tid,
+          tids,
           tick: 0,
           net: self.fork(tid, tids),
-          book: &book,
-          tlog2: tlog2,
+          book,
+          tlog2,
+          conf: config,
           delta: &delta,
           share: &share,
           rlens: &rlens,
           total: &total,
+          waits: &waits,
+          retry: None,
+          halt: &halt,
+          fail: None,
+          fuel,
+          spent: &spent,
+          last: 0,
+          stop: false,
           barry: Arc::clone(&barry),
         };
-        s.spawn(move || {
-          main(&mut ctx)
-        });
+        handles.push(s.spawn(move || {
+          (main(&mut ctx), std::mem::take(&mut ctx.net.rdex), ctx.net.tracer.take(), ctx.net.profiler.take(), ctx.net.next)
+        }));
+      }
+      // Collect pending redexes, traced steps, profiles and failures
+      self.rdex.clear();
+      for (tid, handle) in handles.into_iter().enumerate() {
+        let (got, rdex, tracer, profiler, next) = handle.join().unwrap();
+        self.join(tids, rdex, tracer, profiler);
+        ends.push(next);
+        match got {
+          Ok(got) => done = done && got,
+          Err(err) => errs.push(ThreadError { tid, err }),
+        }
       }
     });
 
-    // Clear redexes and sum stats
-    self.rdex.clear();
+    // Sum stats
     delta.add_to(&mut self.rwts);
 
+    self.join_ends(ends);
+
+    if !errs.is_empty() {
+      return Err(self.read_back(book, RuntimeError::Threads(errs)));
+    }
+    return Ok(done);
+
     // Main reduction loop
     #[inline(always)]
-    fn main(ctx: &mut ThreadContext) {
+    fn main(ctx: &mut ThreadContext) -> Result<bool, RuntimeError> {
       loop {
         reduce(ctx);
         expand(ctx);
         if count(ctx) == 0 { break; }
       }
+      ctx.net.rdex.append(&mut ctx.net.wait);
       ctx.net.rwts.add_to(ctx.delta);
+      match ctx.fail.take() {
+        Some(err) => Err(err),
+        None      => Ok(!ctx.stop),
+      }
+    }
+
+    // Records a failure, stopping all threads on the next count
+    #[inline(always)]
+    fn fail(ctx: &mut ThreadContext, err: RuntimeError) {
+      ctx.fail = Some(err);
+      ctx.halt.store(true, Ordering::Relaxed);
     }
 
     // Reduce redexes locally, then share with target
     #[inline(always)]
     fn reduce(ctx: &mut ThreadContext) {
       loop {
-        let reduced = ctx.net.reduce(ctx.book, LOCAL_LIMIT);
+        let left  = ctx.fuel.saturating_sub(ctx.spent.load(Ordering::Relaxed));
+        let limit = std::cmp::min(ctx.conf.local_limit, std::cmp::max(1, left / ctx.tids));
+        if let Err(err) = ctx.net.reduce(ctx.book, limit) {
+          fail(ctx, err);
+        }
         //println!("[{:04x}] reduced {}", ctx.tid, reduced);
         if count(ctx) == 0 {
           break;
         }
-        let tlog2 = ctx.tlog2;
-        split(ctx, tlog2);
+        if ctx.tlog2 > 0 {
+          let tlog2 = ctx.tlog2;
+          split(ctx, tlog2);
+        }
         ctx.tick += 1;
+        if let Some(tracer) = &mut ctx.net.tracer {
+          tracer.epoch = ctx.tick;
+        }
       }
     }
 
     // Expand head refs
     #[inline(always)]
     fn expand(ctx: &mut ThreadContext) {
-      ctx.net.expand(ctx.book);
+      if !ctx.stop {
+        if let Err(err) = ctx.net.expand(ctx.book) {
+          fail(ctx, err);
+        }
+      }
     }
 
-    // Count total redexes (and populate 'rlens')
+    // Count total redexes (and populate 'rlens'), or 0 if a failure or the fuel stops reduction.
+    // Once only waiting IO calls are left, on every thread, they're retried and counted instead.
     #[inline(always)]
     fn count(ctx: &mut ThreadContext) -> usize {
       ctx.barry.wait();
       ctx.total.store(0, Ordering::Relaxed);
+      ctx.waits.store(0, Ordering::Relaxed);
       ctx.barry.wait();
+      // Read between barriers, so that every thread sees the same flag
+      let halt = ctx.halt.load(Ordering::Relaxed);
+      let rwts = ctx.net.rewrites();
+      ctx.spent.fetch_add(rwts - ctx.last, Ordering::Relaxed);
+      ctx.last = rwts;
       ctx.rlens[ctx.tid].store(ctx.net.rdex.len(), Ordering::Relaxed);
       ctx.total.fetch_add(ctx.net.rdex.len(), Ordering::Relaxed);
+      ctx.waits.fetch_add(ctx.net.wait.len(), Ordering::Relaxed);
       ctx.barry.wait();
-      return ctx.total.load(Ordering::Relaxed);
+      let total = ctx.total.load(Ordering::Relaxed);
+      let waits = ctx.waits.load(Ordering::Relaxed);
+      let spent = ctx.spent.load(Ordering::Relaxed);
+      ctx.stop  = halt || total + waits > 0 && spent >= ctx.fuel;
+      if ctx.stop {
+        return 0;
+      }
+      if total == 0 && waits > 0 {
+        if let Err(err) = ctx.net.retry_io(spent, &mut ctx.retry) {
+          fail(ctx, err);
+        }
+        return waits;
+      }
+      return total;
     }
 
 
@@ -1102,11 +1709,12 @@ impl<'a> Net<'a> {
         let a_tid = ctx.tid;
         let b_tid = if side == 1 { a_tid - shift } else { a_tid + shift };
         let a_len = ctx.net.rdex.len();
-        let b_len = ctx.rlens[b_tid].load(Ordering::Relaxed);
+        // With a non-power-of-two thread count, some threads have no partner on some ticks
+        let b_len = if b_tid < ctx.tids { ctx.rlens[b_tid].load(Ordering::Relaxed) } else { a_len };
         let send  = if a_len > b_len { (a_len - b_len) / 2 } else { 0 };
         let recv  = if b_len > a_len { (b_len - a_len) / 2 } else { 0 };
-        let send  = std::cmp::min(send, SHARE_LIMIT);
-        let recv  = std::cmp::min(recv, SHARE_LIMIT);
+        let send  = std::cmp::min(send, ctx.conf.share_limit);
+        let recv  = std::cmp::min(recv, ctx.conf.share_limit);
         for i in 0 .. send {
           let init = a_len - send * 2;
           let rdx0 = *ctx.net.rdex.get_unchecked(init + i * 2 + 0);
@@ -1118,7 +1726,7 @@ impl<'a> Net<'a> {
           //let ref1 = ctx.net.rdex.get_unchecked_mut(init + i * 2 + 1);
           //let rdx1 = *ref1;
           //*ref1    = (Ptr(0), Ptr(0));
-          let targ = ctx.share.get_unchecked(b_tid * SHARE_LIMIT + i);
+          let targ = ctx.share.get_unchecked(b_tid * ctx.conf.share_limit + i);
           *ctx.net.rdex.get_unchecked_mut(init + i) = rdx0;
           targ.0.store(rdx1.0);
           targ.1.store(rdx1.1);
@@ -1126,11 +1734,259 @@ impl<'a> Net<'a> {
         ctx.net.rdex.truncate(a_len - send);
         ctx.barry.wait();
         for i in 0 .. recv {
-          let got = ctx.share.get_unchecked(a_tid * SHARE_LIMIT + i);
+          let got = ctx.share.get_unchecked(a_tid * ctx.conf.share_limit + i);
           ctx.net.rdex.push((got.0.load(), got.1.load()));
         }
       }
     }
   }
 
+  // Evaluates a term towards normal form in parallel, like 'parallel_normal_with_fuel', on the
+  // work-stealing scheduler. Each thread reduces its own redexes, in chunks of 'STEAL_CHUNK'
+  // rewrites. While some thread is idle, a busy thread with an empty deque moves its oldest
+  // redexes to it (at most 'share_limit'), and idle threads steal half the deque of another one.
+  // There are no barriers while reducing: a thread is active while it has redexes, or is trying
+  // to steal some, and the reduction ends once no thread is active. Heads are then expanded, as
+  // on the split scheduler, and the reduction goes on if that produced redexes.
+  fn steal_normal_with_fuel(&mut self, book: &Book, config: &RuntimeConfig, fuel: usize) -> Result<bool, RuntimeError> {
+
+    // Local thread context
+    struct ThreadContext<'a> {
+      tid: usize, // thread id
+      tids: usize, // thread count
+      tick: usize, // current chunk
+      net: Net<'a>, // thread's own net object
+      book: &'a Book, // definition book
+      conf: &'a RuntimeConfig, // runtime configuration
+      deque: Worker<(Ptr, Ptr)>, // thread's own shared redexes
+      stealers: &'a Vec<Stealer<(Ptr, Ptr)>>, // global deque handles
+      delta: &'a AtomicRewrites, // global delta rewrites
+      active: &'a AtomicUsize, // threads that have or look for redexes
+      total: &'a AtomicUsize, // total redex length, after expanding heads
+      waits: &'a AtomicUsize, // total IO calls waiting for their arguments, after expanding heads
+      retry: Option<usize>, // total rewrites, as of the last retry of those calls
+      halt: &'a AtomicBool, // set when some thread failed
+      fail: Option<RuntimeError>, // thread's own failure
+      fuel: usize, // max total rewrites
+      spent: &'a AtomicUsize, // total rewrites, as of the last chunk of each thread
+      last: usize, // own rewrites, as of the last chunk
+      stop: bool, // set when the fuel stopped the reduction
+      barry: Arc<Barrier>, // synchronization barrier
+    }
+
+    // Initialize global objects
+    let tids  = std::cmp::max(config.threads, 1);
+    let deques = (0 .. tids).map(|_| Worker::new_lifo()).collect::<Vec<_>>();
+    let stealers = deques.iter().map(|deque| deque.stealer()).collect::<Vec<_>>();
+    let delta  = AtomicRewrites::new(); // delta rewrite counter
+    let active = AtomicUsize::new(tids); // active thread count
+    let total  = AtomicUsize::new(0); // sum of redex bag length
+    let waits  = AtomicUsize::new(0); // sum of waiting IO calls
+    let halt   = AtomicBool::new(false); // global failure flag
+    let spent  = AtomicUsize::new(0); // total rewrites
+    let barry  = Arc::new(Barrier::new(tids)); // global barrier
+    let mut done = true; // whether every thread finished
+    let mut errs = vec![]; // failures of each thread
+    let mut ends = vec![]; // where each thread stopped allocating
+
+    // Perform parallel reductions
+    std::thread::scope(|s| {
+      let mut handles = vec![];
+      for (tid, deque) in deques.into_iter().enumerate() {
+        let mut ctx = ThreadContext {
+          tid,
+          tids,
+          tick: 0,
+          net: self.fork(tid, tids),
+          book,
+          conf: config,
+          deque,
+          stealers: &stealers,
+          delta: &delta,
+          active: &active,
+          total: &total,
+          waits: &waits,
+          retry: None,
+          halt: &halt,
+          fail: None,
+          fuel,
+          spent: &spent,
+          last: 0,
+          stop: false,
+          barry: Arc::clone(&barry),
+        };
+        handles.push(s.spawn(move || {
+          (main(&mut ctx), std::mem::take(&mut ctx.net.rdex), ctx.net.tracer.take(), ctx.net.profiler.take(), ctx.net.next)
+        }));
+      }
+      // Collect pending redexes, traced steps, profiles and failures
+      self.rdex.clear();
+      for (tid, handle) in handles.into_iter().enumerate() {
+        let (got, rdex, tracer, profiler, next) = handle.join().unwrap();
+        self.join(tids, rdex, tracer, profiler);
+        ends.push(next);
+        match got {
+          Ok(got) => done = done && got,
+          Err(err) => errs.push(ThreadError { tid, err }),
+        }
+      }
+    });
+
+    // Sum stats
+    delta.add_to(&mut self.rwts);
+
+    self.join_ends(ends);
+
+    if !errs.is_empty() {
+      return Err(self.read_back(book, RuntimeError::Threads(errs)));
+    }
+    return Ok(done);
+
+    // Main reduction loop
+    fn main(ctx: &mut ThreadContext) -> Result<bool, RuntimeError> {
+      loop {
+        work(ctx);
+        if halted(ctx) { break; }
+        expand(ctx);
+        if count(ctx) == 0 { break; }
+      }
+      // Moves the redexes left on the deque back, if the reduction stopped early
+      while let Some(rdx) = ctx.deque.pop() {
+        ctx.net.rdex.push(rdx);
+      }
+      ctx.net.rdex.append(&mut ctx.net.wait);
+      ctx.net.rwts.add_to(ctx.delta);
+      match ctx.fail.take() {
+        Some(err) => Err(err),
+        None      => Ok(!ctx.stop),
+      }
+    }
+
+    // Records a failure, stopping all threads
+    fn fail(ctx: &mut ThreadContext, err: RuntimeError) {
+      ctx.fail = Some(err);
+      ctx.halt.store(true, Ordering::Relaxed);
+    }
+
+    // Reduces local redexes, sharing and stealing them, until no thread is active
+    fn work(ctx: &mut ThreadContext) {
+      loop {
+        if ctx.halt.load(Ordering::Relaxed) {
+          return;
+        }
+        // Reduces a chunk of the local redexes
+        if !ctx.net.rdex.is_empty() {
+          let left  = ctx.fuel.saturating_sub(ctx.spent.load(Ordering::Relaxed));
+          let limit = (left / ctx.tids).clamp(1, STEAL_CHUNK);
+          if let Err(err) = ctx.net.reduce(ctx.book, limit) {
+            fail(ctx, err);
+            return;
+          }
+          let rwts = ctx.net.rewrites();
+          if ctx.spent.fetch_add(rwts - ctx.last, Ordering::Relaxed) + (rwts - ctx.last) >= ctx.fuel {
+            // Stops every thread, as a failure would; 'halted' tells them apart.
+            ctx.halt.store(true, Ordering::Relaxed);
+          }
+          ctx.last = rwts;
+          ctx.tick += 1;
+          if let Some(tracer) = &mut ctx.net.tracer {
+            tracer.epoch = ctx.tick;
+          }
+          share(ctx);
+          continue;
+        }
+        // Takes back its own shared redexes
+        if let Some(rdx) = ctx.deque.pop() {
+          ctx.net.rdex.push(rdx);
+          continue;
+        }
+        // Steals from the other threads, until it gets redexes, or no thread is active. Looking for
+        // redexes counts as being active, so that a stolen batch is never in nobody's hands.
+        ctx.active.fetch_sub(1, Ordering::SeqCst);
+        loop {
+          if ctx.active.load(Ordering::SeqCst) == 0 || ctx.halt.load(Ordering::Relaxed) {
+            return;
+          }
+          ctx.active.fetch_add(1, Ordering::SeqCst);
+          if let Some(rdx) = steal(ctx) {
+            ctx.net.rdex.push(rdx);
+            break;
+          }
+          ctx.active.fetch_sub(1, Ordering::SeqCst);
+          std::thread::yield_now();
+        }
+      }
+    }
+
+    // Moves the oldest local redexes to the deque, if some thread is idle and the deque is empty
+    fn share(ctx: &mut ThreadContext) {
+      let len = ctx.net.rdex.len();
+      if len > 1 && ctx.deque.is_empty() && ctx.active.load(Ordering::Relaxed) < ctx.tids {
+        let send = std::cmp::min(len / 2, ctx.conf.share_limit);
+        for rdx in ctx.net.rdex.drain(0 .. send) {
+          ctx.deque.push(rdx);
+        }
+      }
+    }
+
+    // Steals a batch of redexes from another thread's deque, starting from the next thread
+    fn steal(ctx: &mut ThreadContext) -> Option<(Ptr, Ptr)> {
+      for i in 1 .. ctx.tids {
+        let stealer = &ctx.stealers[(ctx.tid + i) % ctx.tids];
+        loop {
+          match stealer.steal_batch_and_pop(&ctx.deque) {
+            Steal::Success(rdx) => return Some(rdx),
+            Steal::Empty        => break,
+            Steal::Retry        => continue,
+          }
+        }
+      }
+      return None;
+    }
+
+    // Checks whether some thread failed or ran out of fuel, after all threads stopped working
+    fn halted(ctx: &mut ThreadContext) -> bool {
+      ctx.barry.wait();
+      // Read between barriers, so that every thread sees the same flags
+      let halt = ctx.halt.load(Ordering::Relaxed);
+      ctx.stop = ctx.spent.load(Ordering::Relaxed) >= ctx.fuel;
+      ctx.barry.wait();
+      return halt;
+    }
+
+    // Expands head refs
+    fn expand(ctx: &mut ThreadContext) {
+      if let Err(err) = ctx.net.expand(ctx.book) {
+        fail(ctx, err);
+      }
+    }
+
+    // Counts the redexes the expansion produced, or 0 if it failed, resetting the active count.
+    // Once only waiting IO calls are left, on every thread, they're retried and counted instead.
+    fn count(ctx: &mut ThreadContext) -> usize {
+      ctx.barry.wait();
+      ctx.total.store(0, Ordering::Relaxed);
+      ctx.waits.store(0, Ordering::Relaxed);
+      ctx.active.store(ctx.tids, Ordering::SeqCst);
+      ctx.barry.wait();
+      // Read between barriers, since threads that went on reducing may already set it again
+      let halt = ctx.halt.load(Ordering::Relaxed);
+      ctx.total.fetch_add(ctx.net.rdex.len(), Ordering::Relaxed);
+      ctx.waits.fetch_add(ctx.net.wait.len(), Ordering::Relaxed);
+      ctx.barry.wait();
+      let total = ctx.total.load(Ordering::Relaxed);
+      let waits = ctx.waits.load(Ordering::Relaxed);
+      if halt {
+        return 0;
+      }
+      if total == 0 && waits > 0 {
+        if let Err(err) = ctx.net.retry_io(ctx.spent.load(Ordering::Relaxed), &mut ctx.retry) {
+          fail(ctx, err);
+        }
+        return waits;
+      }
+      return total;
+    }
+  }
+
 }
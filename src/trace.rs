@@ -0,0 +1,241 @@
+// Reduction traces
+// ----------------
+// This file records reductions, so that a parallel run, whose steps interleave differently every
+// time, can be reproduced. While a net has a 'Tracer', each interaction and each expansion of a
+// head REF is logged as a 'Step', with the thread that performed it, its epoch (that thread's
+// reduce round) and a global sequence number taken before the step starts. Steps are sorted by
+// sequence number once the reduction ends.
+//
+// 'replay' re-executes a trace on a single thread, from the net the traced reduction started
+// from. Each thread of the original run gets a fork with its allocation area, so nodes end up on
+// the same locations, and the steps run one at a time, in sequence order. Before each step, the
+// replay checks that its redex is pending (or, for expansions, that its port still holds the REF),
+// and stops at the first step that diverges. Since steps that overlapped in time now run one after
+// the other, the first divergence points to the steps that raced. A trace covers one call to
+// 'normal' or 'parallel_normal', and replaying a book with IO performs its effects again.
+
+use crate::run::*;
+use std::collections::HashMap;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+// The rule of a step, named after the 'Net' method that performs it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub enum Rule {
+  Anni, // CON-CON, with equal labels
+  Comm, // CON-CON, with different labels, or OP2/MAT-CON
+  Era2, // ERA-CON, ERA-OP2 or ERA-MAT
+  Era1, // ERA-OP1
+  Void, // a pair of nullary nodes, which just vanishes
+  Copy, // NUM-CON
+  Pass, // OP1-CON
+  Op2n, // OP2-NUM
+  Op1n, // OP1-NUM
+  Mtch, // MAT-NUM
+  Call, // REF-node
+  Expand, // a head REF, expanded in place
+  Invalid, // a pair with no rule
+}
+
+// A logged step. For expansions, 'a' is the REF, and 'b' the port that holds it.
+#[derive(Copy, Clone, Debug, Eq, PartialEq, Hash)]
+pub struct Step {
+  pub tid: usize, // thread that performed it
+  pub epoch: usize, // reduce round of that thread
+  pub rule: Rule,
+  pub a: Ptr,
+  pub b: Ptr,
+}
+
+// A finished trace, with its steps in sequence order.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct Trace {
+  pub tids: usize, // thread count of the traced run
+  pub steps: Vec<Step>,
+}
+
+// Records the steps of one thread.
+pub struct Tracer {
+  clock: Arc<AtomicUsize>, // next sequence number, shared by all threads
+  pub tids: usize, // thread count of the traced run
+  pub epoch: usize, // current reduce round
+  pub steps: Vec<(usize, Step)>, // steps so far, with their sequence numbers
+}
+
+// Why a step couldn't be replayed.
+#[derive(Debug)]
+pub enum Divergence {
+  NoRedex, // the redex isn't pending
+  NoRef { got: Ptr }, // the port doesn't hold the REF, but this
+  Failed(Box<RuntimeError>), // the step failed
+}
+
+// The first step a replay couldn't reproduce.
+#[derive(Debug)]
+pub struct ReplayError {
+  pub index: usize, // position of the step in the trace
+  pub step: Step,
+  pub divergence: Divergence,
+}
+
+impl Rule {
+  // The rule that reduces a redex, as in 'Net::interact'.
+  pub fn of(a: Ptr, b: Ptr) -> Rule {
+    let (a, b) = if a.tag() <= b.tag() { (a, b) } else { (b, a) };
+    match (a.tag(), b.tag()) {
+      (REF         , REF         ) => Rule::Invalid,
+      (REF         , OP2..       ) => Rule::Call,
+      (REF ..= FLT , REF ..= FLT ) => Rule::Void,
+      (NUM ..= FLT , OP2         ) => Rule::Op2n,
+      (NUM ..= FLT , OP1         ) => Rule::Op1n,
+      (NUM ..= FLT , MAT         ) => Rule::Mtch,
+      (NUM ..= FLT , LAM..       ) => Rule::Copy,
+      (ERA         , OP1         ) => Rule::Era1,
+      (ERA         , OP2..       ) => Rule::Era2,
+      (OP1         , LAM..       ) => Rule::Pass,
+      (OP2 | MAT   , LAM..       ) => Rule::Comm,
+      (LAM..       , LAM..       ) => if a.lab() == b.lab() { Rule::Anni } else { Rule::Comm },
+      _                            => Rule::Invalid,
+    }
+  }
+
+  pub const ALL: [Rule; 13] = [
+    Rule::Anni, Rule::Comm, Rule::Era2, Rule::Era1, Rule::Void, Rule::Copy, Rule::Pass,
+    Rule::Op2n, Rule::Op1n, Rule::Mtch, Rule::Call, Rule::Expand, Rule::Invalid,
+  ];
+
+  // The code of a rule in binary traces.
+  pub fn code(self) -> u8 {
+    Rule::ALL.iter().position(|rule| *rule == self).unwrap() as u8
+  }
+
+  pub fn from_code(code: u8) -> Option<Rule> {
+    Rule::ALL.get(code as usize).copied()
+  }
+}
+
+impl std::fmt::Display for Rule {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    f.write_str(&format!("{:?}", self).to_lowercase())
+  }
+}
+
+impl std::fmt::Display for Step {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(f, "{} {:016x} {:016x} (thread {}, epoch {})", self.rule, self.a.0, self.b.0, self.tid, self.epoch)
+  }
+}
+
+impl std::fmt::Display for ReplayError {
+  fn fmt(&self, f: &mut std::fmt::Formatter) -> std::fmt::Result {
+    write!(f, "replay diverged at step {}, {}: ", self.index, self.step)?;
+    match &self.divergence {
+      Divergence::NoRedex       => write!(f, "redex not pending"),
+      Divergence::NoRef { got } => write!(f, "port holds {:016x}, not the REF", got.0),
+      Divergence::Failed(err)   => write!(f, "{}", err),
+    }
+  }
+}
+
+impl std::error::Error for ReplayError {}
+
+impl Default for Tracer {
+  fn default() -> Self {
+    Self::new()
+  }
+}
+
+impl Tracer {
+  pub fn new() -> Self {
+    Tracer { clock: Arc::new(AtomicUsize::new(0)), tids: 1, epoch: 0, steps: vec![] }
+  }
+
+  // A tracer for a child thread, sharing the clock.
+  pub fn fork(&self) -> Self {
+    Tracer { clock: Arc::clone(&self.clock), tids: self.tids, epoch: 0, steps: vec![] }
+  }
+
+  // Takes the steps of a child thread's tracer.
+  pub fn join(&mut self, child: Tracer) {
+    self.steps.extend(child.steps);
+  }
+
+  #[inline(always)]
+  pub fn record(&mut self, tid: usize, rule: Rule, a: Ptr, b: Ptr) {
+    let seq = self.clock.fetch_add(1, Ordering::Relaxed);
+    self.steps.push((seq, Step { tid, epoch: self.epoch, rule, a, b }));
+  }
+
+  pub fn finish(mut self) -> Trace {
+    self.steps.sort_by_key(|(seq, _)| *seq);
+    Trace { tids: self.tids, steps: self.steps.into_iter().map(|(_, step)| step).collect() }
+  }
+}
+
+// Replays a trace on a net in the state the traced reduction started from. On success, the net
+// ends in the state the traced reduction ended in.
+pub fn replay(net: &mut Net, book: &Book, trace: &Trace) -> Result<(), ReplayError> {
+  let tids = std::cmp::max(trace.tids, 1);
+  // Redexes are shared by all threads, since the original run moved them around. They're kept as
+  // a multiset of unordered pairs.
+  let mut pending = HashMap::new();
+  add_pending(&mut pending, &mut net.rdex);
+  let mut nets = (0 .. tids).map(|tid| net.fork(tid, tids)).collect::<Vec<_>>();
+  let mut result = Ok(());
+  for (index, step) in trace.steps.iter().enumerate() {
+    let fail = |divergence| Err(ReplayError { index, step: *step, divergence });
+    let Some(rnet) = nets.get_mut(step.tid) else {
+      result = fail(Divergence::NoRedex);
+      break;
+    };
+    let done = if step.rule == Rule::Expand {
+      let got = rnet.swap_target(step.b, LOCK);
+      if got != step.a {
+        rnet.set_target(step.b, got);
+        result = fail(Divergence::NoRef { got });
+        break;
+      }
+      rnet.call(book, step.a, step.b)
+    } else {
+      match pending.get_mut(&pair(step.a, step.b)) {
+        Some(count) if *count > 0 => *count -= 1,
+        _ => {
+          result = fail(Divergence::NoRedex);
+          break;
+        }
+      }
+      rnet.interact(book, step.a, step.b)
+    };
+    add_pending(&mut pending, &mut rnet.rdex);
+    add_pending(&mut pending, &mut rnet.wait);
+    if let Err(err) = done {
+      result = fail(Divergence::Failed(Box::new(err)));
+      break;
+    }
+  }
+  // Merges the forks back, as 'parallel_normal' does.
+  let delta = AtomicRewrites::new();
+  for rnet in &nets {
+    rnet.rwts.add_to(&delta);
+  }
+  delta.add_to(&mut net.rwts);
+  for (rdx, count) in pending {
+    net.rdex.extend(std::iter::repeat_n(rdx, count));
+  }
+  if tids == 1 {
+    net.next = nets[0].next;
+  } else {
+    net.join_ends(nets.iter().map(|rnet| rnet.next).collect());
+  }
+  return result;
+}
+
+fn pair(a: Ptr, b: Ptr) -> (Ptr, Ptr) {
+  if a.0 <= b.0 { (a, b) } else { (b, a) }
+}
+
+fn add_pending(pending: &mut HashMap<(Ptr, Ptr), usize>, rdex: &mut Vec<(Ptr, Ptr)>) {
+  for (a, b) in rdex.drain(..) {
+    *pending.entry(pair(a, b)).or_insert(0) += 1;
+  }
+}
@@ -27,14 +27,16 @@ pub fn mul(a: U60, b: U60) -> U60 {
   return new((a as u128 * b as u128) as u64);
 }
 
+// Division by zero returns the largest u60.
 #[inline(always)]
 pub fn div(a: U60, b: U60) -> U60 {
-  return a / b;
+  return a.checked_div(b).unwrap_or(0xFFF_FFFF_FFFF_FFFF);
 }
 
+// Modulus by zero returns the dividend.
 #[inline(always)]
 pub fn rem(a: U60, b: U60) -> U60 {
-  return a % b;
+  return a.checked_rem(b).unwrap_or(a);
 }
 
 #[inline(always)]
@@ -52,14 +54,15 @@ pub fn xor(a: U60, b: U60) -> U60 {
   return a ^ b;
 }
 
+// Shifting by 60 or more bits shifts every bit out.
 #[inline(always)]
 pub fn lsh(a: U60, b: U60) -> U60 {
-  return new(a << b);
+  return if b >= 60 { 0 } else { new(a << b) };
 }
 
 #[inline(always)]
 pub fn rsh(a: U60, b: U60) -> U60 {
-  return a >> b;
+  return if b >= 60 { 0 } else { a >> b };
 }
 
 #[inline(always)]
@@ -102,11 +105,54 @@ pub fn max(a: U60, b: U60) -> U60 {
   return if a > b { a } else { b };
 }
 
+// Exponentiation by squaring, wrapping around.
+#[inline(always)]
+pub fn pow(a: U60, b: U60) -> U60 {
+  let mut a = a;
+  let mut b = b;
+  let mut r = 1u64;
+  while b > 0 {
+    if b & 1 == 1 {
+      r = r.wrapping_mul(a);
+    }
+    a = a.wrapping_mul(a);
+    b >>= 1;
+  }
+  return new(r);
+}
+
+// Rotations are taken modulo 60.
+#[inline(always)]
+pub fn rol(a: U60, b: U60) -> U60 {
+  let b = b % 60;
+  return if b == 0 { a } else { new(a << b) | (a >> (60 - b)) };
+}
+
+#[inline(always)]
+pub fn ror(a: U60, b: U60) -> U60 {
+  return rol(a, 60 - b % 60);
+}
+
 #[inline(always)]
 pub fn not(a: U60) -> U60 {
   return !a & 0xFFF_FFFF_FFFF_FFFF;
 }
 
+#[inline(always)]
+pub fn pop(a: U60) -> U60 {
+  return a.count_ones() as u64;
+}
+
+#[inline(always)]
+pub fn clz(a: U60) -> U60 {
+  return a.leading_zeros() as u64 - 4;
+}
+
+#[inline(always)]
+pub fn ctz(a: U60) -> U60 {
+  return if a == 0 { 60 } else { a.trailing_zeros() as u64 };
+}
+
 #[inline(always)]
 pub fn show(a: U60) -> String {
   return format!("{}", a);
@@ -11,7 +11,7 @@ pub fn load_file(file: &str) -> String {
 
 // Parses code and generate Book from hvm-core syntax
 pub fn parse_core(code: &str) -> Book {
-  do_parse_book(code)
+  do_parse_book(code).unwrap()
 }
 
 // Parses code and generate DefinitionBook from hvm-lang syntax
@@ -33,37 +33,56 @@ pub fn replace_template(mut code: String, map: &[(&str, &str)]) -> String {
   code
 }
 
-pub fn hvm_lang_readback(net: &Net, book: &DefinitionBook, id_map: HashMap<run::Val, DefId>) -> (String, bool) {
-  let net = hvml::net::hvmc_to_net::hvmc_to_net(net, &|val| id_map[&val]);
+// The hvm-lang definition of each runtime REF. hvm-lang identifies definitions by the values of
+// their names (see 'ast::name_to_val'), but runtime REFs hold ids of the runtime book's own symbol
+// table, so they're matched by name.
+pub struct IdMap {
+  syms: Symbols, // names of the runtime ids
+  defs: HashMap<run::Val, DefId>, // definition of each runtime id
+}
+
+impl IdMap {
+  // The definition of a reference read back from the runtime net.
+  pub fn get(&self, nam: run::Val) -> DefId {
+    self.defs[&self.syms.id(&val_to_name(nam)).unwrap()]
+  }
+}
+
+pub fn hvm_lang_readback(net: &Net, book: &DefinitionBook, id_map: IdMap) -> (String, bool) {
+  let net = hvml::net::hvmc_to_net::hvmc_to_net(net, &|val| id_map.get(val));
   let (res_term, readback_errs) = hvml::term::net_to_term::net_to_term(&net, book, &Default::default(), false);
 
   (res_term.clone().display(&book.def_names).to_string(), readback_errs.is_empty())
 }
 
-pub fn hvm_lang_normal<'a>(book: &mut DefinitionBook, size: usize) -> (run::Net<'a>, Net, HashMap<run::Val, DefId>) {
+pub fn hvm_lang_normal<'a>(book: &mut DefinitionBook, size: usize) -> (run::Net<'a>, Net, IdMap) {
   let result = hvml::compile_book(book).unwrap();
-  let (root, res_lnet) = normal(result.core_book, size);
-  (root, res_lnet, result.hvmc_names.hvmc_name_to_id)
+  let book = book_to_runtime(&result.core_book).unwrap();
+  let (root, res_lnet) = normal_runtime(&book, size);
+  let defs = result.hvmc_names.hvmc_name_to_id.iter().filter_map(|(val, id)| Some((book.syms.id(&val_to_name(*val))?, *id)));
+  (root, res_lnet, IdMap { defs: defs.collect(), syms: book.syms })
 }
 
-#[allow(unused_variables)]
 pub fn normal(book: Book, size: usize) -> (run::Net<'static>, Net) {
-  fn normal_cpu(book: run::Book, size: usize) -> run::Net<'static> {
+  normal_runtime(&book_to_runtime(&book).unwrap(), size)
+}
+
+#[allow(unused_variables)]
+pub fn normal_runtime(book: &run::Book, size: usize) -> (run::Net<'static>, Net) {
+  fn normal_cpu(book: &run::Book, size: usize) -> run::Net<'static> {
     let data = Box::leak(run::Heap::init(size));
     let mut rnet = run::Net::new(data);
-    rnet.boot(name_to_val("main"));
-    rnet.normal(&book);
+    rnet.boot(book.syms.id("main").unwrap());
+    rnet.normal(book).unwrap();
     rnet
   }
 
   #[cfg(feature = "cuda")]
-  fn normal_gpu(book: run::Book) -> run::Net {
-    let (_, host_net) = hvmc::cuda::host::run_on_gpu(&book, "main").unwrap();
+  fn normal_gpu(book: &run::Book) -> run::Net {
+    let (_, host_net) = hvmc::cuda::host::run_on_gpu(book, "main").unwrap();
     host_net.to_runtime_net()
   }
 
-  let book = book_to_runtime(&book);
-
   let rnet = {
     #[cfg(not(feature = "cuda"))]
     {
@@ -75,6 +94,6 @@ pub fn normal(book: Book, size: usize) -> (run::Net<'static>, Net) {
     }
   };
 
-  let net = net_from_runtime(&rnet);
+  let net = net_from_runtime(&rnet, &book.syms);
   (rnet, net)
 }
@@ -4,13 +4,37 @@ mod loaders;
 mod numeric_tests {
   use crate::loaders::*;
   use hvmc::{
-    ast::{show_net, Book},
-    run::{self, Lab},
+    ast::{book_to_runtime, show_net, validate_book, Book, Net, Problem, Tree},
+    binary::{book_from_bytes, book_to_bytes, BinaryError},
+    run::{self, Lab, Val},
   };
   use insta::{assert_debug_snapshot, assert_snapshot};
 
-  fn op_net(lnum: u32, op: Lab, rnum: u32) -> Book {
-    parse_core(&format!("@main = root & <#{lnum} <#{rnum} root>> ~ #{op}"))
+  // Builds '@main = root & #lnum ~ <op #rnum root>' directly, since '/' can't be parsed. It takes 3
+  // rewrites (booting, then one per operand), where the old '<#lnum <#rnum root>> ~ #op' took 5.
+  fn op_net(lnum: Val, op: Lab, rnum: Val) -> Book {
+    let root = Tree::Var { nam: "root".to_string() };
+    let opr = Tree::Op2 { opr: op, lft: Box::new(Tree::Num { typ: run::U60, val: rnum }), rgt: Box::new(root.clone()) };
+    let main = Net { root, rdex: vec![(Tree::Num { typ: run::U60, val: lnum }, opr)] };
+    Book::from([("main".to_string(), main)])
+  }
+
+  // The value of a net reduced to a number.
+  fn net_val(net: &Net) -> Val {
+    match net.root {
+      Tree::Num { val, .. } => val,
+      _ => panic!("Not a number."),
+    }
+  }
+
+  // Reduces an op net on a strict net, returning its error.
+  fn strict_error(lnum: Val, op: Lab, rnum: Val) -> run::RuntimeError {
+    let book = book_to_runtime(&op_net(lnum, op, rnum)).unwrap();
+    let data = run::Heap::init(16);
+    let mut rnet = run::Net::new(&data);
+    rnet.strict = true;
+    rnet.boot(book.syms.id("main").unwrap());
+    rnet.normal(&book).unwrap_err()
   }
 
   #[test]
@@ -18,7 +42,7 @@ mod numeric_tests {
     let net = op_net(10, run::ADD, 2);
     let (rnet, net) = normal(net, 16);
     assert_snapshot!(show_net(&net), @"#12");
-    assert_debug_snapshot!(rnet.rewrites(), @"5");
+    assert_debug_snapshot!(rnet.rewrites(), @"3");
   }
 
   #[test]
@@ -102,8 +126,8 @@ mod numeric_tests {
   fn test_not() {
     let net = op_net(0, run::NOT, 256);
     let (rnet, net) = normal(net, 16);
-    assert_snapshot!(show_net(&net), @"#16776959");
-    assert_debug_snapshot!(rnet.rewrites(), @"5");
+    assert_snapshot!(show_net(&net), @"#1152921504606846975");
+    assert_debug_snapshot!(rnet.rewrites(), @"3");
   }
 
   #[test]
@@ -121,13 +145,167 @@ mod numeric_tests {
   }
 
   #[test]
-  /// Division by zero always return the value of 0xFFFFFF,
-  /// that is read as the unsigned integer `16777215`
+  fn test_min_max() {
+    let (_rnet, net) = normal(op_net(10, run::MIN, 2), 16);
+    assert_snapshot!(show_net(&net), @"#2");
+    let (_rnet, net) = normal(op_net(10, run::MAX, 2), 16);
+    assert_snapshot!(show_net(&net), @"#10");
+    // -1 is the largest u60, but the smallest of these i60s
+    let (_rnet, net) = normal(op_net(hvmc::i60::new(-1), run::MAX, 2), 16);
+    assert_snapshot!(show_net(&net), @"#1152921504606846975");
+    let (_rnet, net) = normal(op_net(hvmc::i60::new(-1), run::I60 | run::MAX, 2), 16);
+    assert_snapshot!(show_net(&net), @"#2");
+  }
+
+  #[test]
+  fn test_pow() {
+    let (_rnet, net) = normal(op_net(3, run::POW, 4), 16);
+    assert_snapshot!(show_net(&net), @"#81");
+    let (_rnet, net) = normal(op_net(2, run::POW, 60), 16);
+    assert_snapshot!(show_net(&net), @"#0");
+    let (_rnet, net) = normal(op_net(3, run::POW, 0), 16);
+    assert_snapshot!(show_net(&net), @"#1");
+    let (_rnet, net) = normal(op_net(hvmc::i60::new(-2), run::I60 | run::POW, 3), 16);
+    assert_eq!(hvmc::i60::val(net_val(&net)), -8);
+    let (_rnet, net) = normal(op_net(2, run::I60 | run::POW, hvmc::i60::new(-1)), 16);
+    assert_snapshot!(show_net(&net), @"#0");
+    let (_rnet, net) = normal(op_net(hvmc::f60::new(2.0), run::F60 | run::POW, hvmc::f60::new(0.5)), 16);
+    assert_snapshot!(hvmc::f60::show(net_val(&net)), @"1.414213562373096");
+  }
+
+  #[test]
+  fn test_rotate() {
+    let (_rnet, net) = normal(op_net(0b1011, run::ROL, 2), 16);
+    assert_snapshot!(show_net(&net), @"#44");
+    let (_rnet, net) = normal(op_net(0b1011, run::ROR, 2), 16);
+    assert_snapshot!(show_net(&net), @"#864691128455135234");
+    let (_rnet, net) = normal(op_net(0b1011, run::ROL, 62), 16);
+    assert_snapshot!(show_net(&net), @"#44");
+    let (_rnet, net) = normal(op_net(0b1011, run::ROR, 60), 16);
+    assert_snapshot!(show_net(&net), @"#11");
+  }
+
+  #[test]
+  fn test_bit_counts() {
+    let (_rnet, net) = normal(op_net(0b1011000, run::POP, 0), 16);
+    assert_snapshot!(show_net(&net), @"#3");
+    let (_rnet, net) = normal(op_net(0b1011000, run::CLZ, 0), 16);
+    assert_snapshot!(show_net(&net), @"#53");
+    let (_rnet, net) = normal(op_net(0b1011000, run::CTZ, 0), 16);
+    assert_snapshot!(show_net(&net), @"#3");
+    // Zero has 60 leading and trailing zeros
+    let (_rnet, net) = normal(op_net(0, run::CLZ, 0), 16);
+    assert_snapshot!(show_net(&net), @"#60");
+    let (_rnet, net) = normal(op_net(0, run::CTZ, 0), 16);
+    assert_snapshot!(show_net(&net), @"#60");
+    let (_rnet, net) = normal(op_net(hvmc::i60::new(-1), run::I60 | run::POP, 0), 16);
+    assert_snapshot!(show_net(&net), @"#60");
+  }
+
+  #[test]
+  /// Division by zero always return the value of 0xFFF_FFFF_FFFF_FFFF,
+  /// that is read as the unsigned integer `1152921504606846975`
   fn test_div_by_0() {
     let net = op_net(9, run::DIV, 0);
     let (rnet, net) = normal(net, 16);
-    assert_snapshot!(show_net(&net), @"#16777215");
-    assert_debug_snapshot!(rnet.rewrites(), @"5");
+    assert_snapshot!(show_net(&net), @"#1152921504606846975");
+    assert_debug_snapshot!(rnet.rewrites(), @"3");
+  }
+
+  #[test]
+  /// Modulus by zero returns the dividend
+  fn test_mod_by_0() {
+    let net = op_net(9, run::MOD, 0);
+    let (_rnet, net) = normal(net, 16);
+    assert_snapshot!(show_net(&net), @"#9");
+  }
+
+  #[test]
+  /// On i60s, division by zero returns -1, which has the same bits
+  fn test_i60_div_by_0() {
+    let net = op_net(9, run::I60 | run::DIV, 0);
+    let (_rnet, net) = normal(net, 16);
+    assert_snapshot!(show_net(&net), @"#-1");
+    let net = op_net(9, run::I60 | run::MOD, 0);
+    let (_rnet, net) = normal(net, 16);
+    assert_snapshot!(show_net(&net), @"#9");
+  }
+
+  #[test]
+  /// Shifting by 60 or more bits shifts every bit out, instead of wrapping the amount
+  fn test_big_shifts() {
+    let (_rnet, net) = normal(op_net(10, run::LSH, 60), 16);
+    assert_snapshot!(show_net(&net), @"#0");
+    let (_rnet, net) = normal(op_net(10, run::LSH, 64), 16);
+    assert_snapshot!(show_net(&net), @"#0");
+    let (_rnet, net) = normal(op_net(10, run::RSH, 65), 16);
+    assert_snapshot!(show_net(&net), @"#0");
+    let (_rnet, net) = normal(op_net(10, run::LSH, 59), 16);
+    assert_snapshot!(show_net(&net), @"#0");
+    let (_rnet, net) = normal(op_net(1, run::LSH, 59), 16);
+    assert_snapshot!(show_net(&net), @"#576460752303423488");
+    // Arithmetic shifts keep the sign
+    let (_rnet, net) = normal(op_net(run::Val::MAX >> 4, run::I60 | run::RSH, 100), 16);
+    assert_snapshot!(show_net(&net), @"#-1");
+  }
+
+  #[test]
+  /// Strict nets report undefined operations, with the offending redex
+  fn test_strict() {
+    assert_snapshot!(strict_error(9, run::DIV, 0), @"arithmetic error (division by zero): <9/ a> ~ #0");
+    assert_snapshot!(strict_error(9, run::I60 | run::MOD, 0), @"arithmetic error (division by zero): <9%:i60 a> ~ #0");
+    assert_snapshot!(strict_error(9, run::LSH, 64), @"arithmetic error (shift amount out of range): <9<< a> ~ #64");
+    let run::RuntimeError::Arithmetic(err) = strict_error(9, run::DIV, 0) else { panic!() };
+    assert_eq!((err.opr, err.lft, err.rgt), (run::DIV, 9, 0));
+
+    // Defined operations, including float division by zero, still work
+    let book = book_to_runtime(&op_net(9, run::DIV, 3)).unwrap();
+    let data = run::Heap::init(16);
+    let mut rnet = run::Net::new(&data);
+    rnet.strict = true;
+    rnet.boot(book.syms.id("main").unwrap());
+    rnet.normal(&book).unwrap();
+    assert_snapshot!(show_net(&hvmc::ast::net_from_runtime(&rnet, &book.syms)), @"#3");
+    let book = book_to_runtime(&op_net(hvmc::f60::new(1.0), run::F60 | run::DIV, 0)).unwrap();
+    let data = run::Heap::init(16);
+    let mut rnet = run::Net::new(&data);
+    rnet.strict = true;
+    rnet.boot(book.syms.id("main").unwrap());
+    rnet.normal(&book).unwrap();
+    assert_eq!(hvmc::f60::val(rnet.heap.get_root().val()), f64::INFINITY);
+  }
+
+  #[test]
+  /// Numbers are untyped, so a result is read back as an u60, even if an i60 or f60 operation made it
+  fn test_typed_root() {
+    let (rnet, net) = normal(op_net(0, run::I60 | run::SUB, 1), 16);
+    assert_snapshot!(show_net(&net), @"#-1");
+    assert_snapshot!(hvmc::i60::show(rnet.heap.get_root().val()), @"-1");
+    // Operands of a stuck operation are still shown in the type of their operator
+    let (_rnet, net) = normal(parse_core("@main = (a b) & #0 ~ <-:i60 #1 <+:i60 a b>>"), 16);
+    assert_snapshot!(show_net(&net), @"(<-1+:i60 a> a)");
+  }
+
+  #[test]
+  fn test_unknown_operator() {
+    for op in [run::F60 + 0x100, run::I60 | (run::CTZ + 1)] {
+      let errs = validate_book(&op_net(9, op, 3)).unwrap_err();
+      assert_eq!(errs[0].problem, Problem::BadOperator { opr: op });
+      let book = book_to_runtime(&op_net(9, op, 3)).unwrap();
+      let bytes = book_to_bytes(&book);
+      assert_eq!(book_from_bytes(&bytes).err(), Some(BinaryError::BadOperator { lab: op }));
+      // Unvalidated books stop with an error, rather than panicking
+      let data = run::Heap::init(16);
+      let mut rnet = run::Net::new(&data);
+      rnet.boot(book.syms.id("main").unwrap());
+      let run::RuntimeError::Arithmetic(err) = rnet.normal(&book).unwrap_err() else { panic!() };
+      assert_eq!((err.opr, err.rule), (op, "unknown operator"));
+    }
+    assert_snapshot!(validate_book(&op_net(9, run::F60 + 0x100, 3)).unwrap_err()[0].to_string(), @"@main: operator label 0x300 has an unknown type or operation");
+    assert_snapshot!(show_net(&op_net(9, run::I60 | (run::CTZ + 1), 3)["main"]), @r###"
+    root
+    & #9 ~ <?19:i60 #3 root>
+    "###);
   }
 
   #[test]
@@ -1,4 +1,14 @@
-use hvmc::ast::show_net;
+use hvmc::{
+  ast::{book_to_runtime, show_runtime_book, do_parse_book, do_parse_book_with_encoding, do_parse_net, do_parse_tree, net_from_runtime, show_net, show_net_sugared, show_tree, show_tree_sugared, validate_book, name_to_val, val_to_name, Encoding, Symbols},
+  binary::{book_from_bytes, book_to_bytes, net_from_bytes, net_to_bytes, trace_from_bytes, trace_to_bytes, BinaryError},
+  check::check_book,
+  cst,
+  debug::Debugger,
+  dot::net_to_dot,
+  fmt::{format_book, FmtConfig},
+  jit, profile,
+  run, trace,
+};
 use insta::{assert_debug_snapshot, assert_snapshot};
 use loaders::*;
 
@@ -80,3 +90,985 @@ fn test_queue() {
   assert_snapshot!(readback, @"λa λ* ((a λ* λb b) λc λ* ((c λd λe (d e)) λf λ* ((f λg λh (g (g h))) λ* λi i)))");
   assert_debug_snapshot!(rnet.rewrites(), @"65");
 }
+
+#[test]
+fn test_parse_error() {
+  let code = "@main = (a b)\n  & @foo ~ (a $)";
+  let err = do_parse_book(code).unwrap_err();
+  assert_debug_snapshot!(err.offset, @"28");
+  assert_snapshot!(err.to_string(), @"expected a tree, found '$' at 2:15");
+  assert_snapshot!(err.render(code), @r###"
+  error: expected a tree, found '$' at 2:15
+    |
+  2 |   & @foo ~ (a $)
+    |               ^
+  "###);
+}
+
+#[test]
+fn test_out_of_memory() {
+  let book = book_to_runtime(&parse_core("@main = (((a a) (b b)) ((c c) (d d)))")).unwrap();
+  let data = run::Heap::init(4);
+  let mut rnet = run::Net::new(&data);
+  rnet.boot(book.syms.id("main").unwrap());
+  let err = rnet.normal(&book).unwrap_err();
+  assert_snapshot!(err.to_string(), @"out of memory: thread 0 has no free node in area [0, 4)");
+
+  // Heaps with fewer nodes than threads can't give each thread an area.
+  for scheduler in [run::Scheduler::Split, run::Scheduler::Steal] {
+    let config = run::RuntimeConfig { threads: 8, scheduler, ..Default::default() };
+    let mut rnet = run::Net::new(&data);
+    rnet.boot(book.syms.id("main").unwrap());
+    let err = rnet.parallel_normal(&book, &config).unwrap_err();
+    assert_eq!(err.to_string(), "out of memory: thread 0 has no free node in area [0, 0)");
+  }
+
+  // Definitions are injected into heaps of 2^16 nodes, so bigger ones fail to load.
+  let book = parse_core(&format!("@main = *{}", " & * ~ (* *)".repeat(1 << 16)));
+  let Err(err) = book_to_runtime(&book) else { panic!("expected an error") };
+  assert_snapshot!(err.to_string(), @"@main: out of memory: thread 0 has no free node in area [0, 65536)");
+  assert_eq!(check_book(&book).errors, [err]);
+}
+
+#[test]
+fn test_invalid_interaction() {
+  let book = book_to_runtime(&parse_core("@main = a & ?<(#1 *) a> ~ ?<(b b) *>")).unwrap();
+  let data = run::Heap::init(1 << 8);
+  let mut rnet = run::Net::new(&data);
+  rnet.boot(book.syms.id("main").unwrap());
+  let err = rnet.normal(&book).unwrap_err();
+  assert_snapshot!(err.to_string(), @"invalid interaction (no rule for this pair): ?<(#1 *) a> ~ ?<(a a) *>");
+
+  let config = run::RuntimeConfig { threads: 2, ..Default::default() };
+  let mut rnet = run::Net::new(&data);
+  rnet.boot(book.syms.id("main").unwrap());
+  // The failed pair may be reduced by any thread.
+  let run::RuntimeError::Threads(errs) = rnet.parallel_normal(&book, &config).unwrap_err() else { panic!() };
+  assert_eq!(errs.len(), 1);
+  assert_snapshot!(errs[0].err.to_string(), @"invalid interaction (no rule for this pair): ?<(#1 *) a> ~ ?<(a a) *>");
+
+  // Every thread that fails is reported, with the trees of its failed pair.
+  let net = do_parse_net("(a b) & ?<(#1 *) a> ~ ?<(c c) *> & ?<(#2 *) b> ~ ?<(d d) *>").unwrap();
+  let mut rnet = run::Net::new(&data);
+  hvmc::ast::net_to_runtime(&mut rnet, &mut Symbols::new(), &net).unwrap();
+  let err = rnet.parallel_normal(&book, &config).unwrap_err();
+  assert_snapshot!(err.to_string(), @r###"
+  thread 0: invalid interaction (no rule for this pair): ?<(#1 *) a> ~ ?<(a a) *>
+  thread 1: invalid interaction (no rule for this pair): ?<(#2 *) a> ~ ?<(a a) *>
+  "###);
+}
+
+#[test]
+fn test_fuel() {
+  let book = book_to_runtime(&parse_core(
+    "
+    @add  = (<+ a b> (a b))
+    @sum  = (?<(#1 @sumS) a> a)
+    @sumS = ({2 a b} c) & @add ~ (e (d c)) & @sum ~ (a d) & @sum ~ (b e)
+    @main = a & @sum ~ (#6 a)
+  ",
+  )).unwrap();
+  let data = run::Heap::init(1 << 12);
+
+  let mut rnet = run::Net::new(&data);
+  rnet.boot(book.syms.id("main").unwrap());
+  assert!(!rnet.normal_with_fuel(&book, 50).unwrap());
+  assert!(rnet.rewrites() >= 50 && rnet.rdex.len() > 0);
+  assert_eq!(net_from_runtime(&rnet, &book.syms).rdex.len(), rnet.rdex.len());
+  assert!(rnet.normal_with_fuel(&book, usize::MAX).unwrap());
+  assert_snapshot!(show_net(&net_from_runtime(&rnet, &book.syms)), @"#64");
+
+  let config = run::RuntimeConfig { threads: 3, ..Default::default() };
+  let data = run::Heap::init(1 << 12);
+  let mut rnet = run::Net::new(&data);
+  rnet.boot(book.syms.id("main").unwrap());
+  assert!(!rnet.parallel_normal_with_fuel(&book, &config, 50).unwrap());
+  assert!(rnet.rewrites() >= 50 && rnet.rdex.len() > 0);
+  assert_eq!(net_from_runtime(&rnet, &book.syms).rdex.len(), rnet.rdex.len());
+  assert!(rnet.parallel_normal_with_fuel(&book, &config, usize::MAX).unwrap());
+  assert_snapshot!(show_net(&net_from_runtime(&rnet, &book.syms)), @"#64");
+
+  // Threads may overshoot a small fuel, which must not underflow the fuel left.
+  for threads in [2, 8] {
+    let config = run::RuntimeConfig { threads, ..Default::default() };
+    for fuel in [1, 2, 3, 5, 8, 13] {
+      let data = run::Heap::init(1 << 12);
+      let mut rnet = run::Net::new(&data);
+      rnet.boot(book.syms.id("main").unwrap());
+      while !rnet.parallel_normal_with_fuel(&book, &config, fuel).unwrap() {}
+      assert_eq!(show_net(&net_from_runtime(&rnet, &book.syms)), "#64");
+    }
+  }
+
+  // Resumed threads keep allocating where they stopped, rather than searching their whole area.
+  let config = run::RuntimeConfig { threads: 4, ..Default::default() };
+  let data = run::Heap::init(1 << 12);
+  let mut rnet = run::Net::new(&data);
+  rnet.boot(book.syms.id("main").unwrap());
+  assert!(!rnet.parallel_normal_with_fuel(&book, &config, 50).unwrap());
+  for tid in 0 .. 4 {
+    let fork = rnet.fork(tid, 4);
+    assert!(fork.next + 1 < fork.area.size);
+  }
+  assert!(rnet.parallel_normal_with_fuel(&book, &config, usize::MAX).unwrap());
+  assert_snapshot!(show_net(&net_from_runtime(&rnet, &book.syms)), @"#64");
+}
+
+#[test]
+fn test_dot() {
+  let net = do_parse_net("(a {2 b c}) & <+ a b> ~ ?<(#1 *) c>").unwrap();
+  assert_snapshot!(net_to_dot(&net), @r###"
+  graph net {
+    node [fontname="monospace"];
+    root [shape=plaintext, label="root"];
+    n0 [shape=triangle, label="CON"];
+    root -- n0:n;
+    n1 [shape=house, label="DUP 2"];
+    n0:se -- n1:n;
+    r0 [shape=point, color=red];
+    n2 [shape=Mdiamond, label="+"];
+    r0 -- n2:n [color=red, penwidth=2];
+    n0:sw -- n2:sw;
+    n1:sw -- n2:se;
+    n3 [shape=pentagon, label="?"];
+    r0 -- n3:n [color=red, penwidth=2];
+    n4 [shape=triangle, label="CON"];
+    n3:sw -- n4:n;
+    n5 [shape=ellipse, label="#1"];
+    n4:sw -- n5:n;
+    n6 [shape=circle, label="*"];
+    n4:se -- n6:n;
+    n1:se -- n3:se;
+  }
+  "###);
+}
+
+#[test]
+fn test_validate_book() {
+  let book = do_parse_book(
+    "
+    @foo = (a (b b)) & a ~ @bar
+    @baz = (<1152921504606846976+ a> (a c)) & @foo ~ (c c)
+  ",
+  )
+  .unwrap();
+  let errs = validate_book(&book).unwrap_err();
+  let errs = errs.iter().map(|err| err.to_string()).collect::<Vec<_>>();
+  assert_snapshot!(errs.join("\n"), @r###"
+  @main: definition not found
+  @baz: OP1 literal #1152921504606846976 doesn't fit in 60 bits
+  @baz: variable 'c' occurs 3 time(s), expected 2
+  @foo: variable 'a' is a side of an active pair
+  @foo: reference to undefined '@bar'
+  "###);
+  assert!(validate_book(&do_parse_book("@main = a & @main ~ (a b)").unwrap()).is_err());
+  assert!(validate_book(&do_parse_book("@main = (a a)").unwrap()).is_ok());
+
+  // Unvalidated books fail to load, instead of panicking.
+  let Err(err) = book_to_runtime(&book) else { panic!("expected an error") };
+  assert_snapshot!(err.to_string(), @"@foo: variable 'a' is a side of an active pair");
+}
+
+#[test]
+fn test_check_book() {
+  let book = do_parse_book(
+    "
+    @add  = (<+ a b> (a b))
+    @sum  = (?<(#1 @sumS) a> a)
+    @sumS = ({2 a b} c) & @add ~ (e (d c)) & @sum ~ (a d) & @sum ~ (b e)
+    @main = a & @sum ~ (#6 a)
+    @dead = (a *) & @dead ~ (a @lost)
+  ",
+  )
+  .unwrap();
+  let report = check_book(&book);
+  assert_snapshot!(report.show(), @r###"
+  definitions: 5
+    @add: 3 nodes, 0 redexes, safe
+    @dead: invalid
+    @main: 1 nodes, 1 redexes, unsafe (REF)
+    @sum: 3 nodes, 0 redexes, unsafe (REF)
+    @sumS: 6 nodes, 3 redexes, unsafe (DUP, REF)
+  unreachable from @main: @dead
+  recursive: @dead
+  recursive: @sum, @sumS
+  error: @dead: reference to undefined '@lost'
+  "###);
+  assert_snapshot!(report.to_json(), @r###"{"ok":false,"definitions":5,"defs":[{"name":"add","nodes":3,"redexes":0,"safe":true,"blockers":[]},{"name":"dead","nodes":null,"redexes":null,"safe":null,"blockers":null},{"name":"main","nodes":1,"redexes":1,"safe":false,"blockers":["REF"]},{"name":"sum","nodes":3,"redexes":0,"safe":false,"blockers":["REF"]},{"name":"sumS","nodes":6,"redexes":3,"safe":false,"blockers":["DUP","REF"]}],"unreachable":["dead"],"cycles":[["dead"],["sum","sumS"]],"errors":[{"def":"dead","message":"reference to undefined '@lost'"}]}"###);
+}
+
+#[test]
+fn test_format_book() {
+  let code = "
+// Church numerals
+@c2 = ({2 (x y) (y z)} (x z))   // two
+@main = r & @add ~ (@c2 (@c2 r)) & @add // adds
+~ (k (w   (k w)))
+
+// Scott naturals
+@succ = (p ((@pred_case (@succ_case p)) (* (@succ_case @succ_ret))))
+";
+  let config = FmtConfig { width: 40, rename: true };
+  let formatted = format_book(code, &config).unwrap();
+  assert_eq!(format_book(&formatted, &config).unwrap(), formatted);
+  assert_eq!(do_parse_book(&format_book(code, &FmtConfig::default()).unwrap()).unwrap(), do_parse_book(code).unwrap());
+  assert_snapshot!(formatted, @r###"
+  // Church numerals
+  @c2 = ({2 (a b) (b c)} (a c)) // two
+  @main
+    = a
+    & @add ~ (@c2 (@c2 a))
+    // adds
+    & @add ~ (b (c (b c)))
+
+  // Scott naturals
+  @succ = (
+    a
+    (
+      (@pred_case (@succ_case a))
+      (* (@succ_case @succ_ret))
+    )
+  )
+  "###);
+
+  // Numbers keep the type they were written in, even outside of operators.
+  assert_eq!(format_book("@main = (#-5  <1.5 *:f60 #-0.0>)", &FmtConfig::default()).unwrap(), "@main = (#-5 <1.5*:f60 #-0.0>)\n");
+}
+
+#[test]
+fn test_cst_round_trip() {
+  let code = "// doc
+@foo = ( a  {2 b// inner
+ c}) // end
+  & <+ b c>~?<(#1 @foo ) a > // pair
+@bar=<12- *>
+
+// trailing
+";
+  let book = cst::do_parse_book(code).unwrap();
+  assert_eq!(cst::show_book(&book), code);
+  assert_eq!(cst::book_to_ast(&book, Encoding::Scott), do_parse_book(code).unwrap());
+  assert_eq!(book.defs[0].at.comments(), ["// doc"]);
+  assert_eq!(book.defs[0].net.rdex[0].amp.comments(), ["// end"]);
+  assert_eq!(book.defs[1].at.comments(), ["// pair"]);
+  assert_eq!(cst::do_parse_book("@foo = (a $)").unwrap_err(), do_parse_book("@foo = (a $)").unwrap_err());
+
+  // Every example round-trips, too.
+  let mut dirs = vec![std::path::PathBuf::from(concat!(env!("CARGO_MANIFEST_DIR"), "/examples"))];
+  while let Some(dir) = dirs.pop() {
+    for entry in std::fs::read_dir(dir).unwrap() {
+      let path = entry.unwrap().path();
+      if path.is_dir() {
+        dirs.push(path);
+      } else if path.extension().map_or(false, |ext| ext == "hvmc") {
+        let code = std::fs::read_to_string(&path).unwrap();
+        let book = cst::do_parse_book(&code).unwrap();
+        assert_eq!(cst::show_book(&book), code, "{}", path.display());
+        assert_eq!(cst::book_to_ast(&book, Encoding::Scott), do_parse_book(&code).unwrap(), "{}", path.display());
+      }
+    }
+  }
+}
+
+#[test]
+fn test_long_names() {
+  // These share their first 10 characters, which used to make them alias.
+  let net = parse_core(
+    "
+    @List.map.go.left  = (a (* a))
+    @List.map.go.right = (* (b b))
+    @main = root & @List.map.go.right ~ (#1 (#2 root))
+  ",
+  );
+  let syms = book_to_runtime(&net).unwrap().syms;
+  let (_, net) = normal(net, 16);
+  assert_snapshot!(show_net(&net), @"#2");
+  assert_ne!(syms.id("List.map.go.left"), syms.id("List.map.go.right"));
+  assert_eq!(syms.name(syms.id("List.map.go.right").unwrap()), Some("List.map.go.right"));
+  // Readback takes the values of references from the book.
+  assert_eq!(syms.val(syms.id("List.map.go.right").unwrap()), name_to_val("List.map.go.right"));
+  assert_eq!(syms.val(syms.id("main").unwrap()), name_to_val("main"));
+
+  // Each book numbers its own names, definitions first, in name order.
+  assert_eq!(syms.names(), ["List.map.go.left", "List.map.go.right", "main"]);
+  let syms = book_to_runtime(&parse_core("@main = @IO.print")).unwrap().syms;
+  assert_eq!(syms.names(), ["main", "IO.print"]);
+
+  // Tables are restored from their names, which can't repeat.
+  assert_eq!(Symbols::load(&["b", "a"]).unwrap().id("a"), Some(1));
+  assert_snapshot!(Symbols::load(&["a", "b", "a"]).unwrap_err().to_string(), @"symbol collision: 'a' is listed as both id 0 and 2");
+
+  // References keep the packed values of short names, and long names get values of their own.
+  assert_eq!(name_to_val("main"), 0xC24B31);
+  assert_ne!(name_to_val("0main"), name_to_val("main"));
+  for name in ["main", "0main", "List.map.go.left", "List.map.go.right", "$x"] {
+    assert_eq!(val_to_name(name_to_val(name)), name);
+  }
+}
+
+#[test]
+fn test_binary_book() {
+  let book = book_to_runtime(&parse_core(
+    "
+    @add = (<+ a b> (a b))
+    @sum.recursive = (?<(#1 @sum.step) a> a)
+    @sum.step = ({2 a b} c)
+      & @add ~ (e (d c))
+      & @sum.recursive ~ (a d)
+      & @sum.recursive ~ (b e)
+    @main = a & @sum.recursive ~ (#10 a)
+  ",
+  )).unwrap();
+  let bytes = book_to_bytes(&book);
+  let back = book_from_bytes(&bytes).unwrap();
+  assert_eq!(show_runtime_book(&back), show_runtime_book(&book));
+  assert_eq!(book_to_bytes(&back), bytes);
+
+  // Loaded books run like the original.
+  let data = run::Heap::init(1 << 12);
+  let mut rnet = run::Net::new(&data);
+  rnet.boot(book.syms.id("main").unwrap());
+  rnet.normal(&back).unwrap();
+  assert_snapshot!(hvmc::ast::show_runtime_net(&rnet, &book.syms), @"#1024");
+
+  let mut corrupt = bytes.clone();
+  corrupt[40] ^= 1;
+  assert_eq!(book_from_bytes(&corrupt).err(), Some(BinaryError::BadChecksum));
+  assert_eq!(book_from_bytes(&bytes[.. bytes.len() - 1]).err(), Some(BinaryError::BadChecksum));
+  assert_eq!(book_from_bytes(b"@main = *").err(), Some(BinaryError::BadMagic));
+  let mut future = bytes.clone();
+  future[8] = 3;
+  assert_snapshot!(book_from_bytes(&future).err().unwrap().to_string(), @"unsupported binary format version 3 (expected 2)");
+
+  // Definitions with pointers outside of their nodes are rejected, even with a valid checksum.
+  let lam = run::Ptr::new(run::LAM, 0, 1);
+  for aux in [run::Ptr::new(run::VR2, 0, 0x4000_0000), run::Ptr::new(run::VR1, 0, 2), run::Ptr::new(run::LAM, 0, 0)] {
+    let mut evil = run::Book::new();
+    let main = evil.syms.intern("main");
+    evil.def(main, run::Def { safe: true, rdex: vec![], node: vec![(run::NULL, lam), (aux, run::ERAS)] });
+    assert_eq!(book_from_bytes(&book_to_bytes(&evil)).err(), Some(BinaryError::BadDefinition { name: "main".to_string() }));
+  }
+  let mut evil = run::Book::new();
+  let main = evil.syms.intern("main");
+  evil.def(main, run::Def { safe: true, rdex: vec![], node: vec![(run::NULL, run::ERAS); (1 << 16) + 1] });
+  assert_snapshot!(book_from_bytes(&book_to_bytes(&evil)).err().unwrap().to_string(), @"binary definition '@main' has too many nodes, or a pointer outside of them");
+}
+
+#[test]
+fn test_snapshot() {
+  let code = "
+    @add  = (<+ a b> (a b))
+    @sum  = (?<(#1 @sumS) a> a)
+    @sumS = ({2 a b} c) & @add ~ (e (d c)) & @sum ~ (a d) & @sum ~ (b e)
+    @main = a & @sum ~ (#8 a)
+  ";
+  let book = book_to_runtime(&parse_core(code)).unwrap();
+  let config = run::RuntimeConfig { threads: 2, ..Default::default() };
+  let data = run::Heap::init(1 << 12);
+  let mut rnet = run::Net::new(&data);
+  rnet.boot(book.syms.id("main").unwrap());
+  assert!(!rnet.parallel_normal_with_fuel(&book, &config, 300).unwrap());
+  let bytes = net_to_bytes(&rnet, &book);
+
+  // Resumes on a fresh heap, and finishes like an uninterrupted run.
+  let data = run::Heap::init(1 << 12);
+  let mut resumed = run::Net::new(&data);
+  net_from_bytes(&mut resumed, &book, &bytes).unwrap();
+  assert_eq!(resumed.rewrites(), rnet.rewrites());
+  assert_eq!(show_net(&net_from_runtime(&resumed, &book.syms)), show_net(&net_from_runtime(&rnet, &book.syms)));
+  resumed.normal(&book).unwrap();
+  assert_snapshot!(show_net(&net_from_runtime(&resumed, &book.syms)), @"#256");
+  let (full, _) = normal(parse_core(code), 1 << 12);
+  assert_eq!(resumed.rewrites(), full.rewrites());
+
+  let data = run::Heap::init(4);
+  let mut small = run::Net::new(&data);
+  assert!(matches!(net_from_bytes(&mut small, &book, &bytes), Err(BinaryError::HeapTooSmall { size: 4, .. })));
+  assert_eq!(net_from_bytes(&mut small, &book, &book_to_bytes(&book)).err(), Some(BinaryError::BadMagic));
+
+  // Pointers and allocation indices outside of the heap are rejected, even with a valid checksum.
+  let data = run::Heap::init(1 << 12);
+  let mut evil = run::Net::new(&data);
+  evil.rdex.push((run::Ptr::new(run::LAM, 0, 0x4000_0000), run::ERAS));
+  let bytes = net_to_bytes(&evil, &book);
+  assert_eq!(net_from_bytes(&mut run::Net::new(&data), &book, &bytes).err(), Some(BinaryError::HeapTooSmall { need: 0x4000_0001, size: 1 << 12 }));
+  let mut evil = run::Net::new(&data);
+  evil.heap.set(1, run::P1, run::Ptr::new(run::VR2, 0, 1 << 12));
+  let bytes = net_to_bytes(&evil, &book);
+  assert_eq!(net_from_bytes(&mut run::Net::new(&data), &book, &bytes).err(), Some(BinaryError::HeapTooSmall { need: (1 << 12) + 1, size: 1 << 12 }));
+  evil.heap.set(1, run::P1, run::NULL);
+  evil.next = usize::MAX;
+  let bytes = net_to_bytes(&evil, &book);
+  assert!(matches!(net_from_bytes(&mut run::Net::new(&data), &book, &bytes), Err(BinaryError::HeapTooSmall { size: 4096, .. })));
+}
+
+#[test]
+fn test_numeric_types() {
+  let code = "<+:i60 #-5 <*:f60 #1.5 <-3-:i60 <0.1%:f60 a>>>>";
+  let tree = hvmc::ast::do_parse_tree(code).unwrap();
+  assert_eq!(hvmc::ast::show_tree(&tree), code);
+  assert_eq!(hvmc::ast::show_tree(&hvmc::ast::do_parse_tree("<+:f60 #-0.0 a>").unwrap()), "<+:f60 #-0.0 a>");
+  // Numbers keep their type, in and out of operators.
+  assert_eq!(hvmc::ast::show_tree(&hvmc::ast::do_parse_tree("#-1").unwrap()), "#-1");
+  assert_eq!(hvmc::ast::show_tree(&hvmc::ast::do_parse_tree("<+:i60 #5 a>").unwrap()), "<+:i60 #5 a>");
+
+  // Results of typed operators are read back in their type.
+  let run = |code: &str| show_net(&normal(parse_core(code), 64).1);
+  assert_snapshot!(run("@main = #-5"), @"#-5");
+  assert_snapshot!(run("@main = #1.5"), @"#1.5");
+  assert_snapshot!(run("@main = <+:i60 a r> & #3 ~ <-:i60 #5 a>"), @"<+:i60 #-2 a>");
+  assert_snapshot!(run("@main = <+:i60 a r> & #-7 ~ <%:i60 #2 a>"), @"<+:i60 #-1 a>");
+  assert_snapshot!(run("@main = <+:i60 a r> & #-7 ~ <>>:i60 #1 a>"), @"<+:i60 #-4 a>");
+  assert_snapshot!(run("@main = <+:f60 a r> & #1.5 ~ <*:f60 #-2.5 a>"), @"<+:f60 #-3.75 a>");
+  assert_snapshot!(run("@main = <+:f60 a r> & #0.1 ~ <+:f60 #0.2 a>"), @"<+:f60 #0.300000000000001 a>");
+  assert_snapshot!(run("@main = a & #-1 ~ <<:i60 #0 a>"), @"#1");
+  assert_snapshot!(run("@main = a & #-1 ~ <<:u60 #0 a>"), @"#0");
+  assert_snapshot!(run("@main = a & #-1.5 ~ <<:f60 #-1.25 a>"), @"#1");
+
+  assert_snapshot!(do_parse_book("@main = <+:i32 a b>").unwrap_err().to_string(), @"expected a numeric type, found 'i32' at 1:12");
+}
+
+#[test]
+fn test_extended_operators() {
+  let code = "<min #1 <max:i60 #-2 <**:f60 #0.5 <3rol <ror:i60 #0 <7pop <clz #1 <0ctz a>>>>>>>>";
+  let tree = hvmc::ast::do_parse_tree(code).unwrap();
+  assert_eq!(hvmc::ast::show_tree(&tree), code);
+
+  let run = |code: &str| show_net(&normal(parse_core(code), 64).1);
+  assert_snapshot!(run("@main = a & #5 ~ <min #3 a>"), @"#3");
+  assert_snapshot!(run("@main = a & #-5 ~ <max:i60 #-3 a>"), @"#-3");
+  assert_snapshot!(run("@main = <+:f60 a r> & #9.0 ~ <**:f60 #0.5 a>"), @"<+:f60 #3.0 a>");
+  assert_snapshot!(run("@main = a & #2 ~ <**:u60 #10 a>"), @"#1024");
+  assert_snapshot!(run("@main = a & #255 ~ <pop #0 a>"), @"#8");
+  assert_snapshot!(run("@main = a & #1 ~ <ror #1 a>"), @"#576460752303423488");
+
+  assert_snapshot!(do_parse_book("@main = <mux a b>").unwrap_err().to_string(), @"expected an operator, found 'mux' at 1:10");
+}
+
+#[test]
+fn test_register_native() {
+  use std::sync::{atomic::{AtomicUsize, Ordering}, Arc};
+  // Runs a book where '@dbl' doubles numbers natively, counting the native calls.
+  let run = |code: &str| {
+    let mut book = book_to_runtime(&parse_core(code)).unwrap();
+    let calls = Arc::new(AtomicUsize::new(0));
+    let count = calls.clone();
+    book.register_native("dbl", move |net, _ptr, trg| {
+      let lam = net.get(trg);
+      if !lam.is_ctr() || !net.heap.get(lam.loc(), run::P1).is_num() {
+        return false;
+      }
+      count.fetch_add(1, Ordering::Relaxed);
+      let arg = net.heap.get(lam.loc(), run::P1);
+      net.heap.set(lam.loc(), run::P1, run::NULL);
+      net.half_atomic_link(run::Ptr::new(run::VR2, 0, lam.loc()), run::Ptr::big(run::NUM, arg.val() * 2));
+      true
+    });
+    let data = run::Heap::init(1 << 8);
+    let mut rnet = run::Net::new(&data);
+    rnet.boot(book.syms.id("main").unwrap());
+    rnet.normal(&book).unwrap();
+    (show_net(&net_from_runtime(&rnet, &book.syms)), calls.load(Ordering::Relaxed))
+  };
+  let dbl = "@dbl = ({2 <+ b r> b} r)\n";
+  assert_eq!(run(&format!("{dbl}@main = r & @dbl ~ (#21 r)")), ("#42".to_string(), 1));
+  // Declined calls expand the definition instead.
+  assert_eq!(run(&format!("{dbl}@two = #2\n@main = r & @dbl ~ (@two r)")), ("#4".to_string(), 0));
+}
+
+// An in-memory IO handler, recording the output.
+struct MemoryIo {
+  input: std::sync::Mutex<Vec<String>>,
+  output: std::sync::Arc<std::sync::Mutex<String>>,
+}
+
+impl hvmc::io::IoHandler for MemoryIo {
+  fn print(&self, text: &str) {
+    self.output.lock().unwrap().push_str(text);
+  }
+
+  fn read_line(&self) -> String {
+    self.input.lock().unwrap().pop().unwrap_or_default()
+  }
+
+  fn read_file(&self, path: &str) -> std::io::Result<String> {
+    match path {
+      "hello.txt" => Ok("Hello!".to_string()),
+      _ => Err(std::io::Error::new(std::io::ErrorKind::NotFound, "not found")),
+    }
+  }
+}
+
+// Runs a book with IO, returning its result (or error) and its output.
+fn run_io(code: &str, input: &[&str]) -> (String, String) {
+  let mut book = book_to_runtime(&parse_core(code)).unwrap();
+  let output = std::sync::Arc::new(std::sync::Mutex::new(String::new()));
+  let input = input.iter().rev().map(|line| line.to_string()).collect();
+  book.set_io(MemoryIo { input: std::sync::Mutex::new(input), output: output.clone() });
+  let data = run::Heap::init(1 << 12);
+  let mut rnet = run::Net::new(&data);
+  rnet.boot(book.syms.id("main").unwrap());
+  let result = match rnet.normal(&book) {
+    Ok(()) => show_net(&net_from_runtime(&rnet, &book.syms)),
+    Err(err) => err.to_string(),
+  };
+  let output = output.lock().unwrap().clone();
+  (result, output)
+}
+
+// A Scott-encoded string literal, with fresh variable names.
+fn str_code(text: &str) -> String {
+  static FRESH: std::sync::atomic::AtomicUsize = std::sync::atomic::AtomicUsize::new(0);
+  let fresh = || FRESH.fetch_add(1, std::sync::atomic::Ordering::Relaxed);
+  let mut code = format!("(* (n{0} n{0}))", fresh());
+  for chr in text.chars().rev() {
+    code = format!("((#{} ({} r{2})) (* r{2}))", chr as u32, code, fresh());
+  }
+  code
+}
+
+#[test]
+fn test_io() {
+  let hi = str_code("Hi!");
+  assert_debug_snapshot!(run_io(&format!("@main = r & @IO.print ~ ({hi} r)"), &[]), @r###"
+  (
+      "#0",
+      "Hi!",
+  )
+  "###);
+  // Echoes a line, which the print must wait for.
+  assert_debug_snapshot!(run_io("@main = r & @IO.read_line ~ (#0 s) & @IO.print ~ (s r)", &["echo"]), @r###"
+  (
+      "#0",
+      "echo",
+  )
+  "###);
+  // The second print waits for the first one, though it's reached first.
+  let code = format!("@main = r & @IO.print ~ ({} ?<({} *) s>) & @IO.print ~ (s r)", str_code("a"), str_code("b"));
+  assert_debug_snapshot!(run_io(&code, &[]), @r###"
+  (
+      "#0",
+      "ab",
+  )
+  "###);
+  // Strings behind references are expanded.
+  assert_debug_snapshot!(run_io(&format!("@hi = {hi}\n@main = r & @IO.print ~ (@hi r)"), &[]), @r###"
+  (
+      "#0",
+      "Hi!",
+  )
+  "###);
+  let path = str_code("hello.txt");
+  assert_debug_snapshot!(run_io(&format!("@main = r & @IO.read_file ~ ({path} s) & @IO.print ~ (s r)"), &[]), @r###"
+  (
+      "#0",
+      "Hello!",
+  )
+  "###);
+  assert_snapshot!(run_io(&format!("@main = r & @IO.read_file ~ ({} r)", str_code("nope")), &[]).0, @"IO error (@IO.read_file): nope: not found");
+  assert_snapshot!(run_io("@main = (s r) & @IO.print ~ (s r)", &[]).0, @"invalid interaction (IO argument never becomes data): @IO.print ~ (a b)");
+  assert_snapshot!(run_io("@main = * & @IO.print ~ (a b) & @IO.print ~ (b a)", &[]).0, @"invalid interaction (IO argument never becomes data): @IO.print ~ (a b)");
+  assert_snapshot!(run_io("@main = r & @IO.print ~ (#1 r)", &[]).0, @"invalid interaction (IO argument is not a string): @IO.print ~ (#1 a)");
+
+  // On many threads, calls wait for the other threads too, and are stuck once every thread is only
+  // left with waiting calls.
+  let code = "@main = r & @IO.read_line ~ (#0 s) & @IO.print ~ (s r)\n@stuck = * & @IO.print ~ (a b) & @IO.print ~ (b a)";
+  for scheduler in [run::Scheduler::Split, run::Scheduler::Steal] {
+    let mut book = book_to_runtime(&parse_core(code)).unwrap();
+    let output = std::sync::Arc::new(std::sync::Mutex::new(String::new()));
+    book.set_io(MemoryIo { input: std::sync::Mutex::new(vec!["echo".to_string()]), output: output.clone() });
+    let config = run::RuntimeConfig { threads: 4, scheduler, ..Default::default() };
+    let data = run::Heap::init(1 << 12);
+    let mut rnet = run::Net::new(&data);
+    rnet.boot(book.syms.id("main").unwrap());
+    rnet.parallel_normal(&book, &config).unwrap();
+    assert_eq!(show_net(&net_from_runtime(&rnet, &book.syms)), "#0");
+    assert_eq!(*output.lock().unwrap(), "echo");
+    let mut rnet = run::Net::new(&data);
+    rnet.boot(book.syms.id("stuck").unwrap());
+    let run::RuntimeError::Threads(errs) = rnet.parallel_normal(&book, &config).unwrap_err() else { panic!() };
+    assert!(!errs.is_empty());
+    for err in errs {
+      assert_eq!(err.err.to_string(), "invalid interaction (IO argument never becomes data): @IO.print ~ (a b)");
+    }
+  }
+
+  // Without a handler, IO calls are undefined references.
+  let book = book_to_runtime(&parse_core("@main = r & @IO.print ~ (#0 r)")).unwrap();
+  let data = run::Heap::init(1 << 8);
+  let mut rnet = run::Net::new(&data);
+  rnet.boot(book.syms.id("main").unwrap());
+  assert_snapshot!(rnet.normal(&book).unwrap_err().to_string(), @"invalid interaction (reference has no definition): @IO.print ~ (#0 a)");
+  assert!(validate_book(&parse_core("@main = r & @IO.print ~ (#0 r)")).is_ok());
+}
+
+#[test]
+fn test_literals() {
+  // Literals are desugared as they're parsed, and only shown back as literals when sugared.
+  assert_eq!(show_tree(&do_parse_tree("[]").unwrap()), "(* ($0 $0))");
+  assert_eq!(show_tree(&do_parse_tree("[#1]").unwrap()), "((#1 ((* ($0 $0)) $1)) (* $1))");
+  let code = "([\"hi\\n\\\"/\", [#1, *], [a b]] a)";
+  assert_eq!(show_tree_sugared(&do_parse_tree(code).unwrap(), Encoding::Scott), code);
+  assert_eq!(show_tree_sugared(&do_parse_tree("[ #1 ,#2 ]").unwrap(), Encoding::Scott), "[#1, #2]");
+  assert_eq!(show_tree_sugared(&do_parse_tree("\"\\u{1F600}\\t\"").unwrap(), Encoding::Scott), "\"😀\\t\"");
+  assert_eq!(show_tree_sugared(&do_parse_tree("\"\\u{1F600}\\0\"").unwrap(), Encoding::Scott), "[#128512, #0]");
+  let code = "@main = [ \"a/b\" , // c\n #1 ]\n";
+  let book = cst::do_parse_book(code).unwrap();
+  assert_eq!(cst::show_book(&book), code);
+  assert_eq!(cst::book_to_ast(&book, Encoding::Scott), do_parse_book(code).unwrap());
+  assert_eq!(cst::book_to_ast(&book, Encoding::Church), do_parse_book_with_encoding(code, Encoding::Church).unwrap());
+  assert_snapshot!(format_book(code, &FmtConfig::default()).unwrap(), @r###"
+  // c
+  @main = ["a/b", #1]
+  "###);
+
+  assert_snapshot!(do_parse_book("@main = [#1, #2 #3]").unwrap_err().to_string(), @"expected ',' or ']', found '#' at 1:17");
+  assert_snapshot!(do_parse_book("@main = \"a\\q\"").unwrap_err().to_string(), @"expected an escape sequence, found 'q' at 1:12");
+  assert_snapshot!(do_parse_book("@main = \"a").unwrap_err().to_string(), @"expected '\"', found end of input at 1:11");
+  assert_eq!(cst::do_parse_book("@main = [#1, #2 #3]").unwrap_err(), do_parse_book("@main = [#1, #2 #3]").unwrap_err());
+
+  // Readback is structural, but results that are lists can be shown as literals, and lists of
+  // char codes as strings.
+  assert_snapshot!(show_net(&normal(parse_core("@main = [#1]"), 256).1), @"((#1 ((* (a a)) b)) (* b))");
+  let run = |code: &str| show_net_sugared(&normal(parse_core(code), 256).1, Encoding::Scott);
+  assert_snapshot!(run("@main = r & @id ~ (\"hi\" r)\n@id = (x x)"), @r###""hi""###);
+  assert_snapshot!(run("@main = r & [#1, #2, #3] ~ ((* (t t)) (* r))"), @"[#2, #3]");
+  assert_snapshot!(run("@main = r & [#1] ~ ((* (t t)) (* r))"), @"(* (a a))");
+  assert_snapshot!(run("@main = [[#104], [#1, a], (a *)]"), @r###"["h", [#1, a], (a *)]"###);
+
+  // The Church encoding is a fold.
+  let run = |code: &str| show_net_sugared(&normal(do_parse_book_with_encoding(code, Encoding::Church).unwrap(), 256).1, Encoding::Church);
+  assert_snapshot!(run("@main = r & [#1, #2, #3] ~ (@add (#0 r))\n@add = (<+ b r> (b r))"), @"#6");
+  assert_snapshot!(run("@main = [\"ab\", [#1]]"), @r###"["ab", [#1]]"###);
+  let net = normal(do_parse_book_with_encoding("@main = [#1, #2]", Encoding::Church).unwrap(), 256).0;
+  assert_snapshot!(show_net_sugared(&net_from_runtime(&net, &Symbols::new()), Encoding::Scott), @"({268435455 (#1 (a b)) (#2 (c a))} (c b))");
+}
+
+#[test]
+fn test_trace() {
+  let code = "
+    @add  = (<+ a b> (a b))
+    @sum  = (?<(#1 @sumS) a> a)
+    @sumS = ({2 a b} c) & @add ~ (e (d c)) & @sum ~ (a d) & @sum ~ (b e)
+    @main = a & @sum ~ (#8 a)
+  ";
+  let book = book_to_runtime(&parse_core(code)).unwrap();
+  for threads in [1, 4] {
+    let config = run::RuntimeConfig { threads, ..Default::default() };
+    let data = run::Heap::init(1 << 14);
+    let mut rnet = run::Net::new(&data);
+    rnet.boot(book.syms.id("main").unwrap());
+    rnet.tracer = Some(trace::Tracer::new());
+    rnet.parallel_normal(&book, &config).unwrap();
+    let trace = rnet.tracer.take().unwrap().finish();
+    assert_eq!(trace.tids, threads);
+    assert_eq!(trace.steps[0].rule, trace::Rule::Expand);
+    assert!(trace.steps.iter().any(|step| step.rule == trace::Rule::Mtch));
+    assert_eq!(trace_from_bytes(&trace_to_bytes(&trace, &book), &book).unwrap(), trace);
+
+    // Replays on a single thread, ending in the same state.
+    let data = run::Heap::init(1 << 14);
+    let mut replayed = run::Net::new(&data);
+    replayed.boot(book.syms.id("main").unwrap());
+    trace::replay(&mut replayed, &book, &trace).unwrap();
+    assert_eq!(show_net(&net_from_runtime(&replayed, &book.syms)), "#256");
+    assert_eq!(replayed.rewrites(), rnet.rewrites());
+
+    // Stops at the first step that doesn't match.
+    let mut wrong = trace.clone();
+    wrong.steps[3].b = run::Ptr::new(run::LAM, 7, 99);
+    let data = run::Heap::init(1 << 14);
+    let mut replayed = run::Net::new(&data);
+    replayed.boot(book.syms.id("main").unwrap());
+    let err = trace::replay(&mut replayed, &book, &wrong).unwrap_err();
+    assert_eq!(err.index, 3);
+    assert!(err.to_string().ends_with(": redex not pending"), "{}", err);
+  }
+
+  assert_snapshot!(trace::Rule::of(run::Ptr::big(run::REF, 0), run::Ptr::new(run::DUP, 2, 1)).to_string(), @"call");
+  assert_snapshot!(trace::Rule::of(run::Ptr::new(run::DUP, 2, 1), run::Ptr::new(run::DUP, 3, 2)).to_string(), @"comm");
+  let step = trace::Step { tid: 1, epoch: 2, rule: trace::Rule::Anni, a: run::Ptr::new(run::LAM, 0, 5), b: run::Ptr::new(run::LAM, 0, 6) };
+  assert_snapshot!(step.to_string(), @"anni 000000050000000c 000000060000000c (thread 1, epoch 2)");
+  let mut bytes = trace_to_bytes(&trace::Trace { tids: 1, steps: vec![step] }, &book);
+  let at = bytes.len() - 8 - 24;
+  bytes[at] = 99;
+  assert!(matches!(trace_from_bytes(&bytes, &book), Err(BinaryError::BadChecksum)));
+}
+
+#[test]
+fn test_debug() {
+  let code = "
+    @add  = (<+ a b> (a b))
+    @sum  = (?<(#1 @sumS) a> a)
+    @sumS = ({2 a b} c) & @add ~ (e (d c)) & @sum ~ (a d) & @sum ~ (b e)
+    @main = a & @sum ~ (#1 a)
+  ";
+  let book = book_to_runtime(&parse_core(code)).unwrap();
+  let data = run::Heap::init(1 << 10);
+  let mut rnet = run::Net::new(&data);
+  rnet.boot(book.syms.id("main").unwrap());
+  let mut dbg = Debugger::new(rnet, &book);
+  let mut session = |cmds: &[&str]| cmds.iter().map(|cmd| format!("> {}\n{}", cmd, dbg.exec(cmd))).collect::<Vec<_>>().join("\n");
+  assert_snapshot!(session(&["net", "step 2", "redexes", "break @sumS", "break oper", "continue", "net", "heap 3"]), @r###"
+  > net
+  @main
+  > step 2
+  [1] expand: @main at VR2:0@00000000
+  [2] call: @sum ~ (#1 a)
+  > redexes
+  0: anni LAM:0@00000002 ~ LAM:0@00000001
+  > break @sumS
+  Breakpoint 0: @sumS
+  > break oper
+  Breakpoint 1: OPER
+  > continue
+  [3] anni: (?<(#1 @sumS) a> a) ~ (#1 a)
+  Breakpoint 1: OPER, before step 4
+  > net
+  a
+  & ?<(#1 @sumS) a> ~ #1
+  > heap 3
+  00000003: LAM:0@00000004 VR2:0@00000000
+  "###);
+  assert_snapshot!(session(&["fire 0", "delete 1", "breaks", "continue", "continue", "stats", "net", "step"]), @r###"
+  > fire 0
+  [4] mtch: ?<(#1 @sumS) a> ~ #1
+  > delete 1
+  Deleted breakpoint 1: OPER
+  > breaks
+  0: @sumS
+  > continue
+  [5] anni: (#1 @sumS) ~ (* (#0 a))
+  Breakpoint 0: @sumS, before step 6
+  > continue
+  [22] void: #1 ~ *
+  Normal form reached after 22 steps
+  > stats
+  RWTS: 24 (ANNI 9, COMM 1, ERAS 3, DREF 6, OPER 5) in 22 steps
+  > net
+  #2
+  > step
+  Normal form reached after 22 steps
+  "###);
+  assert_snapshot!(session(&["delete 0", "continue", "step", "fire 0", "break nope", "heap zz", "frobnicate"]), @r###"
+  > delete 0
+  Deleted breakpoint 0: @sumS
+  > continue
+  Normal form reached after 22 steps
+  > step
+  Normal form reached after 22 steps
+  > fire 0
+  No redex with index 0 (see 'redexes')
+  > break nope
+  Invalid breakpoint: nope (expected @name, anni, comm, eras, dref or oper)
+  > heap zz
+  No heap location zz
+  > frobnicate
+  Unknown command: frobnicate (see 'help')
+  "###);
+}
+
+#[test]
+fn test_profile() {
+  let code = "
+    @add  = (<+ a b> (a b))
+    @sum  = (?<(#1 @sumS) a> a)
+    @sumS = ({2 a b} c) & @add ~ (e (d c)) & @sum ~ (a d) & @sum ~ (b e)
+    @main = a & @sum ~ (#4 a)
+  ";
+  let book = book_to_runtime(&parse_core(code)).unwrap();
+  let mut profiles = vec![];
+  for threads in [1, 4] {
+    let config = run::RuntimeConfig { threads, ..Default::default() };
+    let data = run::Heap::init(1 << 12);
+    let mut rnet = run::Net::new(&data);
+    rnet.boot(book.syms.id("main").unwrap());
+    rnet.profiler = Some(profile::Profiler::new(data.len()));
+    rnet.parallel_normal(&book, &config).unwrap();
+    let profile = rnet.profiler.take().unwrap().finish(&book);
+    let attributed = profile.defs().iter().find(|def| def.name == "main").unwrap().total;
+    // Erasures skipped by 'redux' aren't interactions, so they're not attributed.
+    assert!(attributed + profile.unattributed() <= rnet.rewrites());
+    profiles.push((profile.show(), profile.folded()));
+  }
+  // Parallel runs attribute the same rewrites.
+  assert_eq!(profiles[0], profiles[1]);
+  assert_snapshot!(profiles[0].0, @r###"
+  DEF          DREF       NODES          RWTS         TOTAL
+  @main           1           1             1           245
+  @sum           31          93           124           244
+  @sumS          15          90            45           120
+  @add           15          45            75            75
+  (15 rewrites couldn't be attributed to a definition)
+  "###);
+  assert_snapshot!(profiles[0].1, @r###"
+  main 1
+  main;sum 124
+  main;sum;sumS 45
+  main;sum;sumS;add 75
+  "###);
+}
+
+#[test]
+fn test_steal_scheduler() {
+  let code = "
+    @add  = (<+ a b> (a b))
+    @sum  = (?<(#1 @sumS) a> a)
+    @sumS = ({2 a b} c) & @add ~ (e (d c)) & @sum ~ (a d) & @sum ~ (b e)
+    @main = a & @sum ~ (#10 a)
+  ";
+  let book = book_to_runtime(&parse_core(code)).unwrap();
+  let (full, _) = normal(parse_core(code), 1 << 16);
+  for threads in 1 ..= 5 {
+    let config = run::RuntimeConfig { threads, scheduler: run::Scheduler::Steal, ..Default::default() };
+    let data = run::Heap::init(1 << 16);
+    let mut rnet = run::Net::new(&data);
+    rnet.boot(book.syms.id("main").unwrap());
+    rnet.parallel_normal(&book, &config).unwrap();
+    assert_eq!(show_net(&net_from_runtime(&rnet, &book.syms)), "#1024");
+    assert_eq!(rnet.rewrites(), full.rewrites());
+
+    // Stops once out of fuel, and resumes on either scheduler.
+    let data = run::Heap::init(1 << 16);
+    let mut rnet = run::Net::new(&data);
+    rnet.boot(book.syms.id("main").unwrap());
+    assert!(!rnet.parallel_normal_with_fuel(&book, &config, 2000).unwrap());
+    assert!(rnet.rewrites() >= 2000 && rnet.rdex.len() > 0);
+    let config = run::RuntimeConfig { scheduler: run::Scheduler::Split, ..config };
+    assert!(rnet.parallel_normal_with_fuel(&book, &config, usize::MAX).unwrap());
+    assert_eq!(show_net(&net_from_runtime(&rnet, &book.syms)), "#1024");
+    assert_eq!(rnet.rewrites(), full.rewrites());
+  }
+
+  let book = book_to_runtime(&parse_core("@main = a & ?<(#1 *) a> ~ ?<(b b) *>")).unwrap();
+  let config = run::RuntimeConfig { threads: 4, scheduler: run::Scheduler::Steal, ..Default::default() };
+  let data = run::Heap::init(1 << 8);
+  let mut rnet = run::Net::new(&data);
+  rnet.boot(book.syms.id("main").unwrap());
+  // The failed pair may be reduced by any thread.
+  let run::RuntimeError::Threads(errs) = rnet.parallel_normal(&book, &config).unwrap_err() else { panic!() };
+  assert_eq!(errs.len(), 1);
+  assert_snapshot!(errs[0].err.to_string(), @"invalid interaction (no rule for this pair): ?<(#1 *) a> ~ ?<(a a) *>");
+}
+
+#[test]
+fn test_tail_calls() {
+  let book = book_to_runtime(&parse_core(
+    "
+    @sum  = (?<(@sumZ @sumS) a> a)
+    @sumZ = (a a)
+    @sumS = ({2 <+ a b> c} (a d)) & @sum ~ (c (b d))
+    @half = (?<(@sumZ @halfS) a> a)
+    @halfS = ({2 <+ a b> c} (a d)) & @sum ~ (c (b d))
+    @main = a & @sum ~ (#10 (#0 a))
+  ",
+  )).unwrap();
+  // The body of a compiled definition.
+  let body = |code: &str, fun: &str| {
+    let start = code.find(&format!("pub fn {}(", fun)).unwrap();
+    return code[start ..].split("\n  }\n").next().unwrap().to_string();
+  };
+  let code = jit::compile_book(&book, true);
+  let sum = body(&code, "F_sum");
+  assert!(sum.contains("'tail: loop {") && sum.contains("// inline @sumS") && sum.contains("// tail call @sum"));
+  assert!(sum.contains("if tails < TAIL_LIMIT && self.rdex.len() - base < TAIL_LIMIT {"));
+  // Only successor branches that call back into the definition are inlined.
+  assert!(!body(&code, "F_half").contains("'tail"));
+  assert!(!body(&code, "F_sumS").contains("'tail"));
+  assert!(!jit::compile_book(&book, false).contains("'tail"));
+}
+
+// A fresh directory for the files of a test that runs 'hvmc'.
+fn test_dir(name: &str) -> std::path::PathBuf {
+  let dir = std::path::Path::new(env!("CARGO_TARGET_TMPDIR")).join(name);
+  let _ = std::fs::remove_dir_all(&dir);
+  std::fs::create_dir_all(&dir).unwrap();
+  return dir;
+}
+
+#[test]
+// Recurses deeper than 'TAIL_LIMIT', so that the compiled loop yields, and checks that it reduces
+// as the interpreter does, on one thread and on several.
+fn test_tail_calls_run() {
+  let code = "
+    @sum  = (?<(@sumZ @sumS) a> a)
+    @sumZ = (a a)
+    @sumS = ({2 <+ a b> c} (a d)) & @sum ~ (c (b d))
+    @main = a & @sum ~ (#1000 (#0 a))
+  ";
+  let dir = test_dir("tail_calls");
+  std::fs::write(dir.join("sum.hvmc"), code).unwrap();
+  let hvmc = env!("CARGO_BIN_EXE_hvmc");
+  let status = std::process::Command::new(hvmc).current_dir(&dir).args(["compile", "sum.hvmc", "--tco"]).status().unwrap();
+  assert!(status.success());
+  let (rnet, net) = normal(parse_core(code), 1 << 12);
+  assert_snapshot!(show_net(&net), @"#499500");
+  for opts in [&["-1"][..], &["-t", "4"]] {
+    let out = std::process::Command::new(dir.join("sum")).arg("run").arg("sum.hvmc").args(opts).output().unwrap();
+    let out = String::from_utf8(out.stdout).unwrap();
+    let mut lines = out.lines();
+    assert_eq!(lines.next(), Some(show_net(&net).as_str()));
+    assert_eq!(lines.next(), Some(format!("RWTS   : {}", rnet.rewrites()).as_str()));
+  }
+}
+
+#[test]
+fn test_compile_lib() {
+  let book = book_to_runtime(&parse_core(
+    "
+    @sum  = (?<(@sumZ @sumS) a> a)
+    @sumZ = (a a)
+    @sumS = ({2 <+ a b> c} (a d)) & @sum ~ (c (b d))
+    @main = a & @sum ~ (#10 (#0 a))
+  ",
+  )).unwrap();
+  // Definitions are methods of a trait, for the library to implement them for 'Net'.
+  let code = jit::compile_lib(&book, false);
+  assert!(code.contains("pub trait Compiled {\n") && code.contains("impl<'a> Compiled for Net<'a> {\n"));
+  assert!(code.contains("  fn F_sum(&mut self, ptr: Ptr, trg: Trg) -> Result<bool, RuntimeError>;\n"));
+  assert!(code.contains("  fn F_sum(&mut self, ptr: Ptr, trg: Trg) -> Result<bool, RuntimeError> {\n"));
+  assert!(!code.contains("pub fn F_"));
+  assert!(code.contains("    F_sum => { return net.F_sum(ptr, Trg::Ptr(x)); }\n"));
+  for entry in ["hvmc_book", "hvmc_call_native"] {
+    assert!(code.contains(&format!("#[no_mangle]\n#[allow(improper_ctypes_definitions)]\npub extern \"C\" fn {}()", entry)));
+  }
+  assert!(jit::compile_lib(&book, true).contains("'tail: loop {"));
+
+  // A book dispatches to a library's 'call_native' before expanding definitions.
+  fn call_native(net: &mut run::Net, book: &run::Book, ptr: run::Ptr, x: run::Ptr) -> Result<bool, run::RuntimeError> {
+    if book.syms.id("two") != Some(ptr.val()) {
+      return Ok(false);
+    }
+    net.link(run::Ptr::big(run::NUM, 2), x);
+    Ok(true)
+  }
+  let run = |call_native: Option<run::CallNative>| {
+    let mut book = book_to_runtime(&parse_core("@two = #3\n@id = (a a)\n@main = r & @id ~ (@two r)")).unwrap();
+    book.call_native = call_native;
+    let data = run::Heap::init(1 << 8);
+    let mut rnet = run::Net::new(&data);
+    rnet.boot(book.syms.id("main").unwrap());
+    rnet.normal(&book).unwrap();
+    show_net(&net_from_runtime(&rnet, &book.syms))
+  };
+  assert_eq!(run(None), "#3");
+  assert_eq!(run(Some(call_native)), "#2");
+}
+
+#[test]
+// Generates a library crate with 'hvmc compile --lib', and builds it against this source tree.
+fn test_compile_lib_build() {
+  let dir = test_dir("compile_lib");
+  std::fs::write(dir.join("sum.hvmc"), "@sum = (?<(#0 @sumS) a> a)\n@sumS = (a b) & @sum ~ (a b)\n@main = a & @sum ~ (#3 a)").unwrap();
+  let hvmc = env!("CARGO_BIN_EXE_hvmc");
+  let status = std::process::Command::new(hvmc).current_dir(&dir).args(["compile", "sum.hvmc", "--lib", "--tco"]).status().unwrap();
+  assert!(status.success());
+  let out = std::process::Command::new("cargo").current_dir(dir.join("sum")).arg("build").output().unwrap();
+  assert!(out.status.success(), "{}", String::from_utf8_lossy(&out.stderr));
+
+  // The directory is named after the file without its extension, so a file needs one.
+  std::fs::write(dir.join("main"), "@main = *").unwrap();
+  let out = std::process::Command::new(hvmc).current_dir(&dir).args(["compile", "main", "--lib"]).output().unwrap();
+  assert!(!out.status.success());
+  assert_snapshot!(String::from_utf8_lossy(&out.stderr), @"can't name a library directory after 'main', which has no extension");
+}